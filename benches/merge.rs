@@ -0,0 +1,111 @@
+//! Benchmarks of the way merging pipeline (Relation::flatten_ways)
+//!
+//! `cargo bench --bench merge`
+//! - test.pbf: the relations of the test file
+//! - shuffled: a synthetic route with its ways shuffled and some of them reversed
+//! - shuffled_gaps: same, with a small gap every 10 ways that has to be joined with the tolerance
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::collections::HashMap;
+
+use osmptparser::{MissingMembers, Node, Parser, Relation, Way};
+
+/// deterministic xorshift, to get the same fixtures on every run
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+fn node(id: u64, lon: f64, lat: f64) -> Node {
+    Node {
+        id,
+        tags: HashMap::new(),
+        lon,
+        lat,
+    }
+}
+
+/// route of `ways` ways of 5 nodes, about 10m between nodes, going east
+fn synthetic_route(ways: usize, gaps: bool) -> Relation {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    let step = 0.0001;
+    let mut next_id = 1;
+    let mut lon = 0_f64;
+    let mut list = Vec::with_capacity(ways);
+    for i in 0..ways {
+        let mut nodes = Vec::with_capacity(5);
+        if gaps && i % 10 == 9 {
+            // start 15m away from the end of the previous way
+            next_id += 1;
+            lon += step / 2_f64;
+        } else if i > 0 {
+            // share the end node of the previous way
+            next_id -= 1;
+            lon -= step;
+        }
+        for _ in 0..5 {
+            nodes.push(node(next_id, lon, 0.001 * (lon * 100_f64).sin()));
+            next_id += 1;
+            lon += step;
+        }
+        if rng.next() % 3 == 0 {
+            nodes.reverse();
+        }
+        list.push(Way {
+            id: i as u64 + 1,
+            tags: HashMap::new(),
+            info: HashMap::new(),
+            nodes,
+        });
+    }
+    // keep the first way (it sets the direction), shuffle the rest
+    for i in (2..list.len()).rev() {
+        let j = 1 + (rng.next() as usize) % i;
+        list.swap(i, j);
+    }
+    Relation {
+        id: 1,
+        tags: HashMap::new(),
+        info: HashMap::new(),
+        ways: list,
+        stops: Vec::new(),
+        members: Vec::new(),
+        missing: MissingMembers::default(),
+    }
+}
+
+fn test_pbf(c: &mut Criterion) {
+    let parser = Parser::new_ptv2("tests/test.pbf", 1);
+    let relations = parser.par_map(&|r| r);
+    c.bench_function("flatten_ways/test.pbf", |b| {
+        b.iter(|| {
+            for r in &relations {
+                r.flatten_ways(150_f64, false).unwrap();
+            }
+        })
+    });
+}
+
+fn synthetic(c: &mut Criterion) {
+    let mut group = c.benchmark_group("flatten_ways");
+    group.sample_size(10);
+    for ways in [1000, 5000] {
+        for gaps in [false, true] {
+            let relation = synthetic_route(ways, gaps);
+            let name = if gaps { "shuffled_gaps" } else { "shuffled" };
+            group.bench_with_input(BenchmarkId::new(name, ways), &relation, |b, r| {
+                b.iter(|| r.flatten_ways(150_f64, false).unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, test_pbf, synthetic);
+criterion_main!(benches);
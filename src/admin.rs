@@ -0,0 +1,389 @@
+//! Administrative areas (`Parser::new_aa`): join of public transports and their stops with the areas that contain them,
+//! and hierarchy of the areas (country → province → department → municipality)
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde_json::{json, Value};
+
+use crate::geo::{bbox, locate};
+use crate::index::SpatialLayer;
+use crate::metrics::AreaMetrics;
+use crate::parser::relation::{Area, LonLat, PublicTransport};
+
+/// points closer than this to a boundary (meters) are not used to find gaps and overlaps,
+/// neighbour boundaries are not always drawn with the same nodes
+const BOUNDARY_TOLERANCE: f64 = 10_f64;
+/// points per side of the grid sampled inside a parent area to find gaps between its children
+const GAP_SAMPLES: usize = 32;
+/// maximum vertices of an area checked against its siblings to find overlaps
+const OVERLAP_SAMPLES: usize = 200;
+
+/// Administrative area containing a route or a stop
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminArea {
+    /// osm id
+    pub id: u64,
+    /// osm object type (w=way, r=relation)
+    pub id_type: char,
+    pub admin_level: u8,
+    pub name: String,
+}
+
+/// Administrative areas of a public transport and of each of its stops, sorted by admin_level
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AdminAssignment {
+    /// areas containing a point of the geometry or a stop
+    pub route: Vec<AdminArea>,
+    /// areas containing each stop, in the same order as the stops
+    pub stops: Vec<Vec<AdminArea>>,
+}
+
+/// {"<admin_level>": [{"id", "id_type", "name"}]}
+pub(crate) fn admin_json(areas: &[AdminArea]) -> Value {
+    let mut levels: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+    for area in areas {
+        levels
+            .entry(area.admin_level.to_string())
+            .or_default()
+            .push(json!({"id": area.id, "id_type": area.id_type, "name": area.name}));
+    }
+    json!(levels)
+}
+
+/// Administrative areas indexed to find the ones containing a point
+pub struct AdminAreas {
+    layer: SpatialLayer<Area>,
+}
+
+impl AdminAreas {
+    /// areas without geometry or without a numeric admin_level tag are ignored
+    pub fn new(areas: Vec<Area>) -> Self {
+        let areas: Vec<Area> = areas
+            .into_iter()
+            .filter(|area| !area.geometry.is_empty() && admin_level(area).is_some())
+            .collect();
+        AdminAreas {
+            layer: SpatialLayer::new(areas),
+        }
+    }
+
+    pub fn areas(&self) -> &[Area] {
+        self.layer.items()
+    }
+
+    /// areas containing `point`, sorted by admin_level
+    pub fn at(&self, point: LonLat) -> Vec<AdminArea> {
+        let mut found: Vec<AdminArea> = self
+            .layer
+            .containing(point)
+            .into_iter()
+            .map(to_admin_area)
+            .collect();
+        sort(&mut found);
+        found
+    }
+
+    /// areas of `pt` and of each of its stops
+    pub fn assignment(&self, pt: &PublicTransport) -> AdminAssignment {
+        let stops: Vec<Vec<AdminArea>> = pt.stops.iter().map(|s| self.at((s.lon, s.lat))).collect();
+        let mut route: Vec<AdminArea> = Vec::new();
+        let mut add = |areas: Vec<AdminArea>| {
+            for area in areas {
+                if !route
+                    .iter()
+                    .any(|a| a.id == area.id && a.id_type == area.id_type)
+                {
+                    route.push(area);
+                }
+            }
+        };
+        for point in pt.geometry.iter().flatten() {
+            add(self.at(*point));
+        }
+        for areas in &stops {
+            add(areas.clone());
+        }
+        sort(&mut route);
+        AdminAssignment { route, stops }
+    }
+
+    /// sets the `admin` of `pt`
+    pub fn assign(&self, pt: &mut PublicTransport) {
+        pt.admin = Some(self.assignment(pt));
+    }
+}
+
+fn admin_level(area: &Area) -> Option<u8> {
+    area.tags.get("admin_level")?.parse().ok()
+}
+
+fn to_admin_area(area: &Area) -> AdminArea {
+    AdminArea {
+        id: area.id,
+        id_type: area.id_type,
+        admin_level: admin_level(area).unwrap(),
+        name: area.tags.get("name").cloned().unwrap_or_default(),
+    }
+}
+
+fn sort(areas: &mut [AdminArea]) {
+    areas.sort_by(|a, b| (a.admin_level, &a.name, a.id).cmp(&(b.admin_level, &b.name, b.id)));
+}
+
+/// Problem found between the areas of an AdminHierarchy
+#[derive(Clone, Debug, PartialEq)]
+pub enum HierarchyIssue {
+    /// part of an area with children not covered by any of them
+    /// `uncovered` is the estimated fraction of the area, `location` a point of the gap
+    Gap {
+        area_id: u64,
+        uncovered: f64,
+        location: LonLat,
+    },
+    /// two children of the same area and admin_level overlap, `location` is a point inside both
+    Overlap {
+        area_ids: (u64, u64),
+        location: LonLat,
+    },
+    /// area without surface, so without a point to find its parent, it is a root of the hierarchy
+    ZeroArea { area_id: u64 },
+}
+
+impl HierarchyIssue {
+    pub fn to_json(&self) -> Value {
+        match self {
+            HierarchyIssue::Gap {
+                area_id,
+                uncovered,
+                location,
+            } => json!({
+                "type": "gap",
+                "area_id": area_id,
+                "uncovered": uncovered,
+                "location": location,
+            }),
+            HierarchyIssue::Overlap { area_ids, location } => json!({
+                "type": "overlap",
+                "area_ids": [area_ids.0, area_ids.1],
+                "location": location,
+            }),
+            HierarchyIssue::ZeroArea { area_id } => json!({
+                "type": "zero_area",
+                "area_id": area_id,
+            }),
+        }
+    }
+}
+
+/// Hierarchy of administrative areas: the parent of an area is the one with the highest admin_level lower than its own
+/// that contains it (its pole of inaccessibility), the smallest one if there are several
+pub struct AdminHierarchy {
+    admin_areas: AdminAreas,
+    parents: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+    /// pole of inaccessibility of each area, computed once for the parents and the overlaps
+    poles: Vec<Option<LonLat>>,
+}
+
+impl AdminHierarchy {
+    /// areas without geometry or without a numeric admin_level tag are ignored
+    pub fn new(areas: Vec<Area>) -> Self {
+        let admin_areas = AdminAreas::new(areas);
+        let areas = admin_areas.areas();
+        let positions: HashMap<(char, u64), usize> = areas
+            .iter()
+            .enumerate()
+            .map(|(i, area)| ((area.id_type, area.id), i))
+            .collect();
+        let metrics: Vec<AreaMetrics> = areas.iter().map(Area::metrics).collect();
+        let mut parents = vec![None; areas.len()];
+        let mut children = vec![Vec::new(); areas.len()];
+        for (i, area) in areas.iter().enumerate() {
+            let level = admin_level(area).unwrap();
+            // reported by issues()
+            let pole = match metrics[i].pole_of_inaccessibility {
+                Some(pole) => pole,
+                None => continue,
+            };
+            let parent = admin_areas
+                .layer
+                .containing(pole)
+                .into_iter()
+                .map(|candidate| positions[&(candidate.id_type, candidate.id)])
+                .filter(|j| admin_level(&areas[*j]).unwrap() < level)
+                // highest admin_level first, then smallest area
+                .min_by(|a, b| {
+                    admin_level(&areas[*b])
+                        .cmp(&admin_level(&areas[*a]))
+                        .then(metrics[*a].area.total_cmp(&metrics[*b].area))
+                });
+            if let Some(parent) = parent {
+                parents[i] = Some(parent);
+                children[parent].push(i);
+            }
+        }
+        let poles = metrics.iter().map(|m| m.pole_of_inaccessibility).collect();
+        AdminHierarchy {
+            admin_areas,
+            parents,
+            children,
+            poles,
+        }
+    }
+
+    pub fn areas(&self) -> &[Area] {
+        self.admin_areas.areas()
+    }
+
+    /// position in areas() of the parent of the area at position `index`
+    pub fn parent(&self, index: usize) -> Option<usize> {
+        self.parents[index]
+    }
+
+    /// positions in areas() of the children of the area at position `index`
+    pub fn children(&self, index: usize) -> &[usize] {
+        &self.children[index]
+    }
+
+    /// positions in areas() of the areas without parent
+    pub fn roots(&self) -> Vec<usize> {
+        (0..self.parents.len())
+            .filter(|i| self.parents[*i].is_none())
+            .collect()
+    }
+
+    /// the areas with their parent_id set
+    pub fn into_areas(self) -> Vec<Area> {
+        let parent_ids: Vec<Option<u64>> = self
+            .parents
+            .iter()
+            .map(|p| p.map(|p| self.areas()[p].id))
+            .collect();
+        let mut areas = self.admin_areas.layer.into_items();
+        for (area, parent_id) in areas.iter_mut().zip(parent_ids) {
+            area.parent_id = parent_id;
+        }
+        areas
+    }
+
+    /// gaps between the children of each area, overlaps between children of the same admin_level
+    /// and areas without surface
+    pub fn issues(&self) -> Vec<HierarchyIssue> {
+        let areas = self.areas();
+        let mut issues = Vec::new();
+        for (i, children) in self.children.iter().enumerate() {
+            if children.is_empty() {
+                continue;
+            }
+            if let Some(gap) = self.gap(i) {
+                issues.push(gap);
+            }
+            for (n, a) in children.iter().enumerate() {
+                for b in &children[n + 1..] {
+                    let (pole_a, pole_b) = (self.poles[*a], self.poles[*b]);
+                    let (a, b) = (&areas[*a], &areas[*b]);
+                    if admin_level(a) != admin_level(b) {
+                        continue;
+                    }
+                    if let Some(location) = overlap(a, pole_a, b).or_else(|| overlap(b, pole_b, a))
+                    {
+                        issues.push(HierarchyIssue::Overlap {
+                            area_ids: (a.id, b.id),
+                            location,
+                        });
+                    }
+                }
+            }
+        }
+        issues.extend(
+            areas
+                .iter()
+                .zip(&self.poles)
+                .filter(|(_, pole)| pole.is_none())
+                .map(|(area, _)| HierarchyIssue::ZeroArea { area_id: area.id }),
+        );
+        issues
+    }
+
+    fn gap(&self, index: usize) -> Option<HierarchyIssue> {
+        let area = &self.areas()[index];
+        let (minx, miny, maxx, maxy) = bbox(&area.geometry)?;
+        let children: Vec<&Area> = self.children[index]
+            .iter()
+            .map(|c| &self.areas()[*c])
+            .collect();
+        let (mut inside, mut uncovered) = (0_usize, 0_usize);
+        let mut location = None;
+        for x in 0..GAP_SAMPLES {
+            for y in 0..GAP_SAMPLES {
+                let point = (
+                    minx + (maxx - minx) * (x as f64 + 0.5) / GAP_SAMPLES as f64,
+                    miny + (maxy - miny) * (y as f64 + 0.5) / GAP_SAMPLES as f64,
+                );
+                if !inside_by(area, point, BOUNDARY_TOLERANCE) {
+                    continue;
+                }
+                inside += 1;
+                if children.iter().all(|child| {
+                    !child.contains(point) && boundary_distance(child, point) > BOUNDARY_TOLERANCE
+                }) {
+                    uncovered += 1;
+                    location.get_or_insert(point);
+                }
+            }
+        }
+        location.map(|location| HierarchyIssue::Gap {
+            area_id: area.id,
+            uncovered: uncovered as f64 / inside as f64,
+            location,
+        })
+    }
+
+    /// nested json tree of the areas, from the roots to the leaves
+    pub fn to_json(&self) -> Value {
+        json!(self
+            .roots()
+            .into_iter()
+            .map(|i| self.node_json(i))
+            .collect::<Vec<_>>())
+    }
+
+    fn node_json(&self, index: usize) -> Value {
+        let area = &self.areas()[index];
+        json!({
+            "id": area.id,
+            "id_type": area.id_type,
+            "name": area.tags.get("name"),
+            "admin_level": admin_level(area),
+            "children": self.children[index].iter().map(|c| self.node_json(*c)).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// distance in meters from `point` to the closest ring of `area`
+fn boundary_distance(area: &Area, point: LonLat) -> f64 {
+    area.geometry
+        .iter()
+        .filter_map(|ring| locate(ring, point))
+        .map(|(_, offset)| offset)
+        .fold(f64::INFINITY, f64::min)
+}
+
+/// true if `point` is inside `area` and farther than `tolerance` meters from its boundary
+fn inside_by(area: &Area, point: LonLat, tolerance: f64) -> bool {
+    area.contains(point) && boundary_distance(area, point) > tolerance
+}
+
+/// a vertex (or `pole`, the pole of inaccessibility of `a`) of `a` well inside `b`
+fn overlap(a: &Area, pole: Option<LonLat>, b: &Area) -> Option<LonLat> {
+    let (ax0, ay0, ax1, ay1) = bbox(&a.geometry)?;
+    let (bx0, by0, bx1, by1) = bbox(&b.geometry)?;
+    if ax0 > bx1 || bx0 > ax1 || ay0 > by1 || by0 > ay1 {
+        return None;
+    }
+    let vertices: Vec<LonLat> = a.geometry.iter().flatten().cloned().collect();
+    let step = vertices.len().div_ceil(OVERLAP_SAMPLES).max(1);
+    pole.into_iter()
+        .chain(vertices.into_iter().step_by(step))
+        .find(|point| inside_by(b, *point, BOUNDARY_TOLERANCE))
+}
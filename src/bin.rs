@@ -1,14 +1,31 @@
 extern crate num_cpus;
-use osmptparser::Parser;
+use osmptparser::admin::{AdminAreas, AdminHierarchy};
+use osmptparser::network::StopNetwork;
+use osmptparser::proj::Crs;
+use osmptparser::simplify::Algorithm;
+use osmptparser::stops::StopRegistry;
+use osmptparser::tiles::{TileOptions, TileSet};
+use osmptparser::timing::SpeedProfiles;
+use osmptparser::validator::{self, ReportFormat, ValidateOptions};
+use osmptparser::writer::{self, Format, WriteError, WriterOptions};
+use osmptparser::{
+    Area, GapFill, ParseStatus, ParseStatusKind, Parser, PublicTransport, RoadGraph, StopArea,
+};
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+use structopt::clap::{AppSettings, Error, ErrorKind};
 use structopt::StructOpt;
 
 /// Openstreetmap areas and public transport (ptv2) parser
 #[derive(StructOpt, Debug)]
+#[structopt(setting = AppSettings::SubcommandsNegateReqs)]
 struct Cli {
     /// Path to the input file to read
     /// must be in OSM PBF format
+    /// (required unless using a subcommand)
     #[structopt(parse(from_os_str))]
-    filename: std::path::PathBuf,
+    filename: Option<std::path::PathBuf>,
 
     /// Filter to use
     /// (mutually exclusive with filter-ptv2)
@@ -16,8 +33,8 @@ struct Cli {
     /// - "natural=beach": only areas wich are beaches
     /// - "name&natural=beach": areas wich are beaches and have a name
     /// - "name&admin_level=1,2,3&boundary=administrative": administrative areas with name and level values of 1 or 2 or 3
-    #[structopt(short = "f", long = "filter")]
-    filter: String,
+    #[structopt(short = "f", long = "filter", required_unless = "filter-ptv2")]
+    filter: Option<String>,
 
     /// get ptv2
     /// (mutually exclusive with filter)
@@ -37,48 +54,681 @@ struct Cli {
     /// Defaults to 150m
     #[structopt(short = "g", long = "gap", default_value = "150.0")]
     gap: f64,
+
+    /// Output format
+    /// Possible values:
+    /// - "geojson": json list with one geojson per area / ptv2
+    /// - "gpkg": GeoPackage with routes, stops and areas layers (needs --output)
+    /// - "fgb": FlatGeobuf with spatial index, ptv2 lines and area polygons (needs --output)
+    /// - "pgcopy": sql with CREATE TABLE and COPY blocks, to pipe into psql
+    /// - "gpx": ptv2 tracks with their stops as waypoints, areas as tracks
+    /// - "kml": ptv2 lines styled with their colour tag and their stops, area polygons
+    /// - "gtfs": GTFS feed with a trip per ptv2 and stop times estimated from the way speeds (needs --output directory)
+    #[structopt(long = "format", default_value = "geojson")]
+    format: Format,
+
+    /// Path to the output file
+    /// Defaults to stdout for text formats
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    output: Option<std::path::PathBuf>,
+
+    /// What to do with incomplete relations, that have members missing from the pbf file
+    /// (e.g. routes cut at the border of the extract)
+    /// Possible values:
+    /// - "include": output them as any other relation
+    /// - "exclude": skip them
+    /// - "only": output only the incomplete relations
+    #[structopt(long = "incomplete", default_value = "include")]
+    incomplete: Incomplete,
+
+    /// Write one file per ptv2 / area instead of a single combined file
+    /// --output is then the directory where the files are created (geojson, gpx and kml formats only)
+    #[structopt(long = "split", requires = "output")]
+    split: bool,
+
+    /// Add the metrics of each ptv2 / area to the geojson properties
+    /// ptv2: length, bbox, stop count, stop spacing and sinuosity
+    /// areas: area, perimeter, bbox, centroid and pole of inaccessibility
+    /// Only with the geojson format
+    #[structopt(long = "metrics")]
+    metrics: bool,
+
+    /// Add the normalized route information of each ptv2 to the geojson properties:
+    /// ref, name, colour (#rrggbb), route_type, network, operator, from and to
+    /// Only with the geojson format
+    #[structopt(long = "flat-properties", requires = "filter-ptv2")]
+    flat_properties: bool,
+
+    /// Languages of the name of the flat properties in order of preference, comma separated (e.g. "es,en"),
+    /// the first one with a name:<language> tag is used, else the name tag
+    #[structopt(long = "languages", use_delimiter = true, requires = "flat-properties")]
+    languages: Vec<String>,
+
+    /// Coordinate reference system of the output geometries, declared in the formats that support it
+    /// gpx and kml are always in WGS84
+    /// Possible values:
+    /// - "4326": WGS84 longitude / latitude
+    /// - "3857": Web Mercator
+    /// - "utm": the UTM zone of the center of the input data
+    /// - "utm:<zone><n|s>" or "326zz" / "327zz": a UTM zone, for example "utm:17s" or "32717"
+    #[structopt(long = "crs", default_value = "4326")]
+    crs: OutputCrs,
+
+    /// Json file with the speed profiles by route type used to estimate the gtfs stop times, for example
+    /// {"bus": {"dwell_time": 30, "maxspeed_factor": 0.6, "highway": {"primary": 40}}, "ferry": {"default_speed": 15}}
+    /// Fields: default_speed, max_speed and highway speeds (km/h), maxspeed_factor, dwell_time (seconds)
+    /// The fields not set keep the default profile of the route type
+    #[structopt(long = "speed-profiles", parse(from_os_str))]
+    speed_profiles: Option<PathBuf>,
+
+    /// Timezone of the gtfs agencies (tz database name, for example "America/Guayaquil")
+    #[structopt(long = "timezone")]
+    timezone: Option<String>,
+
+    /// Add the administrative areas (boundary=administrative with admin_level) that contain
+    /// each ptv2 and each of its stops to the geojson properties, by admin_level
+    /// The areas are parsed from the same input file, only available with the geojson format
+    #[structopt(long = "admin-areas", requires = "filter-ptv2")]
+    admin_areas: bool,
+
+    /// Build the hierarchy of the administrative areas that match --filter (by containment and admin_level)
+    /// Possible values:
+    /// - "tree": write a nested json tree of the areas and the gaps / overlaps found between siblings, instead of the areas
+    /// - "parent-id": write the areas with the osm id of their parent in a parent_id property / column
+    #[structopt(long = "admin-hierarchy", conflicts_with = "filter-ptv2")]
+    admin_hierarchy: Option<Hierarchy>,
+
+    /// Write the stops of all the ptv2 instead of the ptv2, once per node, as a geojson FeatureCollection of points
+    /// with the ids and refs of the routes that serve each stop and its public_transport=stop_area
+    /// The stop areas are parsed from the same input file
+    #[structopt(long = "stops", requires = "filter-ptv2", conflicts_with = "split")]
+    stops: bool,
+
+    /// Write the network of the ptv2 instead of the ptv2: a directed graph with the stops as nodes
+    /// and an edge per pair of consecutive stops of each route, with its route id, ref, length and geometry
+    /// The stop areas are parsed from the same input file
+    /// Possible values:
+    /// - "graphml": GraphML, edge geometries as WKT
+    /// - "json": stops and adjacency lists
+    #[structopt(
+        long = "network",
+        requires = "filter-ptv2",
+        conflicts_with_all = &["split", "stops"]
+    )]
+    network: Option<NetworkFormat>,
+
+    /// Write the ptv2 cut at each stop instead of the ptv2, as a geojson FeatureCollection with a LineString
+    /// per pair of consecutive stops, with the route id, ref, stop ids and names and the length
+    #[structopt(
+        long = "segments",
+        requires = "filter-ptv2",
+        conflicts_with_all = &["split", "stops", "network"]
+    )]
+    segments: bool,
+
+    /// Keep the best partial geometry (multilinestring) of the ptv2 that can not be merged into one line (status 501)
+    /// instead of an empty one, the gap count, length and locations are in the parse status
+    #[structopt(long = "partial")]
+    partial: bool,
+
+    /// Simplify the ptv2 lines / area polygons with this tolerance
+    /// ptv2 keep the vertices of their stops, areas keep valid rings
+    /// Unit: meters
+    #[structopt(long = "simplify")]
+    simplify: Option<f64>,
+
+    /// Simplification algorithm, used with --simplify
+    /// Possible values:
+    /// - "dp": Douglas-Peucker, tolerance is the maximum distance to the simplified line
+    /// - "vw": Visvalingam-Whyatt, tolerance² is the minimum area of the triangle formed by a vertex and its neighbours
+    #[structopt(long = "simplify-algorithm", default_value = "dp")]
+    simplify_algorithm: Algorithm,
+
+    /// Fill the gaps of the ptv2 with the shortest path over the highway / railway ways of the pbf file
+    /// instead of joining them in a straight line, the filled ways are in the parse status
+    #[structopt(long = "fill-gaps")]
+    fill_gaps: bool,
+
+    /// Maximum extra length of the path that fills a gap over the straight distance of the gap (with --fill-gaps)
+    /// Unit: meters
+    #[structopt(long = "max-detour", default_value = "1000.0")]
+    max_detour: f64,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
 }
 
-fn main() {
-    let args = Cli::from_args();
-    let cpus = if args.cpus == 0 {
+#[derive(Debug, Clone, Copy)]
+enum OutputCrs {
+    Fixed(Crs),
+    Utm,
+}
+
+impl FromStr for OutputCrs {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "utm" => Ok(OutputCrs::Utm),
+            _ => Crs::from_str(s).map(OutputCrs::Fixed),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Hierarchy {
+    Tree,
+    ParentId,
+}
+
+impl FromStr for Hierarchy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tree" => Ok(Hierarchy::Tree),
+            "parent-id" => Ok(Hierarchy::ParentId),
+            _ => Err(format!("unknown admin hierarchy option '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NetworkFormat {
+    GraphMl,
+    Json,
+}
+
+impl FromStr for NetworkFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "graphml" => Ok(NetworkFormat::GraphMl),
+            "json" => Ok(NetworkFormat::Json),
+            _ => Err(format!("unknown network format '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Incomplete {
+    Include,
+    Exclude,
+    Only,
+}
+
+impl FromStr for Incomplete {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "include" => Ok(Incomplete::Include),
+            "exclude" => Ok(Incomplete::Exclude),
+            "only" => Ok(Incomplete::Only),
+            _ => Err(format!("unknown incomplete option '{}'", s)),
+        }
+    }
+}
+
+impl Incomplete {
+    fn keep(&self, parse_status: &ParseStatus) -> bool {
+        match self {
+            Incomplete::Include => true,
+            Incomplete::Exclude => !parse_status.is_incomplete(),
+            Incomplete::Only => parse_status.is_incomplete(),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Generates Mapbox Vector Tiles with the ptv2 routes and stops
+    #[structopt(name = "tiles")]
+    Tiles(TilesCli),
+    /// Checks the PTv2 rules of every route and writes a report of what has to be fixed
+    #[structopt(name = "validate")]
+    Validate(ValidateCli),
+}
+
+#[derive(StructOpt, Debug)]
+struct TilesCli {
+    /// Path to the input file to read
+    /// must be in OSM PBF format
+    #[structopt(parse(from_os_str))]
+    filename: std::path::PathBuf,
+
+    /// Path to the output
+    /// if it ends with ".mbtiles" an MBTiles file is written,
+    /// otherwise a directory with z/x/y.pbf tiles
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    output: std::path::PathBuf,
+
+    /// Minimum zoom level to generate
+    #[structopt(long = "min-zoom", default_value = "0")]
+    min_zoom: u8,
+
+    /// Maximum zoom level to generate
+    #[structopt(long = "max-zoom", default_value = "14")]
+    max_zoom: u8,
+
+    /// Line simplification tolerance on each zoom level
+    /// Unit: tile coordinates (tiles are 4096 units wide)
+    #[structopt(long = "simplify", default_value = "1.0")]
+    simplify: f64,
+
+    /// Number of cpus to use
+    /// Set to 0 to use all available cpus
+    #[structopt(short = "c", long = "cpus", default_value = "0")]
+    cpus: usize,
+
+    /// Gap tolerance, see the main command
+    /// Unit: meters
+    #[structopt(short = "g", long = "gap", default_value = "150.0")]
+    gap: f64,
+}
+
+#[derive(StructOpt, Debug)]
+struct ValidateCli {
+    /// Path to the input file to read
+    /// must be in OSM PBF format
+    #[structopt(parse(from_os_str))]
+    filename: std::path::PathBuf,
+
+    /// Path to the report file
+    /// Defaults to stdout
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    output: Option<std::path::PathBuf>,
+
+    /// Report format
+    /// Possible values:
+    /// - "json": list with one object per route and its findings
+    /// - "csv": one row per finding
+    #[structopt(long = "format", default_value = "json")]
+    format: ReportFormat,
+
+    /// Maximum distance from a platform to its closest stop position
+    /// Unit: meters
+    #[structopt(long = "platform-distance", default_value = "50.0")]
+    platform_distance: f64,
+
+    /// Maximum distance from the first / last stop to the ends of the route
+    /// Unit: meters
+    #[structopt(long = "end-distance", default_value = "100.0")]
+    end_distance: f64,
+
+    /// Maximum distance from a stop or platform to the route line
+    /// Unit: meters
+    #[structopt(long = "line-distance", default_value = "50.0")]
+    line_distance: f64,
+
+    /// Number of cpus to use
+    /// Set to 0 to use all available cpus
+    #[structopt(short = "c", long = "cpus", default_value = "0")]
+    cpus: usize,
+
+    /// Gap tolerance, see the main command
+    /// Unit: meters
+    #[structopt(short = "g", long = "gap", default_value = "150.0")]
+    gap: f64,
+
+    /// Fill the gaps of the ptv2 with the shortest path over the highway / railway ways of the pbf file
+    /// instead of joining them in a straight line, the filled ways are in the parse status
+    #[structopt(long = "fill-gaps")]
+    fill_gaps: bool,
+
+    /// Maximum extra length of the path that fills a gap over the straight distance of the gap (with --fill-gaps)
+    /// Unit: meters
+    #[structopt(long = "max-detour", default_value = "1000.0")]
+    max_detour: f64,
+}
+
+fn cpus_or_all(cpus: usize) -> usize {
+    if cpus == 0 {
         num_cpus::get()
     } else {
-        args.cpus
+        cpus
+    }
+}
+
+fn tiles(args: TilesCli) {
+    let input_filename = &args.filename.into_os_string().into_string().unwrap();
+    let mut tileset = match TileSet::new(TileOptions {
+        min_zoom: args.min_zoom,
+        max_zoom: args.max_zoom,
+        simplify: args.simplify,
+        ..TileOptions::default()
+    }) {
+        Ok(tileset) => tileset,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let parser = Parser::new_ptv2(input_filename, cpus_or_all(args.cpus));
+    parser.for_each_public_transport(args.gap, |pt| tileset.add_public_transport(&pt));
+    let result = if args.output.extension() == Some("mbtiles".as_ref()) {
+        tileset.write_mbtiles(&args.output)
+    } else {
+        tileset.write_directory(&args.output)
     };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn validate(args: ValidateCli) {
     let input_filename = &args.filename.into_os_string().into_string().unwrap();
-    if args.filter_ptv2 {
-        let parser = Parser::new_ptv2(input_filename, cpus);
-        println!("[");
-        let mut first = true;
-        for pt in parser.get_public_transports(args.gap) {
-            if !first {
-                println!(",");
-            } else {
-                first = false;
+    let cpus = cpus_or_all(args.cpus);
+    let mut parser = Parser::new_ptv2(input_filename, cpus);
+    if args.fill_gaps {
+        let graph = RoadGraph::new(input_filename, cpus);
+        parser.set_gap_fill(Some(GapFill::new(graph, args.max_detour)));
+    }
+    let options = ValidateOptions {
+        platform_distance: args.platform_distance,
+        end_distance: args.end_distance,
+        line_distance: args.line_distance,
+    };
+    let mut reports = Vec::new();
+    parser.for_each_public_transport(args.gap, |pt| {
+        reports.push(validator::validate_with(&pt, &options));
+    });
+    reports.sort_by_key(|r| r.route_id);
+    let invalid = reports.iter().filter(|r| !r.is_valid()).count();
+    eprintln!(
+        "{} routes validated, {} with errors",
+        reports.len(),
+        invalid
+    );
+    let format = args.format;
+    let result = match args.output {
+        Some(path) => std::fs::File::create(path)
+            .and_then(|f| validator::write_reports(&reports, format, std::io::BufWriter::new(f))),
+        None => validator::write_reports(&reports, format, std::io::stdout()),
+    };
+    if let Err(e) = result {
+        eprintln!("Error Writing: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn main() {
+    let args = Cli::from_args();
+    match args.command {
+        Some(Command::Tiles(tiles_args)) => return tiles(tiles_args),
+        Some(Command::Validate(validate_args)) => return validate(validate_args),
+        None => {}
+    }
+    let filename = args.filename.unwrap_or_else(|| {
+        Error::with_description(
+            "The following required arguments were not provided:\n    <filename>",
+            ErrorKind::MissingRequiredArgument,
+        )
+        .exit()
+    });
+    let cpus = cpus_or_all(args.cpus);
+    let simplify_algorithm = args.simplify_algorithm;
+    let simplify = args.simplify.map(|t| (simplify_algorithm, t));
+    let incomplete = args.incomplete;
+    let input_filename = &filename.into_os_string().into_string().unwrap();
+    let mut parser = if args.filter_ptv2 {
+        Parser::new_ptv2(input_filename, cpus)
+    } else {
+        Parser::new(input_filename, cpus, args.filter.clone().unwrap())
+    };
+    if args.filter_ptv2 && args.fill_gaps {
+        let graph = RoadGraph::new(input_filename, cpus);
+        parser.set_gap_fill(Some(GapFill::new(graph, args.max_detour)));
+    }
+    if args.admin_hierarchy == Some(Hierarchy::Tree) {
+        return admin_hierarchy_tree(parser, args.gap, &incomplete, args.output);
+    }
+    if let Some(format) = args.network {
+        let mut network = StopNetwork::new(StopArea::parse(input_filename, cpus));
+        for_each_public_transport(&parser, args.gap, args.partial, |pt| {
+            if incomplete.keep(&pt.parse_status) {
+                network.add_public_transport(&pt);
             }
-            print!("  {}", pt.to_geojson());
+        });
+        return write_network(&network, format, args.output);
+    }
+    let crs = match args.crs {
+        OutputCrs::Fixed(crs) => crs,
+        OutputCrs::Utm => match parser.bbox() {
+            Some((minx, miny, maxx, maxy)) => {
+                Crs::utm_for(((minx + maxx) / 2_f64, (miny + maxy) / 2_f64))
+            }
+            None => Crs::Wgs84,
+        },
+    };
+    if args.stops {
+        if args.format != Format::GeoJson {
+            eprintln!("--stops is only available in geojson format");
+            std::process::exit(1);
+        }
+        let mut registry = StopRegistry::new(StopArea::parse(input_filename, cpus));
+        for_each_public_transport(&parser, args.gap, args.partial, |pt| {
+            if incomplete.keep(&pt.parse_status) {
+                registry.add_public_transport(&pt);
+            }
+        });
+        eprintln!(
+            "{} stops, {} stop areas",
+            registry.len(),
+            registry.stop_areas().len()
+        );
+        return write_geojson(registry.to_geojson(), crs, args.output);
+    }
+    if args.segments {
+        if args.format != Format::GeoJson {
+            eprintln!("--segments is only available in geojson format");
+            std::process::exit(1);
         }
-        print!("]");
+        let mut features = Vec::new();
+        for_each_public_transport(&parser, args.gap, args.partial, |mut pt| {
+            if !incomplete.keep(&pt.parse_status) {
+                return;
+            }
+            if let Some((algorithm, tolerance)) = simplify {
+                pt.simplify(algorithm, tolerance);
+            }
+            if let serde_json::Value::Array(segments) = pt.segments_geojson()["features"].take() {
+                features.extend(segments);
+            }
+        });
+        eprintln!("{} segments", features.len());
+        let geojson = serde_json::json!({
+            "type": "FeatureCollection",
+            "features": features,
+        });
+        return write_geojson(geojson, crs, args.output);
+    }
+    let speed_profiles = match &args.speed_profiles {
+        Some(path) => read_speed_profiles(path).unwrap_or_else(|e| {
+            eprintln!("Error reading {}: {}", path.display(), e);
+            std::process::exit(1);
+        }),
+        None => SpeedProfiles::default(),
+    };
+    let options = WriterOptions {
+        metrics: args.metrics,
+        admin_areas: args.admin_areas,
+        crs,
+        speed_profiles,
+        timezone: args.timezone.clone(),
+        flat_properties: if args.flat_properties {
+            Some(args.languages.clone())
+        } else {
+            None
+        },
+    };
+    let writer = if args.split {
+        writer::SplitWriter::create(args.format, args.output.as_deref().unwrap())
+            .map(|w| Box::new(w.with_options(options)) as Box<dyn writer::Writer>)
     } else {
-        let parser = Parser::new(input_filename, cpus, args.filter);
-        println!("[");
-        let mut first = true;
-        for area in parser.get_areas(args.gap) {
-            if area.parse_status.code != 0 {
-                continue;
+        writer::create_with(args.format, args.output.as_deref(), options)
+    };
+    let mut writer = writer.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    // first write error, nothing else is written after it
+    let mut error: Option<WriteError> = None;
+    if args.filter_ptv2 {
+        let admin_areas = if args.admin_areas {
+            let areas = Parser::new_aa(input_filename, cpus).get_areas(args.gap);
+            Some(AdminAreas::new(
+                areas
+                    .into_iter()
+                    .filter(|area| area.parse_status.kind != ParseStatusKind::Broken)
+                    .collect(),
+            ))
+        } else {
+            None
+        };
+        let write = |mut pt: PublicTransport| {
+            if error.is_some() || !incomplete.keep(&pt.parse_status) {
+                return;
             }
-            if area.geometry.is_empty() {
-                continue;
+            if let Some(admin_areas) = &admin_areas {
+                admin_areas.assign(&mut pt);
             }
-            if !first {
-                println!(",");
-            } else {
-                first = false;
+            if let Some((algorithm, tolerance)) = simplify {
+                pt.simplify(algorithm, tolerance);
+            }
+            if let Err(e) = writer.write_public_transport(&pt) {
+                error = Some(e);
+            }
+        };
+        for_each_public_transport(&parser, args.gap, args.partial, write);
+    } else {
+        let mut write = |mut area: Area| {
+            if error.is_some() || !keep_area(&area, &incomplete) {
+                return;
             }
-            print!("  {}", area.to_geojson());
+            if let Some((algorithm, tolerance)) = simplify {
+                area.simplify(algorithm, tolerance);
+            }
+            if let Err(e) = writer.write_area(&area) {
+                error = Some(e);
+            }
+        };
+        if args.admin_hierarchy == Some(Hierarchy::ParentId) {
+            let areas = parser.get_areas(args.gap);
+            let hierarchy = AdminHierarchy::new(
+                areas
+                    .into_iter()
+                    .filter(|area| keep_area(area, &incomplete))
+                    .collect(),
+            );
+            for area in hierarchy.into_areas() {
+                write(area);
+            }
+        } else {
+            parser.for_each_area(args.gap, write);
         }
-        println!();
-        println!("]");
     }
+    let result = match error {
+        Some(e) => Err(e),
+        None => writer.finish(),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// runs `consume` on each ptv2, with the best partial geometry of the broken ones if `partial`
+fn for_each_public_transport<C>(parser: &Parser, gap: f64, partial: bool, consume: C)
+where
+    C: FnMut(PublicTransport),
+{
+    if partial {
+        parser.for_each_public_transport_partial(gap, consume);
+    } else {
+        parser.for_each_public_transport(gap, consume);
+    }
+}
+
+fn read_speed_profiles(path: &std::path::Path) -> Result<SpeedProfiles, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let json = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+    SpeedProfiles::from_json(&json)
+}
+
+fn keep_area(area: &Area, incomplete: &Incomplete) -> bool {
+    area.parse_status.code() == 0
+        && !area.geometry.is_empty()
+        && incomplete.keep(&area.parse_status)
+}
+
+/// writes the network graph in `format`
+fn write_network(network: &StopNetwork, format: NetworkFormat, output: Option<PathBuf>) {
+    eprintln!(
+        "{} stops, {} edges",
+        network.stops().len(),
+        network.edges().len()
+    );
+    match format {
+        NetworkFormat::GraphMl => write_output(output, |out| network.write_graphml(out)),
+        NetworkFormat::Json => write_json(&network.to_json(), output),
+    }
+}
+
+/// writes a geojson FeatureCollection with its geometries in `crs`
+fn write_geojson(mut geojson: serde_json::Value, crs: Crs, output: Option<PathBuf>) {
+    crs.reproject_geojson(&mut geojson);
+    write_json(&geojson, output);
+}
+
+/// writes `json` in one line
+fn write_json(json: &serde_json::Value, output: Option<PathBuf>) {
+    write_output(output, |out| {
+        let mut out = std::io::BufWriter::new(out);
+        writeln!(out, "{}", json).and_then(|_| out.flush())
+    });
+}
+
+/// writes with `write` to the `output` file or to stdout when None, exits on errors
+fn write_output<F>(output: Option<PathBuf>, write: F)
+where
+    F: FnOnce(Box<dyn Write>) -> std::io::Result<()>,
+{
+    let result = match output {
+        Some(path) => std::fs::File::create(path).and_then(|f| write(Box::new(f))),
+        None => write(Box::new(std::io::stdout())),
+    };
+    if let Err(e) = result {
+        eprintln!("Error Writing: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// writes the nested json tree of the administrative areas and the gaps / overlaps between siblings
+fn admin_hierarchy_tree(
+    parser: Parser,
+    gap: f64,
+    incomplete: &Incomplete,
+    output: Option<PathBuf>,
+) {
+    let areas = parser.get_areas(gap);
+    let hierarchy = AdminHierarchy::new(
+        areas
+            .into_iter()
+            .filter(|area| keep_area(area, incomplete))
+            .collect(),
+    );
+    let issues = hierarchy.issues();
+    eprintln!(
+        "{} administrative areas, {} gaps / overlaps / zero areas",
+        hierarchy.areas().len(),
+        issues.len()
+    );
+    let tree = serde_json::json!({
+        "areas": hierarchy.to_json(),
+        "issues": issues.iter().map(|issue| issue.to_json()).collect::<Vec<_>>(),
+    });
+    write_json(&tree, output);
 }
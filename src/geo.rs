@@ -0,0 +1,164 @@
+//! Geodesic helpers shared by the parser, the validator and the metrics
+
+use crate::parser::relation::LonLat;
+
+/// mean earth radius in meters
+pub const EARTH_RADIUS: f64 = 6_371_000_f64;
+
+/// haversine distance between two points, in meters
+pub fn haversine(p1: LonLat, p2: LonLat) -> f64 {
+    let (lon1, lat1) = p1;
+    let (lon2, lat2) = p2;
+    let dlat = (lat2 - lat1).to_radians();
+    let dlon = (lon2 - lon1).to_radians();
+    let a = (dlat / 2_f64).sin() * (dlat / 2_f64).sin()
+        + lat1.to_radians().cos()
+            * lat2.to_radians().cos()
+            * (dlon / 2_f64).sin()
+            * (dlon / 2_f64).sin();
+    let c = 2_f64 * a.sqrt().atan2((1_f64 - a).sqrt());
+    EARTH_RADIUS * c
+}
+
+/// Closest point of `line` to `point`, returns (distance along the line from its start, distance from `point` to the line)
+/// both in meters, None if the line has no points
+/// Each segment is projected on a local equirectangular plane centered on `point`, good enough for the short segments of osm ways
+pub fn locate(line: &[LonLat], point: LonLat) -> Option<(f64, f64)> {
+    locate_from(line, point, 0_f64)
+}
+
+/// Closest point of `line` to `point` at `from` meters or more from its start, see locate()
+/// None if the line has no points or is shorter than `from`
+pub fn locate_from(line: &[LonLat], point: LonLat, from: f64) -> Option<(f64, f64)> {
+    let meters_per_degree = EARTH_RADIUS * std::f64::consts::PI / 180_f64;
+    let cos_lat = point.1.to_radians().cos();
+    let to_plane = |p: &LonLat| {
+        (
+            (p.0 - point.0) * cos_lat * meters_per_degree,
+            (p.1 - point.1) * meters_per_degree,
+        )
+    };
+    if line.len() == 1 {
+        if from > 0_f64 {
+            return None;
+        }
+        let (x, y) = to_plane(&line[0]);
+        return Some((0_f64, (x * x + y * y).sqrt()));
+    }
+    let mut best: Option<(f64, f64)> = None;
+    let mut along = 0_f64;
+    for segment in line.windows(2) {
+        let length = haversine(segment[0], segment[1]);
+        if along + length < from {
+            along += length;
+            continue;
+        }
+        let (ax, ay) = to_plane(&segment[0]);
+        let (bx, by) = to_plane(&segment[1]);
+        let (dx, dy) = (bx - ax, by - ay);
+        let len2 = dx * dx + dy * dy;
+        let min_t = if length == 0_f64 {
+            0_f64
+        } else {
+            ((from - along) / length).clamp(0_f64, 1_f64)
+        };
+        let t = if len2 == 0_f64 {
+            0_f64
+        } else {
+            (-(ax * dx + ay * dy) / len2).clamp(min_t, 1_f64)
+        };
+        let (cx, cy) = (ax + t * dx, ay + t * dy);
+        let offset = (cx * cx + cy * cy).sqrt();
+        if best.is_none_or(|(_, best_offset)| offset < best_offset) {
+            best = Some((along + t * length, offset));
+        }
+        along += length;
+    }
+    best
+}
+
+/// length of a linestring in meters
+pub fn length(line: &[LonLat]) -> f64 {
+    line.windows(2).map(|s| haversine(s[0], s[1])).sum()
+}
+
+/// Part of a multilinestring between the distances `from` and `to` along it in meters (gaps between parts not counted, as in locate()),
+/// reversed if `to` is lower than `from`, the parts are joined into one line
+pub fn slice(parts: &[Vec<LonLat>], from: f64, to: f64) -> Vec<LonLat> {
+    if to < from {
+        let mut line = slice(parts, to, from);
+        line.reverse();
+        return line;
+    }
+    let mut line: Vec<LonLat> = Vec::new();
+    let mut start = 0_f64;
+    for segment in parts.iter().flat_map(|part| part.windows(2)) {
+        let (a, b) = (segment[0], segment[1]);
+        let segment_length = haversine(a, b);
+        let end = start + segment_length;
+        if end >= from && start <= to {
+            let at = |distance: f64| {
+                if segment_length == 0_f64 {
+                    return a;
+                }
+                let t = ((distance - start) / segment_length).clamp(0_f64, 1_f64);
+                (a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1))
+            };
+            if line.is_empty() {
+                line.push(at(from));
+            }
+            line.push(at(to.min(end)));
+        }
+        start = end;
+    }
+    line.dedup();
+    line
+}
+
+/// Bounding box (min_lon, min_lat, max_lon, max_lat) of a geometry, None if it is empty
+pub fn bbox(geometry: &[Vec<LonLat>]) -> Option<(f64, f64, f64, f64)> {
+    geometry
+        .iter()
+        .flatten()
+        .fold(None, |acc, &(lon, lat)| match acc {
+            None => Some((lon, lat, lon, lat)),
+            Some((minx, miny, maxx, maxy)) => {
+                Some((minx.min(lon), miny.min(lat), maxx.max(lon), maxy.max(lat)))
+            }
+        })
+}
+
+/// local equirectangular projection in meters, centered on the mean latitude of some points
+pub struct Plane {
+    cos_lat: f64,
+}
+
+impl Plane {
+    pub fn new<'a, I: Iterator<Item = &'a LonLat>>(points: I) -> Self {
+        let (sum, count) = points.fold((0_f64, 0_usize), |(s, c), p| (s + p.1, c + 1));
+        let lat = if count == 0 {
+            0_f64
+        } else {
+            sum / count as f64
+        };
+        Plane {
+            cos_lat: lat.to_radians().cos(),
+        }
+    }
+
+    pub fn project(&self, p: &LonLat) -> (f64, f64) {
+        let meters_per_degree = EARTH_RADIUS.to_radians();
+        (
+            p.0 * self.cos_lat * meters_per_degree,
+            p.1 * meters_per_degree,
+        )
+    }
+
+    pub fn unproject(&self, p: (f64, f64)) -> LonLat {
+        let meters_per_degree = EARTH_RADIUS.to_radians();
+        (
+            p.0 / (self.cos_lat * meters_per_degree),
+            p.1 / meters_per_degree,
+        )
+    }
+}
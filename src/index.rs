@@ -0,0 +1,232 @@
+//! Spatial index over parsed public transports, their stops and areas
+//!
+//! Each layer is a packed hilbert R-tree (see rtree), distances are geodesic and in meters
+
+use std::collections::HashSet;
+
+use crate::geo::{haversine, locate, EARTH_RADIUS};
+use crate::parser::relation::{Area, LonLat, Node, PublicTransport};
+use crate::rtree::{hilbert_order, BBox, PackedRTree};
+
+const NODE_SIZE: usize = 16;
+
+/// Something that can be stored in a SpatialLayer
+pub trait Spatial {
+    /// (min_lon, min_lat, max_lon, max_lat), None if it has no geometry
+    fn bbox(&self) -> Option<(f64, f64, f64, f64)>;
+    /// distance in meters from `point` to the geometry, 0 inside areas
+    fn distance(&self, point: LonLat) -> f64;
+}
+
+impl Spatial for Node {
+    fn bbox(&self) -> Option<(f64, f64, f64, f64)> {
+        Some((self.lon, self.lat, self.lon, self.lat))
+    }
+
+    fn distance(&self, point: LonLat) -> f64 {
+        haversine((self.lon, self.lat), point)
+    }
+}
+
+impl Spatial for PublicTransport {
+    fn bbox(&self) -> Option<(f64, f64, f64, f64)> {
+        crate::geo::bbox(&self.geometry)
+    }
+
+    fn distance(&self, point: LonLat) -> f64 {
+        self.geometry
+            .iter()
+            .filter_map(|part| locate(part, point))
+            .map(|(_, offset)| offset)
+            .fold(f64::INFINITY, f64::min)
+    }
+}
+
+impl Spatial for Area {
+    fn bbox(&self) -> Option<(f64, f64, f64, f64)> {
+        crate::geo::bbox(&self.geometry)
+    }
+
+    fn distance(&self, point: LonLat) -> f64 {
+        if self.contains(point) {
+            return 0_f64;
+        }
+        self.geometry
+            .iter()
+            .filter_map(|ring| locate(ring, point))
+            .map(|(_, offset)| offset)
+            .fold(f64::INFINITY, f64::min)
+    }
+}
+
+impl Area {
+    /// true if `point` is inside the polygon (even-odd rule, so the rings after the first are holes)
+    pub fn contains(&self, point: LonLat) -> bool {
+        let (x, y) = point;
+        let mut inside = false;
+        for s in self.geometry.iter().flat_map(|ring| ring.windows(2)) {
+            let ((ax, ay), (bx, by)) = (s[0], s[1]);
+            if (ay > y) != (by > y) && x < (bx - ax) * (y - ay) / (by - ay) + ax {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+}
+
+/// `meters` along a meridian, in degrees of latitude
+fn degrees(meters: f64) -> f64 {
+    (meters / EARTH_RADIUS).to_degrees()
+}
+
+/// lower bound of the distance in meters from `point` to any point of `bbox`
+fn bbox_distance(bbox: &BBox, point: LonLat) -> f64 {
+    let dx = (bbox.min_x - point.0).max(point.0 - bbox.max_x).max(0_f64);
+    let dy = (bbox.min_y - point.1).max(point.1 - bbox.max_y).max(0_f64);
+    // the meridians are closest at the latitude farthest from the equator
+    let max_lat = bbox
+        .min_y
+        .abs()
+        .max(bbox.max_y.abs())
+        .max(point.1.abs())
+        .min(90_f64);
+    let dx = dx * max_lat.to_radians().cos();
+    (dx * dx + dy * dy).sqrt().to_radians() * EARTH_RADIUS
+}
+
+/// Items of one type with an R-tree of their bounding boxes
+pub struct SpatialLayer<T> {
+    items: Vec<T>,
+    tree: Option<PackedRTree>,
+}
+
+impl<T: Spatial> SpatialLayer<T> {
+    /// items without geometry are kept but never found by the queries
+    pub fn new(items: Vec<T>) -> Self {
+        let boxes: Vec<(usize, BBox)> = items
+            .iter()
+            .enumerate()
+            .filter_map(|(i, item)| {
+                item.bbox().map(|(min_x, min_y, max_x, max_y)| {
+                    (
+                        i,
+                        BBox {
+                            min_x,
+                            min_y,
+                            max_x,
+                            max_y,
+                        },
+                    )
+                })
+            })
+            .collect();
+        let tree = if boxes.is_empty() {
+            None
+        } else {
+            let order = hilbert_order(&boxes.iter().map(|b| b.1).collect::<Vec<_>>());
+            let sorted: Vec<(BBox, u64)> = order
+                .iter()
+                .map(|i| (boxes[*i].1, boxes[*i].0 as u64))
+                .collect();
+            Some(PackedRTree::build(&sorted, NODE_SIZE))
+        };
+        SpatialLayer { items, tree }
+    }
+
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    pub fn into_items(self) -> Vec<T> {
+        self.items
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// items whose bounding box intersects (min_lon, min_lat, max_lon, max_lat)
+    pub fn in_bbox(&self, bbox: (f64, f64, f64, f64)) -> Vec<&T> {
+        let (min_x, min_y, max_x, max_y) = bbox;
+        let bbox = BBox {
+            min_x,
+            min_y,
+            max_x,
+            max_y,
+        };
+        let mut offsets = match &self.tree {
+            Some(tree) => tree.search(&bbox),
+            None => Vec::new(),
+        };
+        offsets.sort_unstable();
+        offsets.iter().map(|i| &self.items[*i as usize]).collect()
+    }
+
+    /// items at `meters` or less from `point` with their distance, closest first
+    pub fn within(&self, point: LonLat, meters: f64) -> Vec<(&T, f64)> {
+        let dy = degrees(meters);
+        let dx = dy / point.1.to_radians().cos().max(1e-6);
+        let mut found: Vec<(&T, f64)> = self
+            .in_bbox((point.0 - dx, point.1 - dy, point.0 + dx, point.1 + dy))
+            .into_iter()
+            .map(|item| (item, item.distance(point)))
+            .filter(|(_, distance)| *distance <= meters)
+            .collect();
+        found.sort_by(|a, b| a.1.total_cmp(&b.1));
+        found
+    }
+
+    /// the `k` items closest to `point` with their distance, closest first
+    pub fn nearest(&self, point: LonLat, k: usize) -> Vec<(&T, f64)> {
+        let tree = match &self.tree {
+            Some(tree) => tree,
+            None => return Vec::new(),
+        };
+        tree.nearest(
+            k,
+            |bbox| bbox_distance(bbox, point),
+            |i| self.items[i as usize].distance(point),
+        )
+        .into_iter()
+        .map(|(i, distance)| (&self.items[i as usize], distance))
+        .collect()
+    }
+}
+
+impl SpatialLayer<Area> {
+    /// areas that contain `point`
+    pub fn containing(&self, point: LonLat) -> Vec<&Area> {
+        self.in_bbox((point.0, point.1, point.0, point.1))
+            .into_iter()
+            .filter(|area| area.contains(point))
+            .collect()
+    }
+}
+
+/// Spatial index over parser results: public transport lines, their stops (once per node) and areas
+pub struct SpatialIndex {
+    pub routes: SpatialLayer<PublicTransport>,
+    pub stops: SpatialLayer<Node>,
+    pub areas: SpatialLayer<Area>,
+}
+
+impl SpatialIndex {
+    pub fn new(routes: Vec<PublicTransport>, areas: Vec<Area>) -> Self {
+        let mut seen = HashSet::new();
+        let stops: Vec<Node> = routes
+            .iter()
+            .flat_map(|pt| pt.stops.iter())
+            .filter(|stop| seen.insert(stop.id))
+            .cloned()
+            .collect();
+        SpatialIndex {
+            routes: SpatialLayer::new(routes),
+            stops: SpatialLayer::new(stops),
+            areas: SpatialLayer::new(areas),
+        }
+    }
+}
@@ -1,7 +1,32 @@
+pub mod admin;
+mod geo;
+pub mod index;
+pub mod metrics;
+pub mod network;
 mod parser;
-pub use parser::parse_status::ParseStatus;
+pub mod proj;
+pub mod route;
+mod rtree;
+pub mod schedule;
+pub mod simplify;
+pub mod stops;
+pub mod tiles;
+pub mod timing;
+pub mod validator;
+pub mod writer;
+pub use parser::parse_status::{Gap, ParseIssue, ParseStatus, ParseStatusKind};
 pub use parser::relation::Area;
+pub use parser::relation::LonLat;
+pub use parser::relation::Member;
+pub use parser::relation::MemberType;
+pub use parser::relation::MissingMembers;
+pub use parser::relation::Node;
 pub use parser::relation::PublicTransport;
 pub use parser::relation::Relation;
+pub use parser::relation::StopPosition;
+pub use parser::relation::StopSegment;
+pub use parser::relation::Way;
+pub use parser::road_graph::{GapFill, GraphPath, Network, RoadGraph};
+pub use parser::stop_area::StopArea;
 pub use parser::Parser;
 pub use parser::ParserRelationIterator;
@@ -0,0 +1,374 @@
+//! Per route / area numbers for analytics: lengths, areas, bounding boxes, centroids and stop spacing
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use serde_json::{json, Value};
+
+use crate::geo::{bbox, haversine, length, Plane, EARTH_RADIUS};
+use crate::parser::relation::{Area, LonLat, PublicTransport};
+
+/// (min_lon, min_lat, max_lon, max_lat)
+pub type BBox = (f64, f64, f64, f64);
+
+/// Metrics of a public transport route
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouteMetrics {
+    /// geodesic length of the geometry in meters
+    pub length: f64,
+    /// None if the geometry is empty
+    pub bbox: Option<BBox>,
+    pub stop_count: usize,
+    /// distance along the route between consecutive stops in meters,
+    /// None with less than two stops located on the geometry
+    pub stop_spacing_avg: Option<f64>,
+    pub stop_spacing_min: Option<f64>,
+    pub stop_spacing_max: Option<f64>,
+    /// length over the straight distance between start and end,
+    /// None when they are closer than a meter (circular routes)
+    pub sinuosity: Option<f64>,
+}
+
+/// Metrics of an area, the rings inside an odd number of other rings are holes and the rest are outer rings
+#[derive(Clone, Debug, PartialEq)]
+pub struct AreaMetrics {
+    /// geodesic area in square meters
+    pub area: f64,
+    /// length of all the rings in meters
+    pub perimeter: f64,
+    /// None if the geometry is empty
+    pub bbox: Option<BBox>,
+    /// area weighted centroid, may be outside of concave polygons, None if the area is 0
+    pub centroid: Option<LonLat>,
+    /// point inside the largest outer ring farthest from its boundary and its holes, None if the area is 0
+    pub pole_of_inaccessibility: Option<LonLat>,
+}
+
+impl RouteMetrics {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "length": self.length,
+            "bbox": self.bbox,
+            "stop_count": self.stop_count,
+            "stop_spacing_avg": self.stop_spacing_avg,
+            "stop_spacing_min": self.stop_spacing_min,
+            "stop_spacing_max": self.stop_spacing_max,
+            "sinuosity": self.sinuosity,
+        })
+    }
+}
+
+impl AreaMetrics {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "area": self.area,
+            "perimeter": self.perimeter,
+            "bbox": self.bbox,
+            "centroid": self.centroid,
+            "pole_of_inaccessibility": self.pole_of_inaccessibility,
+        })
+    }
+}
+
+impl PublicTransport {
+    pub fn metrics(&self) -> RouteMetrics {
+        let distances: Vec<f64> = self
+            .stop_positions()
+            .iter()
+            .flatten()
+            .map(|p| p.distance)
+            .collect();
+        let spacings: Vec<f64> = distances.windows(2).map(|d| (d[1] - d[0]).abs()).collect();
+        let route_length: f64 = self.geometry.iter().map(|part| length(part)).sum();
+        let ends = self
+            .geometry
+            .first()
+            .and_then(|part| part.first())
+            .zip(self.geometry.last().and_then(|part| part.last()));
+        let sinuosity = match ends {
+            Some((start, end)) if haversine(*start, *end) >= 1_f64 => {
+                Some(route_length / haversine(*start, *end))
+            }
+            _ => None,
+        };
+        RouteMetrics {
+            length: route_length,
+            bbox: bbox(&self.geometry),
+            stop_count: self.stops.len(),
+            stop_spacing_avg: if spacings.is_empty() {
+                None
+            } else {
+                Some(spacings.iter().sum::<f64>() / spacings.len() as f64)
+            },
+            stop_spacing_min: spacings.iter().cloned().reduce(f64::min),
+            stop_spacing_max: spacings.iter().cloned().reduce(f64::max),
+            sinuosity,
+        }
+    }
+}
+
+impl Area {
+    pub fn metrics(&self) -> AreaMetrics {
+        let plane = Plane::new(self.geometry.iter().flatten());
+        let rings: Vec<Vec<(f64, f64)>> = self
+            .geometry
+            .iter()
+            .map(|ring| ring.iter().map(|p| plane.project(p)).collect())
+            .collect();
+        let holes = holes(&rings);
+        let area = self
+            .geometry
+            .iter()
+            .zip(&holes)
+            .map(|(ring, hole)| {
+                if *hole {
+                    -ring_area(ring)
+                } else {
+                    ring_area(ring)
+                }
+            })
+            .sum::<f64>()
+            .max(0_f64);
+        let (centroid, pole) = if area > 0_f64 {
+            (
+                plane_centroid(&rings, &holes).map(|c| plane.unproject(c)),
+                largest_polygon(&rings, &holes)
+                    .and_then(|polygon| pole_of_inaccessibility(&polygon))
+                    .map(|c| plane.unproject(c)),
+            )
+        } else {
+            (None, None)
+        };
+        AreaMetrics {
+            area,
+            perimeter: self.geometry.iter().map(|ring| length(ring)).sum(),
+            bbox: bbox(&self.geometry),
+            centroid,
+            pole_of_inaccessibility: pole,
+        }
+    }
+}
+
+/// geodesic area of a ring in square meters, on a sphere
+fn ring_area(ring: &[LonLat]) -> f64 {
+    let sum: f64 = ring
+        .windows(2)
+        .map(|s| {
+            (s[1].0 - s[0].0).to_radians()
+                * (2_f64 + s[0].1.to_radians().sin() + s[1].1.to_radians().sin())
+        })
+        .sum();
+    (sum * EARTH_RADIUS * EARTH_RADIUS / 2_f64).abs()
+}
+
+/// (area, centroid) of a ring on the plane with the shoelace formula, area is always positive
+fn ring_centroid(ring: &[(f64, f64)]) -> (f64, (f64, f64)) {
+    let (ox, oy) = ring[0];
+    let (mut a, mut cx, mut cy) = (0_f64, 0_f64, 0_f64);
+    for s in ring.windows(2) {
+        let (x0, y0) = (s[0].0 - ox, s[0].1 - oy);
+        let (x1, y1) = (s[1].0 - ox, s[1].1 - oy);
+        let cross = x0 * y1 - x1 * y0;
+        a += cross;
+        cx += (x0 + x1) * cross;
+        cy += (y0 + y1) * cross;
+    }
+    if a == 0_f64 {
+        return (0_f64, (ox, oy));
+    }
+    (
+        (a / 2_f64).abs(),
+        (ox + cx / (3_f64 * a), oy + cy / (3_f64 * a)),
+    )
+}
+
+/// true if `p` is inside `ring` (even-odd rule)
+fn ring_contains(ring: &[(f64, f64)], p: (f64, f64)) -> bool {
+    let mut inside = false;
+    for s in ring.windows(2) {
+        let (a, b) = (s[0], s[1]);
+        if (a.1 > p.1) != (b.1 > p.1) && p.0 < (b.0 - a.0) * (p.1 - a.1) / (b.1 - a.1) + a.0 {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// whether each ring is a hole, by its nesting depth: the rings inside an odd number of other rings
+fn holes(rings: &[Vec<(f64, f64)>]) -> Vec<bool> {
+    rings
+        .iter()
+        .enumerate()
+        .map(|(i, ring)| match ring.first() {
+            Some(p) => {
+                rings
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, other)| *j != i && ring_contains(other, *p))
+                    .count()
+                    % 2
+                    == 1
+            }
+            None => false,
+        })
+        .collect()
+}
+
+/// the largest outer ring followed by the rings nested in it
+fn largest_polygon(rings: &[Vec<(f64, f64)>], holes: &[bool]) -> Option<Vec<Vec<(f64, f64)>>> {
+    let (largest, outer) = rings
+        .iter()
+        .enumerate()
+        .filter(|(i, ring)| !holes[*i] && !ring.is_empty())
+        .map(|(i, ring)| (i, ring, ring_centroid(ring).0))
+        .max_by(|a, b| a.2.total_cmp(&b.2))
+        .map(|(i, ring, _)| (i, ring))?;
+    let mut polygon = vec![outer.clone()];
+    polygon.extend(
+        rings
+            .iter()
+            .enumerate()
+            .filter(|(i, ring)| {
+                *i != largest && ring.first().is_some_and(|p| ring_contains(outer, *p))
+            })
+            .map(|(_, ring)| ring.clone()),
+    );
+    Some(polygon)
+}
+
+fn plane_centroid(rings: &[Vec<(f64, f64)>], holes: &[bool]) -> Option<(f64, f64)> {
+    let (mut total, mut x, mut y) = (0_f64, 0_f64, 0_f64);
+    for (i, ring) in rings.iter().enumerate().filter(|(_, r)| !r.is_empty()) {
+        let sign = if holes[i] { -1_f64 } else { 1_f64 };
+        let (a, (cx, cy)) = ring_centroid(ring);
+        total += sign * a;
+        x += sign * a * cx;
+        y += sign * a * cy;
+    }
+    if total <= 0_f64 {
+        return None;
+    }
+    Some((x / total, y / total))
+}
+
+/// distance from `p` to the boundary of the polygon, negative when `p` is outside
+fn signed_distance(rings: &[Vec<(f64, f64)>], p: (f64, f64)) -> f64 {
+    let mut inside = false;
+    let mut min = f64::INFINITY;
+    for s in rings.iter().flat_map(|ring| ring.windows(2)) {
+        let (a, b) = (s[0], s[1]);
+        if (a.1 > p.1) != (b.1 > p.1) && p.0 < (b.0 - a.0) * (p.1 - a.1) / (b.1 - a.1) + a.0 {
+            inside = !inside;
+        }
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let len2 = dx * dx + dy * dy;
+        let t = if len2 == 0_f64 {
+            0_f64
+        } else {
+            (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / len2).clamp(0_f64, 1_f64)
+        };
+        let (ex, ey) = (a.0 + t * dx - p.0, a.1 + t * dy - p.1);
+        min = min.min((ex * ex + ey * ey).sqrt());
+    }
+    if inside {
+        min
+    } else {
+        -min
+    }
+}
+
+/// square cell of the pole of inaccessibility search
+struct Cell {
+    center: (f64, f64),
+    half: f64,
+    distance: f64,
+    /// maximum distance that a point of the cell can have
+    max: f64,
+}
+
+impl Cell {
+    fn new(rings: &[Vec<(f64, f64)>], center: (f64, f64), half: f64) -> Self {
+        let distance = signed_distance(rings, center);
+        Cell {
+            center,
+            half,
+            distance,
+            max: distance + half * std::f64::consts::SQRT_2,
+        }
+    }
+}
+
+impl PartialEq for Cell {
+    fn eq(&self, other: &Self) -> bool {
+        self.max == other.max
+    }
+}
+impl Eq for Cell {}
+impl PartialOrd for Cell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Cell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.max.total_cmp(&other.max)
+    }
+}
+
+/// polylabel: quadtree search of the point farthest from the boundary of a polygon
+/// (the outer ring first and then the rings nested in it),
+/// with a precision of a thousandth of the polygon size (at least a meter)
+fn pole_of_inaccessibility(rings: &[Vec<(f64, f64)>]) -> Option<(f64, f64)> {
+    let outer = rings.first().filter(|r| !r.is_empty())?;
+    let (minx, miny, maxx, maxy) = outer.iter().fold(
+        (
+            f64::INFINITY,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NEG_INFINITY,
+        ),
+        |(a, b, c, d), p| (a.min(p.0), b.min(p.1), c.max(p.0), d.max(p.1)),
+    );
+    let size = (maxx - minx).min(maxy - miny);
+    if size <= 0_f64 {
+        return None;
+    }
+    let precision = (size / 1000_f64).max(1_f64);
+
+    let mut queue = BinaryHeap::new();
+    let half = size / 2_f64;
+    let mut x = minx;
+    while x < maxx {
+        let mut y = miny;
+        while y < maxy {
+            queue.push(Cell::new(rings, (x + half, y + half), half));
+            y += size;
+        }
+        x += size;
+    }
+    let mut best = Cell::new(
+        rings,
+        plane_centroid(rings, &holes(rings))
+            .unwrap_or(((minx + maxx) / 2_f64, (miny + maxy) / 2_f64)),
+        0_f64,
+    );
+    while let Some(cell) = queue.pop() {
+        if cell.distance > best.distance {
+            best = Cell::new(rings, cell.center, 0_f64);
+        }
+        if cell.max - best.distance <= precision {
+            continue;
+        }
+        let half = cell.half / 2_f64;
+        for (dx, dy) in [
+            (-1_f64, -1_f64),
+            (1_f64, -1_f64),
+            (-1_f64, 1_f64),
+            (1_f64, 1_f64),
+        ] {
+            let center = (cell.center.0 + dx * half, cell.center.1 + dy * half);
+            queue.push(Cell::new(rings, center, half));
+        }
+    }
+    Some(best.center)
+}
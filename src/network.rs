@@ -0,0 +1,300 @@
+//! Topology of the public transport network: a directed graph with the deduplicated stops as nodes
+//! and an edge per pair of consecutive stops of each route, to find connections and transfers
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::io::{self, Write};
+
+use serde_json::{json, Value};
+
+use crate::parser::relation::{LonLat, PublicTransport};
+use crate::parser::stop_area::StopArea;
+use crate::stops::{Stop, StopRegistry};
+use crate::writer::xml_escape;
+
+/// Ride of a route between two consecutive stops
+#[derive(Clone, Debug)]
+pub struct Edge {
+    /// node id of the stop where the ride starts
+    pub from: u64,
+    /// node id of the next stop
+    pub to: u64,
+    /// osm id of the public transport
+    pub route_id: u64,
+    pub route_ref: Option<String>,
+    /// route line between both stops, empty if they are not located on the geometry
+    pub geometry: Vec<LonLat>,
+    /// distance along the route in meters, 0 if the stops are not located on the geometry
+    pub length: f64,
+}
+
+/// Path found between two stops
+#[derive(Clone, Debug)]
+pub struct Journey<'a> {
+    /// rides in order, walking between the stops of a stop area is not an edge
+    pub edges: Vec<&'a Edge>,
+    /// changes of route along the journey
+    pub transfers: usize,
+}
+
+/// Directed graph of stops and the rides between them
+pub struct StopNetwork {
+    registry: StopRegistry,
+    edges: Vec<Edge>,
+    /// positions in edges of the rides starting at each node
+    adjacency: HashMap<u64, Vec<usize>>,
+}
+
+/// search state: stop node id and route being ridden (None before the first ride)
+type State = (u64, Option<u64>);
+
+impl StopNetwork {
+    /// empty network, the stops of the same stop area are connected by walking (a transfer without a ride)
+    pub fn new(stop_areas: Vec<StopArea>) -> Self {
+        StopNetwork {
+            registry: StopRegistry::new(stop_areas),
+            edges: Vec::new(),
+            adjacency: HashMap::new(),
+        }
+    }
+
+    /// adds the stops of `pt` and an edge per segment
+    pub fn add_public_transport(&mut self, pt: &PublicTransport) {
+        self.registry.add_public_transport(pt);
+        for segment in pt.segments() {
+            self.adjacency
+                .entry(segment.from)
+                .or_default()
+                .push(self.edges.len());
+            self.edges.push(Edge {
+                from: segment.from,
+                to: segment.to,
+                route_id: pt.id,
+                route_ref: pt.route_ref().map(String::from),
+                geometry: segment.geometry,
+                length: segment.length,
+            });
+        }
+    }
+
+    /// the nodes of the graph, with the routes serving each one and their stop areas
+    pub fn stops(&self) -> &StopRegistry {
+        &self.registry
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// rides starting at the node
+    pub fn edges_from(&self, node_id: u64) -> Vec<&Edge> {
+        self.adjacency
+            .get(&node_id)
+            .map(|edges| edges.iter().map(|i| &self.edges[*i]).collect())
+            .unwrap_or_default()
+    }
+
+    /// journey from `from` to `to` (node ids) with the fewest rides, then the fewest transfers
+    pub fn shortest_hops(&self, from: u64, to: u64) -> Option<Journey<'_>> {
+        self.search(from, to, false)
+    }
+
+    /// journey from `from` to `to` (node ids) with the fewest transfers, then the fewest rides
+    pub fn fewest_transfers(&self, from: u64, to: u64) -> Option<Journey<'_>> {
+        self.search(from, to, true)
+    }
+
+    /// other stops of the stop area of the node
+    fn walks(&self, node_id: u64) -> Vec<&Stop> {
+        match self.registry.stop(node_id).and_then(|stop| stop.stop_area) {
+            Some(stop_area) => self
+                .registry
+                .stop_area_stops(stop_area)
+                .into_iter()
+                .filter(|stop| stop.node.id != node_id)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// dijkstra over (stop, route) states, the cost is (transfers, rides) or (rides, transfers)
+    fn search(&self, from: u64, to: u64, transfers_first: bool) -> Option<Journey<'_>> {
+        self.registry.stop(from)?;
+        let cost = |transfers: usize, rides: usize| {
+            if transfers_first {
+                (transfers, rides)
+            } else {
+                (rides, transfers)
+            }
+        };
+        let mut best: HashMap<State, (usize, usize)> = HashMap::new();
+        let mut previous: HashMap<State, (State, Option<usize>)> = HashMap::new();
+        let mut heap = BinaryHeap::new();
+        best.insert((from, None), (0, 0));
+        heap.push(Reverse((cost(0, 0), 0, 0, from, None)));
+        while let Some(Reverse((_, transfers, rides, node, route))) = heap.pop() {
+            let state = (node, route);
+            if best.get(&state) != Some(&(transfers, rides)) {
+                // outdated entry
+                continue;
+            }
+            if node == to {
+                let mut edges = Vec::new();
+                let mut state = state;
+                while let Some((prev, edge)) = previous.get(&state) {
+                    edges.extend(edge.map(|i| &self.edges[i]));
+                    state = *prev;
+                }
+                edges.reverse();
+                return Some(Journey { edges, transfers });
+            }
+            let rides_from = self.adjacency.get(&node).into_iter().flatten().map(|i| {
+                let edge = &self.edges[*i];
+                let transfer = route.is_some_and(|r| r != edge.route_id);
+                (
+                    (edge.to, Some(edge.route_id)),
+                    transfers + transfer as usize,
+                    rides + 1,
+                    Some(*i),
+                )
+            });
+            // walking keeps the route, boarding another one at the next stop is the transfer
+            let walks = self
+                .walks(node)
+                .into_iter()
+                .map(|stop| ((stop.node.id, route), transfers, rides, None));
+            for (next, transfers, rides, edge) in rides_from.chain(walks) {
+                if best
+                    .get(&next)
+                    .is_none_or(|b| cost(transfers, rides) < cost(b.0, b.1))
+                {
+                    best.insert(next, (transfers, rides));
+                    previous.insert(next, (state, edge));
+                    heap.push(Reverse((
+                        cost(transfers, rides),
+                        transfers,
+                        rides,
+                        next.0,
+                        next.1,
+                    )));
+                }
+            }
+        }
+        None
+    }
+
+    /// {"nodes": [{"id", "lon", "lat", "name", "stop_area", "routes"}],
+    /// "adjacency": {"<node id>": [{"to", "route_id", "ref", "length", "geometry"}]}}
+    pub fn to_json(&self) -> Value {
+        let nodes: Vec<Value> = self
+            .registry
+            .stops()
+            .iter()
+            .map(|stop| {
+                json!({
+                    "id": stop.node.id,
+                    "lon": stop.node.lon,
+                    "lat": stop.node.lat,
+                    "name": stop.node.tags.get("name"),
+                    "stop_area": stop.stop_area,
+                    "routes": stop.routes,
+                })
+            })
+            .collect();
+        let adjacency: BTreeMap<u64, Vec<Value>> = self
+            .adjacency
+            .iter()
+            .map(|(node_id, edges)| {
+                let edges = edges
+                    .iter()
+                    .map(|i| {
+                        let edge = &self.edges[*i];
+                        json!({
+                            "to": edge.to,
+                            "route_id": edge.route_id,
+                            "ref": edge.route_ref,
+                            "length": edge.length,
+                            "geometry": edge.geometry,
+                        })
+                    })
+                    .collect();
+                (*node_id, edges)
+            })
+            .collect();
+        json!({
+            "nodes": nodes,
+            "adjacency": adjacency,
+        })
+    }
+
+    /// writes the graph in GraphML, the geometry of the edges as WKT
+    pub fn write_graphml<W: Write>(&self, out: W) -> io::Result<()> {
+        let mut out = io::BufWriter::new(out);
+        writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(
+            out,
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">"
+        )?;
+        for (id, target, name, key_type) in [
+            ("lon", "node", "lon", "double"),
+            ("lat", "node", "lat", "double"),
+            ("name", "node", "name", "string"),
+            ("stop_area", "node", "stop_area", "long"),
+            ("route_id", "edge", "route_id", "long"),
+            ("ref", "edge", "ref", "string"),
+            ("length", "edge", "length", "double"),
+            ("geometry", "edge", "geometry", "string"),
+        ] {
+            writeln!(
+                out,
+                "  <key id=\"{}\" for=\"{}\" attr.name=\"{}\" attr.type=\"{}\"/>",
+                id, target, name, key_type
+            )?;
+        }
+        writeln!(out, "  <graph id=\"network\" edgedefault=\"directed\">")?;
+        for stop in self.registry.stops() {
+            writeln!(out, "    <node id=\"n{}\">", stop.node.id)?;
+            writeln!(out, "      <data key=\"lon\">{}</data>", stop.node.lon)?;
+            writeln!(out, "      <data key=\"lat\">{}</data>", stop.node.lat)?;
+            if let Some(name) = stop.node.tags.get("name") {
+                writeln!(out, "      <data key=\"name\">{}</data>", xml_escape(name))?;
+            }
+            if let Some(stop_area) = stop.stop_area {
+                writeln!(out, "      <data key=\"stop_area\">{}</data>", stop_area)?;
+            }
+            writeln!(out, "    </node>")?;
+        }
+        for edge in &self.edges {
+            writeln!(
+                out,
+                "    <edge source=\"n{}\" target=\"n{}\">",
+                edge.from, edge.to
+            )?;
+            writeln!(out, "      <data key=\"route_id\">{}</data>", edge.route_id)?;
+            if let Some(route_ref) = &edge.route_ref {
+                writeln!(
+                    out,
+                    "      <data key=\"ref\">{}</data>",
+                    xml_escape(route_ref)
+                )?;
+            }
+            writeln!(out, "      <data key=\"length\">{}</data>", edge.length)?;
+            if edge.geometry.len() > 1 {
+                let points: Vec<String> = edge
+                    .geometry
+                    .iter()
+                    .map(|(lon, lat)| format!("{} {}", lon, lat))
+                    .collect();
+                writeln!(
+                    out,
+                    "      <data key=\"geometry\">LINESTRING({})</data>",
+                    points.join(",")
+                )?;
+            }
+            writeln!(out, "    </edge>")?;
+        }
+        writeln!(out, "  </graph>")?;
+        writeln!(out, "</graphml>")?;
+        out.flush()
+    }
+}
@@ -3,6 +3,8 @@ extern crate osm_pbf_iter;
 use std::io::{self, Write};
 pub mod parse_status;
 pub mod relation;
+pub mod road_graph;
+pub mod stop_area;
 
 use std::collections::{HashMap, HashSet};
 use std::fmt;
@@ -15,7 +17,9 @@ use std::thread;
 
 use osm_pbf_iter::{Blob, BlobReader, Primitive, PrimitiveBlock, RelationMemberType};
 
-use relation::{Area, Node, PublicTransport, Relation, Way};
+use crate::schedule::Schedule;
+use relation::{Area, Member, MemberType, MissingMembers, Node, PublicTransport, Relation, Way};
+use road_graph::GapFill;
 
 #[derive(Clone, Debug)]
 struct NodeData {
@@ -40,27 +44,73 @@ struct RelationData {
     info: HashMap<String, String>,
     ways: Vec<u64>,
     stops: Vec<u64>,
+    members: Vec<Member>,
 }
 
 type WayIdsSet = HashSet<u64>;
 type NodeIdsSet = HashSet<u64>;
 
+#[derive(Default)]
 struct MessageRelations {
     relations: Vec<RelationData>,
     stop_ids: NodeIdsSet,
     way_ids: WayIdsSet,
 }
 
+#[derive(Default)]
 struct MessageWays {
     ways: Vec<WayData>,
     relations_ways: HashMap<u64, WayData>,
     node_ids: NodeIdsSet,
 }
 
+#[derive(Default)]
 struct MessageNodes {
     nodes: HashMap<u64, NodeData>,
 }
 
+/// runs `func` on each block of the pbf file in `cpus` threads, each thread folds its blocks into its own `T`,
+/// returns the result of each thread (the pbf counterpart of Parser::par_map_each())
+fn map_blocks<T, F>(pbf_filename: &str, cpus: usize, func: F) -> Vec<T>
+where
+    T: Default + Send + 'static,
+    F: Fn(&PrimitiveBlock, &mut T) + Send + Clone + 'static,
+{
+    let mut workers = Vec::with_capacity(cpus);
+    for _ in 0..cpus {
+        let (req_tx, req_rx) = sync_channel(2);
+        let (res_tx, res_rx) = sync_channel(0);
+        workers.push((req_tx, res_rx));
+        let func_local = func.clone();
+        thread::spawn(move || {
+            let mut result = T::default();
+            while let Ok(blob) = req_rx.recv() {
+                let data = (blob as Blob).into_data();
+                let primitive_block = PrimitiveBlock::parse(&data);
+                func_local(&primitive_block, &mut result);
+            }
+            res_tx.send(result).unwrap();
+        });
+    }
+
+    let f = File::open(pbf_filename).unwrap();
+    let mut reader = BlobReader::new(BufReader::new(f));
+    let mut w = 0;
+    for blob in &mut reader {
+        let req_tx = &workers[w].0;
+        w = (w + 1) % cpus;
+        req_tx.send(blob).unwrap();
+    }
+
+    workers
+        .into_iter()
+        .map(|(req_tx, res_rx)| {
+            drop(req_tx);
+            res_rx.recv().unwrap()
+        })
+        .collect()
+}
+
 /// Main class that parses a pbf file and maintains a cache of relations/ways/nodes
 /// then provides methods to access the public transports (PTv2) inside that (cached) file
 #[derive(Clone)]
@@ -70,6 +120,7 @@ pub struct Parser {
     ways: Vec<WayData>,
     nodes: HashMap<u64, NodeData>,
     cpus: usize,
+    gap_fill: Option<GapFill>,
 }
 
 /// Sequential iterator that returns a Relation on each turn
@@ -85,17 +136,18 @@ impl Parser {
     /// "tag_key"
     /// "tag_key=tag_value"
     /// "tag_key=tag_value,tag_value2&tag_key2=tag_value3"
-    fn filter_relation(relation: &osm_pbf_iter::Relation, conditions: &String) -> bool {
+    fn filter_relation(relation: &osm_pbf_iter::Relation, conditions: &str) -> bool {
         for condition in conditions.split('&') {
             let mut condition_split = condition.split('=');
             let condition_key = condition_split.next().unwrap().to_string();
             let condition_values = condition_split.next();
-            let tag = relation.tags().find(|&kv| kv.0 == condition_key);
-            if tag == None {
-                return false;
-            } else if condition_values != None {
-                let tag_value = tag.unwrap().1;
-                let condition_values_string = condition_values.unwrap().to_string();
+            let tag = match relation.tags().find(|&kv| kv.0 == condition_key) {
+                Some(tag) => tag,
+                None => return false,
+            };
+            if let Some(condition_values) = condition_values {
+                let tag_value = tag.1;
+                let condition_values_string = condition_values.to_string();
                 let condition_values_split = condition_values_string.split(',');
                 let mut found = false;
                 for condition_value in condition_values_split {
@@ -116,17 +168,18 @@ impl Parser {
     /// "tag_key"
     /// "tag_key=tag_value"
     /// "tag_key=tag_value,tag_value2&tag_key2=tag_value3"
-    fn filter_way(way: &osm_pbf_iter::Way, conditions: &String) -> bool {
+    fn filter_way(way: &osm_pbf_iter::Way, conditions: &str) -> bool {
         for condition in conditions.split('&') {
             let mut condition_split = condition.split('=');
             let condition_key = condition_split.next().unwrap().to_string();
             let condition_values = condition_split.next();
-            let tag = way.tags().find(|&kv| kv.0 == condition_key);
-            if tag == None {
-                return false;
-            } else if condition_values != None {
-                let tag_value = tag.unwrap().1;
-                let condition_values_string = condition_values.unwrap().to_string();
+            let tag = match way.tags().find(|&kv| kv.0 == condition_key) {
+                Some(tag) => tag,
+                None => return false,
+            };
+            if let Some(condition_values) = condition_values {
+                let tag_value = tag.1;
+                let condition_values_string = condition_values.to_string();
                 let condition_values_split = condition_values_string.split(',');
                 let mut found = false;
                 for condition_value in condition_values_split {
@@ -148,7 +201,7 @@ impl Parser {
         Self::new(
             pbf_filename,
             cpus,
-            "name&route_master&route=bus,tram,train,subway,light_rail,monorail,trolleybus"
+            "name&type=route&route=bus,tram,train,subway,light_rail,monorail,trolleybus"
                 .to_string(),
         )
     }
@@ -175,8 +228,8 @@ impl Parser {
         let mut ways = Vec::new() as Vec<WayData>;
         let mut relations_ways = HashMap::default() as HashMap<u64, WayData>;
         let mut nodes = HashMap::default() as HashMap<u64, NodeData>;
-        let way_ids = Arc::new(RwLock::new(HashSet::default() as WayIdsSet));
-        let node_ids = Arc::new(RwLock::new(HashSet::default() as NodeIdsSet));
+        let mut way_ids = HashSet::default() as WayIdsSet;
+        let mut node_ids = HashSet::default() as NodeIdsSet;
         let filters_arc = Arc::new(filters);
         /*
             pbf relations collect
@@ -184,117 +237,86 @@ impl Parser {
         {
             eprint!("START Relations map, ");
             io::stderr().flush().unwrap();
-            let mut workers = Vec::with_capacity(cpus);
-            for _ in 0..cpus {
-                let (req_tx, req_rx) = sync_channel(2);
-                let (res_tx, res_rx) = sync_channel(0);
-                workers.push((req_tx, res_rx));
-                let filters_local = filters_arc.clone();
-                thread::spawn(move || {
-                    let mut relations = Vec::new() as Vec<RelationData>;
-                    let mut stop_ids = HashSet::default() as NodeIdsSet;
-                    let mut way_ids = HashSet::default() as WayIdsSet;
-
-                    while let Ok(blob) = req_rx.recv() {
-                        let data = (blob as Blob).into_data();
-                        let primitive_block = PrimitiveBlock::parse(&data);
-                        for primitive in primitive_block.primitives() {
-                            if let Primitive::Relation(relation) = primitive {
-                                if Self::filter_relation(&relation, filters_local.as_ref()) {
-                                    let mut info: HashMap<String, String> = HashMap::new();
-                                    if let Some(info_data) = relation.info.clone() {
-                                        if let Some(version) = info_data.version {
-                                            info.insert("version".to_string(), version.to_string());
-                                        }
-                                        if let Some(timestamp) = info_data.timestamp {
-                                            info.insert(
-                                                "timestamp".to_string(),
-                                                timestamp.to_string(),
-                                            );
-                                        }
-                                        if let Some(changeset) = info_data.changeset {
-                                            info.insert(
-                                                "changeset".to_string(),
-                                                changeset.to_string(),
-                                            );
-                                        }
-                                        if let Some(uid) = info_data.uid {
-                                            info.insert("uid".to_string(), uid.to_string());
-                                        }
-                                        if let Some(user) = info_data.user {
-                                            info.insert("user".to_string(), user.to_string());
-                                        }
-                                        if let Some(visible) = info_data.visible {
-                                            info.insert("visible".to_string(), visible.to_string());
-                                        }
+            let filters_local = filters_arc.clone();
+            let results = map_blocks(
+                pbf_filename,
+                cpus,
+                move |primitive_block, result: &mut MessageRelations| {
+                    for primitive in primitive_block.primitives() {
+                        if let Primitive::Relation(relation) = primitive {
+                            if Self::filter_relation(&relation, filters_local.as_ref()) {
+                                let mut info: HashMap<String, String> = HashMap::new();
+                                if let Some(info_data) = relation.info.clone() {
+                                    if let Some(version) = info_data.version {
+                                        info.insert("version".to_string(), version.to_string());
                                     }
-                                    // condicion para saber si esta relation es un public transport
-                                    let mut rd = RelationData {
-                                        id: relation.id,
-                                        tags: relation
-                                            .tags()
-                                            .map(|t| (t.0.to_string(), t.1.to_string()))
-                                            .collect(),
-                                        info,
-                                        ways: Vec::new(),
-                                        stops: Vec::new(),
-                                    };
-                                    for member in relation.members() {
-                                        // member = (role: &str, id: u64, type: RelationMemberType)
-                                        if member.2 == RelationMemberType::Way {
-                                            rd.ways.push(member.1);
-                                            way_ids.insert(member.1);
-                                        }
-                                        if member.2 == RelationMemberType::Node {
-                                            rd.stops.push(member.1);
-                                            stop_ids.insert(member.1);
-                                        }
+                                    if let Some(timestamp) = info_data.timestamp {
+                                        info.insert("timestamp".to_string(), timestamp.to_string());
+                                    }
+                                    if let Some(changeset) = info_data.changeset {
+                                        info.insert("changeset".to_string(), changeset.to_string());
+                                    }
+                                    if let Some(uid) = info_data.uid {
+                                        info.insert("uid".to_string(), uid.to_string());
                                     }
-                                    if !rd.ways.is_empty() {
-                                        relations.push(rd);
-                                    } else {
-                                        // println!("WARNING: relation has no ways 'https://www.openstreetmap.org/relation/{:?}'", relation.id);
+                                    if let Some(user) = info_data.user {
+                                        info.insert("user".to_string(), user.to_string());
                                     }
+                                    if let Some(visible) = info_data.visible {
+                                        info.insert("visible".to_string(), visible.to_string());
+                                    }
+                                }
+                                // condicion para saber si esta relation es un public transport
+                                let mut rd = RelationData {
+                                    id: relation.id,
+                                    tags: relation
+                                        .tags()
+                                        .map(|t| (t.0.to_string(), t.1.to_string()))
+                                        .collect(),
+                                    info,
+                                    ways: Vec::new(),
+                                    stops: Vec::new(),
+                                    members: Vec::new(),
+                                };
+                                for member in relation.members() {
+                                    // member = (role: &str, id: u64, type: RelationMemberType)
+                                    rd.members.push(Member {
+                                        id: member.1,
+                                        member_type: match member.2 {
+                                            RelationMemberType::Node => MemberType::Node,
+                                            RelationMemberType::Way => MemberType::Way,
+                                            RelationMemberType::Relation => MemberType::Relation,
+                                        },
+                                        role: member.0.to_string(),
+                                    });
+                                    if member.2 == RelationMemberType::Way {
+                                        rd.ways.push(member.1);
+                                        result.way_ids.insert(member.1);
+                                    }
+                                    if member.2 == RelationMemberType::Node {
+                                        rd.stops.push(member.1);
+                                        result.stop_ids.insert(member.1);
+                                    }
+                                }
+                                if !rd.ways.is_empty() {
+                                    result.relations.push(rd);
+                                } else {
+                                    // println!("WARNING: relation has no ways 'https://www.openstreetmap.org/relation/{:?}'", relation.id);
                                 }
                             }
                         }
                     }
-
-                    res_tx
-                        .send(MessageRelations {
-                            relations,
-                            stop_ids,
-                            way_ids,
-                        })
-                        .unwrap();
-                });
-            }
-
-            let f = File::open(pbf_filename).unwrap();
-            let mut reader = BlobReader::new(BufReader::new(f));
-
-            let mut w = 0;
-            for blob in &mut reader {
-                let req_tx = &workers[w].0;
-                w = (w + 1) % cpus;
-                req_tx.send(blob).unwrap();
-            }
+                },
+            );
 
             eprint!("reduce, ");
             io::stderr().flush().unwrap();
             // reduce / join all data from workers into one structure
-            {
-                // write lock
-                let mut node_ids_write = node_ids.write().unwrap();
-                let mut way_ids_write = way_ids.write().unwrap();
-                for (req_tx, res_rx) in workers.into_iter() {
-                    drop(req_tx);
-                    let worker_data = res_rx.recv().unwrap();
-                    relations.extend(worker_data.relations);
-                    node_ids_write.extend(worker_data.stop_ids);
-                    way_ids_write.extend(worker_data.way_ids);
-                }
-            } // write lock
+            for worker_data in results {
+                relations.extend(worker_data.relations);
+                node_ids.extend(worker_data.stop_ids);
+                way_ids.extend(worker_data.way_ids);
+            }
             eprintln!("found {}", relations.len());
         }
 
@@ -304,125 +326,86 @@ impl Parser {
         {
             eprint!("START Ways map, ");
             io::stderr().flush().unwrap();
-            let mut workers = Vec::with_capacity(cpus);
-            for _ in 0..cpus {
-                let (req_tx, req_rx) = sync_channel(2);
-                let (res_tx, res_rx) = sync_channel(0);
-                workers.push((req_tx, res_rx));
-                let way_ids_local = way_ids.clone();
-                let filters_local = filters_arc.clone();
-                thread::spawn(move || {
-                    let mut ways = Vec::new() as Vec<WayData>;
-                    let mut relations_ways = HashMap::default() as HashMap<u64, WayData>;
-                    let mut node_ids = HashSet::default() as NodeIdsSet;
-                    let way_ids_read = way_ids_local.read().unwrap();
-                    while let Ok(blob) = req_rx.recv() {
-                        let blob = (blob as Blob).into_data();
-                        let primitive_block = PrimitiveBlock::parse(&blob);
-                        for primitive in primitive_block.primitives() {
-                            if let Primitive::Way(way) = primitive {
-                                // relations_ways, collect ways that are part of relations previously found
-                                if way_ids_read.contains(&way.id) {
-                                    for node in way.refs() {
-                                        node_ids.insert(node as u64);
-                                    }
-                                    relations_ways.insert(
-                                        way.id,
-                                        WayData {
-                                            id: way.id,
-                                            tags: way
-                                                .tags()
-                                                .map(|t| (t.0.to_string(), t.1.to_string()))
-                                                .collect(),
-                                            info: HashMap::new(),
-                                            nodes: way.refs().map(|id| id as u64).collect(),
-                                        },
-                                    );
+            let way_ids_read = Arc::new(way_ids);
+            let filters_local = filters_arc.clone();
+            let results = map_blocks(
+                pbf_filename,
+                cpus,
+                move |primitive_block, result: &mut MessageWays| {
+                    for primitive in primitive_block.primitives() {
+                        if let Primitive::Way(way) = primitive {
+                            // relations_ways, collect ways that are part of relations previously found
+                            if way_ids_read.contains(&way.id) {
+                                for node in way.refs() {
+                                    result.node_ids.insert(node as u64);
                                 }
-                                // ways, collect ways that are not part of relations previously found but conform to filters
-                                if Self::filter_way(&way, filters_local.as_ref()) {
-                                    let mut info: HashMap<String, String> = HashMap::new();
-                                    if let Some(info_data) = way.info.clone() {
-                                        if let Some(version) = info_data.version {
-                                            info.insert("version".to_string(), version.to_string());
-                                        }
-                                        if let Some(timestamp) = info_data.timestamp {
-                                            info.insert(
-                                                "timestamp".to_string(),
-                                                timestamp.to_string(),
-                                            );
-                                        }
-                                        if let Some(changeset) = info_data.changeset {
-                                            info.insert(
-                                                "changeset".to_string(),
-                                                changeset.to_string(),
-                                            );
-                                        }
-                                        if let Some(uid) = info_data.uid {
-                                            info.insert("uid".to_string(), uid.to_string());
-                                        }
-                                        if let Some(user) = info_data.user {
-                                            info.insert("user".to_string(), user.to_string());
-                                        }
-                                        if let Some(visible) = info_data.visible {
-                                            info.insert("visible".to_string(), visible.to_string());
-                                        }
-                                    }
-                                    let wd = WayData {
+                                result.relations_ways.insert(
+                                    way.id,
+                                    WayData {
                                         id: way.id,
                                         tags: way
                                             .tags()
                                             .map(|t| (t.0.to_string(), t.1.to_string()))
                                             .collect(),
-                                        info,
+                                        info: HashMap::new(),
                                         nodes: way.refs().map(|id| id as u64).collect(),
-                                    };
-                                    if !wd.nodes.is_empty() {
-                                        for node in way.refs() {
-                                            node_ids.insert(node as u64);
-                                        }
-                                        ways.push(wd);
-                                    } else {
-                                        // println!("WARNING: way has no nodes 'https://www.openstreetmap.org/way/{:?}'", way.id);
+                                    },
+                                );
+                            }
+                            // ways, collect ways that are not part of relations previously found but conform to filters
+                            if Self::filter_way(&way, filters_local.as_ref()) {
+                                let mut info: HashMap<String, String> = HashMap::new();
+                                if let Some(info_data) = way.info.clone() {
+                                    if let Some(version) = info_data.version {
+                                        info.insert("version".to_string(), version.to_string());
+                                    }
+                                    if let Some(timestamp) = info_data.timestamp {
+                                        info.insert("timestamp".to_string(), timestamp.to_string());
+                                    }
+                                    if let Some(changeset) = info_data.changeset {
+                                        info.insert("changeset".to_string(), changeset.to_string());
+                                    }
+                                    if let Some(uid) = info_data.uid {
+                                        info.insert("uid".to_string(), uid.to_string());
+                                    }
+                                    if let Some(user) = info_data.user {
+                                        info.insert("user".to_string(), user.to_string());
+                                    }
+                                    if let Some(visible) = info_data.visible {
+                                        info.insert("visible".to_string(), visible.to_string());
+                                    }
+                                }
+                                let wd = WayData {
+                                    id: way.id,
+                                    tags: way
+                                        .tags()
+                                        .map(|t| (t.0.to_string(), t.1.to_string()))
+                                        .collect(),
+                                    info,
+                                    nodes: way.refs().map(|id| id as u64).collect(),
+                                };
+                                if !wd.nodes.is_empty() {
+                                    for node in way.refs() {
+                                        result.node_ids.insert(node as u64);
                                     }
+                                    result.ways.push(wd);
+                                } else {
+                                    // println!("WARNING: way has no nodes 'https://www.openstreetmap.org/way/{:?}'", way.id);
                                 }
                             }
                         }
                     }
-
-                    res_tx
-                        .send(MessageWays {
-                            ways,
-                            relations_ways,
-                            node_ids,
-                        })
-                        .unwrap();
-                });
-            }
-
-            let f = File::open(pbf_filename).unwrap();
-            let mut reader = BlobReader::new(BufReader::new(f));
-
-            let mut w = 0;
-            for blob in &mut reader {
-                let req_tx = &workers[w].0;
-                w = (w + 1) % cpus;
-                req_tx.send(blob).unwrap();
-            }
+                },
+            );
 
             eprint!("reduce, ");
             io::stderr().flush().unwrap();
             // reduce / join all data from workers into one structure
-            {
-                let mut node_ids_write = node_ids.write().unwrap();
-                for (req_tx, res_rx) in workers.into_iter() {
-                    drop(req_tx);
-                    let worker_data = res_rx.recv().unwrap();
-                    ways.extend(worker_data.ways);
-                    relations_ways.extend(worker_data.relations_ways);
-                    node_ids_write.extend(worker_data.node_ids);
-                }
-            } // write lock
+            for worker_data in results {
+                ways.extend(worker_data.ways);
+                relations_ways.extend(worker_data.relations_ways);
+                node_ids.extend(worker_data.node_ids);
+            }
             eprintln!(
                 "found {} for relations +{} new",
                 relations_ways.len(),
@@ -436,64 +419,39 @@ impl Parser {
         {
             eprint!("START Nodes map, ");
             io::stderr().flush().unwrap();
-            let mut workers = Vec::with_capacity(cpus);
-            for _ in 0..cpus {
-                let (req_tx, req_rx) = sync_channel(2);
-                let (res_tx, res_rx) = sync_channel(0);
-                workers.push((req_tx, res_rx));
-                let node_ids_local = node_ids.clone();
-                thread::spawn(move || {
-                    // nodes_parser_worker(req_rx, res_tx);
-                    let node_ids_read = node_ids_local.read().unwrap();
-                    let mut nodes = HashMap::default() as HashMap<u64, NodeData>;
-                    while let Ok(blob) = req_rx.recv() {
-                        let blob = (blob as Blob).into_data();
-                        let primitive_block = PrimitiveBlock::parse(&blob);
-                        for primitive in primitive_block.primitives() {
-                            if let Primitive::Node(node) = primitive {
-                                if node_ids_read.contains(&node.id) {
-                                    nodes.insert(
-                                        node.id,
-                                        NodeData {
-                                            // id: node.id,
-                                            tags: node
-                                                .tags
-                                                .into_iter()
-                                                .map(|t| (t.0.to_string(), t.1.to_string()))
-                                                .collect(),
-                                            lat: node.lat,
-                                            lon: node.lon,
-                                        },
-                                    );
-                                }
+            let node_ids_read = Arc::new(node_ids);
+            let results = map_blocks(
+                pbf_filename,
+                cpus,
+                move |primitive_block, result: &mut MessageNodes| {
+                    for primitive in primitive_block.primitives() {
+                        if let Primitive::Node(node) = primitive {
+                            if node_ids_read.contains(&node.id) {
+                                result.nodes.insert(
+                                    node.id,
+                                    NodeData {
+                                        // id: node.id,
+                                        tags: node
+                                            .tags
+                                            .into_iter()
+                                            .map(|t| (t.0.to_string(), t.1.to_string()))
+                                            .collect(),
+                                        lat: node.lat,
+                                        lon: node.lon,
+                                    },
+                                );
                             }
                         }
                     }
-
-                    res_tx.send(MessageNodes { nodes }).unwrap();
-                });
-            }
-
-            let f = File::open(pbf_filename).unwrap();
-            let mut reader = BlobReader::new(BufReader::new(f));
-
-            let mut w = 0;
-            for blob in &mut reader {
-                let req_tx = &workers[w].0;
-                w = (w + 1) % cpus;
-                req_tx.send(blob).unwrap();
-            }
+                },
+            );
 
             eprint!("reduce, ");
             io::stderr().flush().unwrap();
             // reduce / join all data from workers into one structure
-            {
-                for (req_tx, res_rx) in workers.into_iter() {
-                    drop(req_tx);
-                    let worker_data = res_rx.recv().unwrap();
-                    nodes.extend(worker_data.nodes);
-                }
-            } // write lock
+            for worker_data in results {
+                nodes.extend(worker_data.nodes);
+            }
         } // local vars block
         eprintln!("found {}", nodes.len());
 
@@ -503,29 +461,112 @@ impl Parser {
             ways,
             nodes,
             cpus,
+            gap_fill: None,
         }
     }
 
     /// Builds a vector in parallel with all the public transport ways normalized and "fixed".
     /// It works in parallel using the same amount of threads that were configured on new()
     pub fn get_public_transports(&self, gap: f64) -> Vec<PublicTransport> {
-        self.par_map(&move |r| {
-            // println!("{:?}",r.id);
-            let (f, s) = r.flatten_ways(gap, false).unwrap();
-            PublicTransport {
-                id: r.id,
-                tags: r.tags.clone(),
-                info: r.info.clone(),
-                stops: r.stops,
-                geometry: f
-                    .iter()
-                    .map(|v| v.iter().map(|n| (n.lon, n.lat)).collect())
-                    .collect(),
-                parse_status: s,
-            }
+        let mut pts = Vec::with_capacity(self.relations.len());
+        self.for_each_public_transport(gap, |pt| pts.push(pt));
+        pts
+    }
+
+    /// Same as get_public_transports() but each public transport is passed to `consume` as soon as it is ready,
+    /// so they can be streamed (to a writer for example) without keeping all of them in memory
+    pub fn for_each_public_transport<C>(&self, gap: f64, consume: C)
+    where
+        C: FnMut(PublicTransport),
+    {
+        self.public_transports(gap, false, consume)
+    }
+
+    /// Same as get_public_transports() but the broken ones (status 501) keep their best partial geometry
+    /// as a multilinestring, see Relation::flatten_ways_partial()
+    pub fn get_public_transports_partial(&self, gap: f64) -> Vec<PublicTransport> {
+        let mut pts = Vec::with_capacity(self.relations.len());
+        self.for_each_public_transport_partial(gap, |pt| pts.push(pt));
+        pts
+    }
+
+    /// Same as for_each_public_transport() but keeping the partial geometry of the broken ones
+    pub fn for_each_public_transport_partial<C>(&self, gap: f64, consume: C)
+    where
+        C: FnMut(PublicTransport),
+    {
+        self.public_transports(gap, true, consume)
+    }
+
+    /// Bounding box (min_lon, min_lat, max_lon, max_lat) of the nodes in the internal cache, None if there are none
+    pub fn bbox(&self) -> Option<(f64, f64, f64, f64)> {
+        self.nodes.values().fold(None, |acc, node| match acc {
+            None => Some((node.lon, node.lat, node.lon, node.lat)),
+            Some((minx, miny, maxx, maxy)) => Some((
+                minx.min(node.lon),
+                miny.min(node.lat),
+                maxx.max(node.lon),
+                maxy.max(node.lat),
+            )),
         })
     }
 
+    /// Fill the gaps of the public transports with the shortest path over a road graph (see Relation::fill_gaps()),
+    /// `None` to stop filling them
+    pub fn set_gap_fill(&mut self, gap_fill: Option<GapFill>) {
+        self.gap_fill = gap_fill;
+    }
+
+    fn public_transports<C>(&self, gap: f64, partial: bool, consume: C)
+    where
+        C: FnMut(PublicTransport),
+    {
+        let gap_fill = self.gap_fill.clone();
+        self.par_map_each(
+            &move |mut r| {
+                // println!("{:?}",r.id);
+                let filled = match &gap_fill {
+                    Some(gap_fill) => r.fill_gaps(gap_fill, gap),
+                    None => Vec::new(),
+                };
+                let (f, mut s) = r.flatten_route(gap, partial).unwrap();
+                s.issues.extend(filled);
+                let (schedule, schedule_issues) = Schedule::from_tags(&r.tags);
+                s.issues.extend(schedule_issues);
+                let segment_ways = r.segment_ways(&f);
+                let way_tags = r
+                    .ways
+                    .iter()
+                    .filter(|w| !w.tags.is_empty())
+                    .map(|w| (w.id, w.tags.clone()))
+                    .collect();
+                let way_nodes = r
+                    .ways
+                    .iter()
+                    .flat_map(|w| w.nodes.iter().map(|n| n.id))
+                    .collect();
+                PublicTransport {
+                    id: r.id,
+                    tags: r.tags.clone(),
+                    info: r.info.clone(),
+                    stops: r.stops,
+                    members: r.members,
+                    geometry: f
+                        .iter()
+                        .map(|v| v.iter().map(|n| (n.lon, n.lat)).collect())
+                        .collect(),
+                    parse_status: s,
+                    admin: None,
+                    segment_ways,
+                    way_tags,
+                    way_nodes,
+                    schedule,
+                }
+            },
+            consume,
+        )
+    }
+
     /// Iterates in parallel through the cache of public transports and
     /// offers the possibility to further process them with the `func` function being executed in parallel
     /// It works in parallel using the same amount of threads that were configured on new()
@@ -533,6 +574,19 @@ impl Parser {
     where
         F: Fn(Relation) -> R + Sync + Send + Clone + 'static,
         R: Send + 'static,
+    {
+        let mut relations = Vec::with_capacity(self.relations.len());
+        self.par_map_each(func, |r| relations.push(r));
+        relations
+    }
+
+    /// Same as par_map() but instead of collecting the results in a vector,
+    /// each one is passed to `consume` (in the calling thread) as soon as a worker finishes it
+    pub fn par_map_each<R, F, C>(&self, func: &F, mut consume: C)
+    where
+        F: Fn(Relation) -> R + Sync + Send + Clone + 'static,
+        R: Send + 'static,
+        C: FnMut(R),
     {
         let cpus = self.cpus;
         let length = self.relations.len();
@@ -558,19 +612,17 @@ impl Parser {
                 });
             }
 
-            // reduce / join all data from workers into one structure
-            let mut relations = Vec::with_capacity(length);
+            // reduce / consume all data from workers
             let mut errors = 0;
             while errors < cpus {
                 errors = 0;
                 for res_rx in workers.iter() {
                     match res_rx.recv() {
-                        Ok(worker_data) => relations.push(worker_data),
+                        Ok(worker_data) => consume(worker_data),
                         Err(_) => errors += 1,
                     };
                 }
             }
-            relations
         })
         .unwrap()
     }
@@ -578,27 +630,42 @@ impl Parser {
     /// Builds a vector in parallel with all the areas normalized and "fixed".
     /// It works in parallel using the same amount of threads that were configured on new()
     pub fn get_areas(&self, gap: f64) -> Vec<Area> {
-        let relations_ways = self.par_map(&move |r| {
-            let (f, s) = r.flatten_ways(gap, true).unwrap();
-            Area {
-                id: r.id,
-                id_type: 'r',
-                tags: r.tags,
-                info: r.info,
-                geometry: f
-                    .iter()
-                    .map(|v| v.iter().map(|n| (n.lon, n.lat)).collect())
-                    .collect(),
-                parse_status: s,
-            }
-        });
+        let mut areas = Vec::with_capacity(self.relations.len() + self.ways.len());
+        self.for_each_area(gap, |area| areas.push(area));
+        areas
+    }
+
+    /// Same as get_areas() but each area is passed to `consume` as soon as it is ready,
+    /// so they can be streamed (to a writer for example) without keeping all of them in memory
+    pub fn for_each_area<C>(&self, gap: f64, mut consume: C)
+    where
+        C: FnMut(Area),
+    {
+        self.par_map_each(
+            &move |r| {
+                let (f, s) = r.flatten_ways(gap, true).unwrap();
+                Area {
+                    id: r.id,
+                    id_type: 'r',
+                    tags: r.tags,
+                    info: r.info,
+                    geometry: f
+                        .iter()
+                        .map(|v| v.iter().map(|n| (n.lon, n.lat)).collect())
+                        .collect(),
+                    parse_status: s,
+                    parent_id: None,
+                }
+            },
+            &mut consume,
+        );
 
-        // iterates over all ways (par_map_ways) and creates a vector of areas
+        // iterates over all ways (par_map_ways) and creates the areas
         let cpus = self.cpus;
         let length = self.ways.len();
         let mut workers = Vec::with_capacity(cpus);
         let index = Arc::new(RwLock::new(AtomicUsize::new(0)));
-        let areas_ways = crossbeam::scope(|s| {
+        crossbeam::scope(|s| {
             for _ in 0..cpus {
                 let (res_tx, res_rx) = sync_channel(200);
                 workers.push(res_rx);
@@ -624,33 +691,26 @@ impl Parser {
                             .map(|v| v.iter().map(|n| (n.lon, n.lat)).collect())
                             .collect(),
                         parse_status: s,
+                        parent_id: None,
                     };
                     // println!("{:?}", area);
                     res_tx.send(area).unwrap();
                 });
             }
 
-            // reduce / join all data from workers into one structure
-            let mut ways = Vec::with_capacity(length);
+            // reduce / consume all data from workers
             let mut errors = 0;
             while errors < cpus {
                 errors = 0;
                 for res_rx in workers.iter() {
                     match res_rx.recv() {
-                        Ok(worker_data) => ways.push(worker_data),
+                        Ok(worker_data) => consume(worker_data),
                         Err(_) => errors += 1,
                     };
                 }
             }
-            ways
         })
         .unwrap();
-
-        // returns relations_ways + areas_ways
-        relations_ways
-            .into_iter()
-            .chain(areas_ways.into_iter())
-            .collect()
     }
 
     /// Builds the Relation from the provided osm_id `id`
@@ -662,6 +722,20 @@ impl Parser {
 
     /// Builds the Relation providing relationdata internal cache
     fn get_relation_from(&self, relation_data: &RelationData) -> Relation {
+        let mut missing = MissingMembers::default();
+        let mut node_ids = relation_data.stops.clone();
+        for wid in &relation_data.ways {
+            match self.relations_ways.get(wid) {
+                Some(way) => node_ids.extend(&way.nodes),
+                None => missing.ways.push(*wid),
+            }
+        }
+        let mut seen = HashSet::new();
+        for nid in node_ids {
+            if !self.nodes.contains_key(&nid) && seen.insert(nid) {
+                missing.nodes.push(nid);
+            }
+        }
         Relation {
             id: relation_data.id,
             tags: relation_data.tags.clone(),
@@ -686,6 +760,8 @@ impl Parser {
                         })
                         .collect(),
                 })
+                // the nodes of a way without any node in the file are already in missing
+                .filter(|way| !way.nodes.is_empty())
                 .collect(),
             stops: relation_data
                 .stops
@@ -698,6 +774,8 @@ impl Parser {
                     lon: self.nodes[nid].lon,
                 })
                 .collect(),
+            members: relation_data.members.clone(),
+            missing,
         }
     }
 
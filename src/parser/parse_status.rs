@@ -1,23 +1,322 @@
-/// Public transport simple model
+use serde_json::{json, Value};
+use std::fmt;
+
+use super::relation::{LonLat, MissingMembers};
+
+/// Overall result of building the geometry of a relation / way
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseStatusKind {
+    /// ways formed a continuous linestring as they are
+    Ok,
+    /// ways had to be reordered to form a continuous linestring
+    Sorted,
+    /// gaps shorter than the tolerance had to be joined
+    Joined,
+    /// ways had to be reordered and gaps joined
+    JoinedSorted,
+    /// no continuous linestring could be built, geometry is empty
+    Broken,
+}
+
+impl ParseStatusKind {
+    /// numeric code of the status, the same values used before the kinds existed
+    pub fn code(self) -> u64 {
+        match self {
+            ParseStatusKind::Ok => 0,
+            ParseStatusKind::Sorted => 101,
+            ParseStatusKind::Joined => 102,
+            ParseStatusKind::JoinedSorted => 103,
+            ParseStatusKind::Broken => 501,
+        }
+    }
+
+    pub fn detail(self) -> &'static str {
+        match self {
+            ParseStatusKind::Ok => "",
+            ParseStatusKind::Sorted => "Sorted",
+            ParseStatusKind::Joined => "Joined",
+            ParseStatusKind::JoinedSorted => "Joined Sorted",
+            ParseStatusKind::Broken => "Broken",
+        }
+    }
+}
+
+/// Space between the end of one way and the start of the next one
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gap {
+    pub from_way: u64,
+    pub from_node: u64,
+    pub to_way: u64,
+    pub to_node: u64,
+    /// haversine distance between both nodes, in meters
+    pub distance: f64,
+    /// middle point between both nodes
+    pub location: LonLat,
+}
+
+/// A workaround applied or a problem found while building the geometry
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseIssue {
+    /// the relation is incomplete, some of its members are not in the pbf file
+    MissingMembers(MissingMembers),
+    /// there are no ways to build the geometry from
+    NoWays,
+    /// ways were reordered, `way_ids` is the new order
+    WaysSorted { way_ids: Vec<u64> },
+    /// gap shorter than the tolerance, the ways were joined
+    GapJoined(Gap),
+    /// gap longer than the tolerance, the ways could not be joined
+    Gap(Gap),
+    /// ring was not closed but its ends were within the tolerance, so it was closed
+    RingClosed(Gap),
+    /// ring is not closed and its ends are farther than the tolerance
+    RingNotClosed(Gap),
+    /// gap filled with the shortest path of `length` meters over the road graph, traveling `way_ids`
+    /// (ways that are not members of the relation)
+    GapFilled {
+        gap: Gap,
+        way_ids: Vec<u64>,
+        length: f64,
+    },
+    /// the geometry was reversed to follow the direction of travel of the route
+    Reversed,
+    /// the route travels way `way_id` (from node `from_node` to `to_node`) against its oneway direction
+    AgainstOneway {
+        way_id: u64,
+        from_node: u64,
+        to_node: u64,
+    },
+    /// the service information tag `key` (interval, interval:conditional, duration or opening_hours)
+    /// could not be parsed, see Schedule
+    InvalidSchedule {
+        key: String,
+        value: String,
+        message: String,
+    },
+}
+
+impl ParseIssue {
+    /// short name of the issue, as used in the json output
+    pub fn name(&self) -> &'static str {
+        match self {
+            ParseIssue::MissingMembers(_) => "missing_members",
+            ParseIssue::NoWays => "no_ways",
+            ParseIssue::WaysSorted { .. } => "ways_sorted",
+            ParseIssue::GapJoined(_) => "gap_joined",
+            ParseIssue::Gap(_) => "gap",
+            ParseIssue::RingClosed(_) => "ring_closed",
+            ParseIssue::RingNotClosed(_) => "ring_not_closed",
+            ParseIssue::GapFilled { .. } => "gap_filled",
+            ParseIssue::Reversed => "reversed",
+            ParseIssue::AgainstOneway { .. } => "against_oneway",
+            ParseIssue::InvalidSchedule { .. } => "invalid_schedule",
+        }
+    }
+
+    /// the gap of the issue, if it has one
+    pub fn gap(&self) -> Option<&Gap> {
+        match self {
+            ParseIssue::GapJoined(gap)
+            | ParseIssue::Gap(gap)
+            | ParseIssue::RingClosed(gap)
+            | ParseIssue::RingNotClosed(gap)
+            | ParseIssue::GapFilled { gap, .. } => Some(gap),
+            ParseIssue::MissingMembers(_)
+            | ParseIssue::NoWays
+            | ParseIssue::WaysSorted { .. }
+            | ParseIssue::Reversed
+            | ParseIssue::AgainstOneway { .. }
+            | ParseIssue::InvalidSchedule { .. } => None,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        match self {
+            ParseIssue::MissingMembers(missing) => json!({
+                "type": self.name(),
+                "ways": missing.ways,
+                "nodes": missing.nodes,
+            }),
+            ParseIssue::NoWays | ParseIssue::Reversed => json!({ "type": self.name() }),
+            ParseIssue::AgainstOneway {
+                way_id,
+                from_node,
+                to_node,
+            } => json!({
+                "type": self.name(),
+                "way_id": way_id,
+                "from_node": from_node,
+                "to_node": to_node,
+            }),
+            ParseIssue::InvalidSchedule {
+                key,
+                value,
+                message,
+            } => json!({
+                "type": self.name(),
+                "key": key,
+                "value": value,
+                "message": message,
+            }),
+            ParseIssue::WaysSorted { way_ids } => json!({
+                "type": self.name(),
+                "way_ids": way_ids,
+            }),
+            ParseIssue::GapFilled {
+                gap,
+                way_ids,
+                length,
+            } => json!({
+                "type": self.name(),
+                "from_way": gap.from_way,
+                "from_node": gap.from_node,
+                "to_way": gap.to_way,
+                "to_node": gap.to_node,
+                "distance": gap.distance,
+                "location": [gap.location.0, gap.location.1],
+                "way_ids": way_ids,
+                "length": length,
+            }),
+            ParseIssue::GapJoined(gap)
+            | ParseIssue::Gap(gap)
+            | ParseIssue::RingClosed(gap)
+            | ParseIssue::RingNotClosed(gap) => json!({
+                "type": self.name(),
+                "from_way": gap.from_way,
+                "from_node": gap.from_node,
+                "to_way": gap.to_way,
+                "to_node": gap.to_node,
+                "distance": gap.distance,
+                "location": [gap.location.0, gap.location.1],
+            }),
+        }
+    }
+}
+
+impl fmt::Display for ParseIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.gap() {
+            Some(gap) if !matches!(self, ParseIssue::GapFilled { .. }) => write!(
+                f,
+                "{} of {:.1}m between way {} (node {}) and way {} (node {}) at {},{}",
+                self.name(),
+                gap.distance,
+                gap.from_way,
+                gap.from_node,
+                gap.to_way,
+                gap.to_node,
+                gap.location.1,
+                gap.location.0
+            ),
+            _ => match self {
+                ParseIssue::MissingMembers(missing) => write!(
+                    f,
+                    "{}: ways {:?}, nodes {:?}",
+                    self.name(),
+                    missing.ways,
+                    missing.nodes
+                ),
+                ParseIssue::WaysSorted { way_ids } => {
+                    write!(f, "{} to {:?}", self.name(), way_ids)
+                }
+                ParseIssue::GapFilled {
+                    gap,
+                    way_ids,
+                    length,
+                } => write!(
+                    f,
+                    "{} of {:.1}m between way {} (node {}) and way {} (node {}) with {:.1}m of ways {:?}",
+                    self.name(),
+                    gap.distance,
+                    gap.from_way,
+                    gap.from_node,
+                    gap.to_way,
+                    gap.to_node,
+                    length,
+                    way_ids
+                ),
+                ParseIssue::AgainstOneway {
+                    way_id,
+                    from_node,
+                    to_node,
+                } => write!(
+                    f,
+                    "{} on way {} from node {} to node {}",
+                    self.name(),
+                    way_id,
+                    from_node,
+                    to_node
+                ),
+                ParseIssue::InvalidSchedule {
+                    key,
+                    value,
+                    message,
+                } => write!(f, "{} {}={}: {}", self.name(), key, value, message),
+                _ => write!(f, "{}", self.name()),
+            },
+        }
+    }
+}
+
+/// Result of building the geometry, with every workaround applied and every problem found
 #[derive(Clone, Debug, PartialEq)]
 pub struct ParseStatus {
-    /// status code
-    pub code: u64,
-    /// details
-    pub detail: String,
+    pub kind: ParseStatusKind,
+    pub issues: Vec<ParseIssue>,
 }
 
 impl ParseStatus {
-    pub fn new(code: u64, detail: &str) -> Self {
-        ParseStatus {
-            code,
-            detail: detail.to_string(),
-        }
+    pub fn new(kind: ParseStatusKind, issues: Vec<ParseIssue>) -> Self {
+        ParseStatus { kind, issues }
     }
     pub fn ok() -> Self {
         ParseStatus {
-            code: 0,
-            detail: "".to_string(),
+            kind: ParseStatusKind::Ok,
+            issues: Vec::new(),
         }
     }
+
+    /// status code (0 ok, 101 sorted, 102 joined, 103 joined sorted, 501 broken)
+    pub fn code(&self) -> u64 {
+        self.kind.code()
+    }
+
+    /// status code description
+    pub fn detail(&self) -> &'static str {
+        self.kind.detail()
+    }
+
+    /// true if the relation has members that are not in the pbf file
+    pub fn is_incomplete(&self) -> bool {
+        self.issues
+            .iter()
+            .any(|i| matches!(i, ParseIssue::MissingMembers(_)))
+    }
+
+    fn gaps(&self) -> impl Iterator<Item = &Gap> {
+        self.issues.iter().filter_map(|i| match i {
+            ParseIssue::Gap(gap) => Some(gap),
+            _ => None,
+        })
+    }
+
+    /// number of gaps that could not be joined, the parts of a partial geometry are one more than this
+    pub fn gap_count(&self) -> usize {
+        self.gaps().count()
+    }
+
+    /// sum of the distances of the gaps that could not be joined, in meters
+    pub fn gap_length(&self) -> f64 {
+        self.gaps().map(|g| g.distance).sum()
+    }
+
+    /// middle point of each gap that could not be joined
+    pub fn gap_locations(&self) -> Vec<LonLat> {
+        self.gaps().map(|g| g.location).collect()
+    }
+
+    /// issues as a json list
+    pub fn issues_json(&self) -> Value {
+        Value::Array(self.issues.iter().map(|i| i.to_json()).collect())
+    }
 }
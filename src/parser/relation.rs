@@ -1,9 +1,16 @@
-use serde_json::json;
-use std::collections::HashMap;
-use std::f64::INFINITY;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt;
 
-use super::parse_status::ParseStatus;
+use crate::admin::{admin_json, AdminAssignment};
+use crate::geo::{haversine, length, locate_from, slice};
+use crate::schedule::Schedule;
+
+use super::parse_status::{Gap, ParseIssue, ParseStatus, ParseStatusKind};
+use super::road_graph::GapFill;
+
+/// extra distance to the line (meters) accepted to locate a stop after the previous one instead of at its closest point
+const SEQUENCE_TOLERANCE: f64 = 50_f64;
 
 #[derive(Debug)]
 pub struct ParseError;
@@ -39,6 +46,33 @@ pub struct Way {
     pub nodes: Vec<Node>,
 }
 
+/// OSM member type
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemberType {
+    Node,
+    Way,
+    Relation,
+}
+
+impl MemberType {
+    /// osm name of the type, as used in the osm urls
+    pub fn name(self) -> &'static str {
+        match self {
+            MemberType::Node => "node",
+            MemberType::Way => "way",
+            MemberType::Relation => "relation",
+        }
+    }
+}
+
+/// Member of an OSM relation
+#[derive(Clone, Debug, PartialEq)]
+pub struct Member {
+    pub id: u64,
+    pub member_type: MemberType,
+    pub role: String,
+}
+
 /// OSM relation representation with all the relevant osm data (tags and ids of relation and all ways and nodes)
 #[derive(Clone, Debug)]
 pub struct Relation {
@@ -47,9 +81,28 @@ pub struct Relation {
     pub info: HashMap<String, String>,
     pub ways: Vec<Way>,
     pub stops: Vec<Node>,
+    /// all the members with their roles, in the order of the relation
+    pub members: Vec<Member>,
+    /// members referenced by the relation that are not in the pbf file (e.g. cut at the extract border)
+    pub missing: MissingMembers,
+}
+
+/// Ids of the members of a relation that could not be found, by type
+/// nodes include both node members and nodes of the member ways
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MissingMembers {
+    pub ways: Vec<u64>,
+    pub nodes: Vec<u64>,
 }
 
-type LonLat = (f64, f64);
+impl MissingMembers {
+    pub fn is_empty(&self) -> bool {
+        self.ways.is_empty() && self.nodes.is_empty()
+    }
+}
+
+/// (longitude, latitude) coordinate pair in degrees
+pub type LonLat = (f64, f64);
 
 /// Public transport simple model
 #[derive(Clone, Debug)]
@@ -62,10 +115,50 @@ pub struct PublicTransport {
     pub info: HashMap<String, String>,
     /// stop nodes of the public transport relation with latlng and internal tags
     pub stops: Vec<Node>,
+    /// all the members of the public transport relation with their roles, in the order of the relation
+    pub members: Vec<Member>,
     /// geometry (linestring/multilinestring), best effort fixed
     pub geometry: Vec<Vec<LonLat>>,
     /// parse status, info on workarounds applied when parsing to fix semi-broken osm route
     pub parse_status: ParseStatus,
+    /// administrative areas of the route and its stops, None unless set by AdminAreas::assign()
+    pub admin: Option<AdminAssignment>,
+    /// osm way of each segment of the geometry (see Relation::segment_ways()), in the same shape as `geometry`
+    pub segment_ways: Vec<Vec<Option<u64>>>,
+    /// tags of the member ways (and of the ways that fill its gaps) with tags, by way id
+    pub way_tags: HashMap<u64, HashMap<String, String>>,
+    /// ids of the nodes of the member ways
+    pub way_nodes: HashSet<u64>,
+    /// service information parsed from the tags, the tags that could not be parsed are InvalidSchedule issues
+    pub schedule: Schedule,
+}
+
+/// Position of a stop along the geometry of its public transport (linear referencing)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StopPosition {
+    /// distance in meters from the start of the geometry to the closest point of the line,
+    /// the gaps between parts of a multilinestring are not counted
+    pub distance: f64,
+    /// distance in meters from the stop to the line
+    pub offset: f64,
+}
+
+/// Part of the geometry of a public transport between two consecutive stops of its stop_sequence()
+#[derive(Clone, Debug, PartialEq)]
+pub struct StopSegment {
+    /// node id of the first stop
+    pub from: u64,
+    /// node id of the next stop
+    pub to: u64,
+    /// distance in meters along the geometry of the first and the next stop (StopPosition::distance),
+    /// 0 if the geometry is empty
+    pub start: f64,
+    pub end: f64,
+    /// distance in meters along the geometry between both stops
+    pub length: f64,
+    /// line from the closest point of the geometry to the first stop to the closest point to the next one,
+    /// empty if the geometry is empty
+    pub geometry: Vec<LonLat>,
 }
 
 /// Area simple model
@@ -83,116 +176,317 @@ pub struct Area {
     pub geometry: Vec<Vec<LonLat>>,
     /// parse status, info on workarounds applied when parsing to fix semi-broken osm area
     pub parse_status: ParseStatus,
+    /// osm id of the administrative area that contains this one, None unless set by AdminHierarchy
+    pub parent_id: Option<u64>,
 }
 
-fn pointdistance(p1: &Node, p2: &Node) -> f64 {
-    ((p1.lat - p2.lat).powf(2f64) + (p1.lon - p2.lon).powf(2f64)).sqrt()
+/// ways being merged, with the ids of the osm ways that form it in order
+/// nodes are borrowed from the ways, so segments are cheap to copy around while merging
+#[derive(Clone, Debug)]
+struct Segment<'a> {
+    way_ids: Vec<u64>,
+    nodes: Vec<&'a Node>,
 }
 
-fn edgedistance(w1: &Vec<Node>, w2: &Vec<Node>) -> f64 {
-    let w1p1 = &w1[0];
-    let w1p2 = &w1[w1.len() - 1];
-    let w2p1 = &w2[0];
-    let w2p2 = &w2[w2.len() - 1];
-    [
-        pointdistance(w1p1, w2p1),
-        pointdistance(w1p2, w2p2),
-        pointdistance(w1p1, w2p2),
-        pointdistance(w1p2, w2p1),
-    ]
-    .iter()
-    .fold(-1f64, |a, b| if a < *b { a } else { *b })
+impl<'a> Segment<'a> {
+    fn new(way: &'a Way) -> Self {
+        Segment {
+            way_ids: vec![way.id],
+            nodes: way.nodes.iter().collect(),
+        }
+    }
+
+    fn first(&self) -> &'a Node {
+        self.nodes[0]
+    }
+
+    fn last(&self) -> &'a Node {
+        self.nodes[self.nodes.len() - 1]
+    }
+
+    fn reverse(&mut self) {
+        self.nodes.reverse();
+        self.way_ids.reverse();
+    }
+
+    /// appends `other`, skipping its first node if it is the same as the last one of self
+    fn append(&mut self, other: Segment<'a>, skip_first: bool) {
+        let skip = if skip_first { 1 } else { 0 };
+        self.nodes.extend(other.nodes.into_iter().skip(skip));
+        self.way_ids.extend(other.way_ids);
+    }
+
+    fn to_nodes(&self) -> Vec<Node> {
+        self.nodes.iter().map(|n| (*n).clone()).collect()
+    }
+}
+
+/// gap between node `from` of way `from_way` and node `to` of way `to_way`
+fn gap(from_way: u64, from: &Node, to_way: u64, to: &Node) -> Gap {
+    Gap {
+        from_way,
+        from_node: from.id,
+        to_way,
+        to_node: to.id,
+        distance: dist_haversine(from, to),
+        location: ((from.lon + to.lon) / 2_f64, (from.lat + to.lat) / 2_f64),
+    }
+}
+
+/// gap between the closest extreme points of two segments
+fn closest_gap<'a>(s1: &Segment<'a>, s2: &Segment<'a>) -> Gap {
+    let first = |s: &Segment<'a>| (s.way_ids[0], s.first());
+    let last = |s: &Segment<'a>| (s.way_ids[s.way_ids.len() - 1], s.last());
+    let candidates = [
+        (last(s1), first(s2)),
+        (last(s1), last(s2)),
+        (first(s1), first(s2)),
+        (first(s1), last(s2)),
+    ];
+    candidates
+        .iter()
+        .map(|((w1, n1), (w2, n2))| gap(*w1, n1, *w2, n2))
+        .fold(None, |closest: Option<Gap>, g| match closest {
+            Some(c) if c.distance <= g.distance => Some(c),
+            _ => Some(g),
+        })
+        .unwrap()
 }
 
 /// try to reverse directions in linestrings
 /// to join segments into a single linestring
 /// - This is normal in openstreetmap format
 /// - Also ST_LineMerge() should do this already
-fn first_pass(ways: &Vec<Vec<Node>>) -> Result<Vec<Vec<Node>>, ()> {
+fn first_pass<'a>(ways: &[Segment<'a>]) -> Result<Vec<Segment<'a>>, ()> {
     // try to flatten by joining most ways as possible
-    let n = ways.len();
     let mut ordered_ways = vec![ways[0].clone()];
-    for i in 1..n {
-        let way = ways[i].clone();
-        let mut prev_way = ordered_ways[ordered_ways.len() - 1].clone();
+    // the last ordered segment is the previous way as it was, nothing was joined to it yet
+    let mut fresh = true;
+    for way in ways[1..].iter().cloned() {
+        let prev_way = ordered_ways.last_mut().unwrap();
         // if its the first segment on the linestring,
         // try reversing it if it matches with the second
-        if ordered_ways[ordered_ways.len() - 1] == ways[i - 1]
-            && (way[0] == prev_way[0] || way[way.len() - 1] == prev_way[0])
-        {
-            let mut rev = prev_way.clone();
-            rev.reverse();
-            let lasti = ordered_ways.len() - 1;
-            ordered_ways[lasti] = rev;
-            prev_way = ordered_ways[lasti].clone();
+        if fresh && (way.first() == prev_way.first() || way.last() == prev_way.first()) {
+            prev_way.reverse();
         }
         // concat the second segment with the first one
-        if prev_way[prev_way.len() - 1] == way[0] {
-            let lasti = ordered_ways.len() - 1;
-            let tail = &mut (way.clone()[1..].to_vec());
-            ordered_ways[lasti].append(tail);
+        if prev_way.last() == way.first() {
+            prev_way.append(way, true);
+            fresh = false;
         }
         // concat the second segment reversig it
-        else if prev_way[prev_way.len() - 1] == way[way.len() - 1] {
-            let lasti = ordered_ways.len() - 1;
-            let mut rev = way.clone();
+        else if prev_way.last() == way.last() {
+            let mut rev = way;
             rev.reverse();
-            let tail = &mut (rev[1..].to_vec());
-            ordered_ways[lasti].append(tail);
+            prev_way.append(rev, true);
+            fresh = false;
         }
         // cannot form a single linestring, continue processing
         else {
             ordered_ways.push(way);
+            fresh = true;
         }
     }
 
     Ok(ordered_ways)
 }
 
+/// Index of the extreme points of the segments, to find the closest segment to a point
+/// - by node id, for the segments that share the node (exact joins)
+/// - in a grid of cells, for the closest one by haversine distance (tolerance joins)
+struct EndIndex<'a, 'b> {
+    segments: &'b [Segment<'a>],
+    by_node: HashMap<u64, Vec<usize>>,
+    grid: HashMap<(i64, i64), Vec<usize>>,
+    /// cell size in degrees
+    cell: f64,
+    used: Vec<bool>,
+    remaining: usize,
+}
+
+impl<'a, 'b> EndIndex<'a, 'b> {
+    fn new(segments: &'b [Segment<'a>]) -> Self {
+        // cells of about the mean distance between the extreme points of the segments
+        let span: f64 = segments
+            .iter()
+            .map(|s| (s.first().lon - s.last().lon).hypot(s.first().lat - s.last().lat))
+            .sum();
+        let cell = (span / segments.len() as f64).max(1e-5);
+        let mut index = EndIndex {
+            segments,
+            by_node: HashMap::new(),
+            grid: HashMap::new(),
+            cell,
+            used: vec![false; segments.len()],
+            remaining: segments.len(),
+        };
+        for (i, s) in segments.iter().enumerate() {
+            for n in [s.first(), s.last()] {
+                index.by_node.entry(n.id).or_default().push(i);
+                index.grid.entry(index.cell_of(n)).or_default().push(i);
+            }
+        }
+        index
+    }
+
+    fn cell_of(&self, n: &Node) -> (i64, i64) {
+        (
+            (n.lon / self.cell).floor() as i64,
+            (n.lat / self.cell).floor() as i64,
+        )
+    }
+
+    fn take(&mut self, i: usize) -> Segment<'a> {
+        self.used[i] = true;
+        self.remaining -= 1;
+        self.segments[i].clone()
+    }
+
+    /// closest unused segment to any of the extreme points of `segment`, lowest index on ties
+    fn closest(&self, segment: &Segment) -> Option<usize> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let ends = [segment.first(), segment.last()];
+        let exact = ends
+            .iter()
+            .flat_map(|n| self.by_node.get(&n.id).into_iter().flatten())
+            .filter(|i| !self.used[**i])
+            .min();
+        if exact.is_some() {
+            return exact.cloned();
+        }
+
+        let mut best: Option<(f64, usize)> = None;
+        let consider = |best: &mut Option<(f64, usize)>, n: &Node, i: usize| {
+            let s = &self.segments[i];
+            let d = dist_haversine(n, s.first()).min(dist_haversine(n, s.last()));
+            if best.is_none_or(|(bd, bi)| d < bd || (d == bd && i < bi)) {
+                *best = Some((d, i));
+            }
+        };
+        for n in ends.iter() {
+            let (cx, cy) = self.cell_of(n);
+            // the points outside ring r are at least r cells away
+            let cos_lat = n.lat.to_radians().cos().max(0.01);
+            let cell_meters = self.cell.to_radians() * crate::geo::EARTH_RADIUS * cos_lat;
+            let mut r = 0_i64;
+            loop {
+                // rings bigger than the occupied cells, cheaper to check all the segments
+                if ((2 * r + 1) * (2 * r + 1)) as usize > self.grid.len() {
+                    for i in (0..self.segments.len()).filter(|i| !self.used[*i]) {
+                        consider(&mut best, n, i);
+                    }
+                    break;
+                }
+                for x in cx - r..=cx + r {
+                    for y in cy - r..=cy + r {
+                        if (x - cx).abs() != r && (y - cy).abs() != r {
+                            continue;
+                        }
+                        for i in self.grid.get(&(x, y)).into_iter().flatten() {
+                            if !self.used[*i] {
+                                consider(&mut best, n, *i);
+                            }
+                        }
+                    }
+                }
+                if best.is_some_and(|(d, _)| d <= r as f64 * cell_meters) {
+                    break;
+                }
+                r += 1;
+            }
+        }
+        best.map(|(_, i)| i)
+    }
+}
+
 /// move ways from one place to another to get the closest ones together
 /// the first one is taken as important (the one that sets the direction)
-/// Also joins the way if extreme points are the same
+/// each one is followed by the closest remaining one to any of its extreme points
 /// - This is not "expected" by osm. We are trying to fix the way now
 /// - Nevertheless I think this is also done by ST_LineMerge()
-/// TODO: this can probably be made with some std function like vec.sort(|nodesa, nodesb| edgedistance(nodesa, nodesb))
-fn sort_ways(ways: &Vec<Vec<Node>>) -> Result<Vec<Vec<Node>>, ()> {
-    let mut ws = ways.to_owned();
-    let mut sorted_ways = vec![ws[0].clone()];
-    ws = ws[1..].to_vec();
-    while !ws.is_empty() {
-        let mut mindist = INFINITY;
-        let mut minidx = 0usize;
-        for (i, _) in ws.iter().enumerate() {
-            let w = ws[i].clone();
-            let dist = edgedistance(&w, &sorted_ways[sorted_ways.len() - 1]);
-            if dist < mindist {
-                mindist = dist;
-                minidx = i;
-            }
-        }
-        sorted_ways.push(ws[minidx].clone());
-        ws.remove(minidx);
+fn sort_ways<'a>(ways: &[Segment<'a>]) -> Result<Vec<Segment<'a>>, ()> {
+    let mut index = EndIndex::new(ways);
+    let mut sorted_ways = vec![index.take(0)];
+    while let Some(i) = index.closest(&sorted_ways[sorted_ways.len() - 1]) {
+        sorted_ways.push(index.take(i));
     }
     Ok(sorted_ways)
 }
 
 /// calculate haversine distance between two nodes
 fn dist_haversine(p1: &Node, p2: &Node) -> f64 {
-    let lon1 = p1.lon;
-    let lat1 = p1.lat;
-    let lon2 = p2.lon;
-    let lat2 = p2.lat;
-
-    let radius = 6_371_000_f64; // meters
-    let dlat = (lat2 - lat1).to_radians();
-    let dlon = (lon2 - lon1).to_radians();
-    let a = (dlat / 2_f64).sin() * (dlat / 2_f64).sin()
-        + lat1.to_radians().cos()
-            * lat2.to_radians().cos()
-            * (dlon / 2_f64).sin()
-            * (dlon / 2_f64).sin();
-    let c = 2_f64 * a.sqrt().atan2((1_f64 - a).sqrt());
-    radius * c
+    haversine((p1.lon, p1.lat), (p2.lon, p2.lat))
+}
+
+/// segment being joined by join_ways(), reversing it only flips `reversed`
+/// so joining at its first node does not reverse all the nodes joined so far
+struct Chain<'a> {
+    way_ids: VecDeque<u64>,
+    nodes: VecDeque<&'a Node>,
+    reversed: bool,
+}
+
+impl<'a> Chain<'a> {
+    fn new(segment: Segment<'a>) -> Self {
+        Chain {
+            way_ids: segment.way_ids.into(),
+            nodes: segment.nodes.into(),
+            reversed: false,
+        }
+    }
+
+    fn first(&self) -> (u64, &'a Node) {
+        if self.reversed {
+            (
+                self.way_ids[self.way_ids.len() - 1],
+                self.nodes[self.nodes.len() - 1],
+            )
+        } else {
+            (self.way_ids[0], self.nodes[0])
+        }
+    }
+
+    fn last(&self) -> (u64, &'a Node) {
+        if self.reversed {
+            (self.way_ids[0], self.nodes[0])
+        } else {
+            (
+                self.way_ids[self.way_ids.len() - 1],
+                self.nodes[self.nodes.len() - 1],
+            )
+        }
+    }
+
+    fn reverse(&mut self) {
+        self.reversed = !self.reversed;
+    }
+
+    fn append(&mut self, other: Segment<'a>) {
+        if self.reversed {
+            for id in other.way_ids {
+                self.way_ids.push_front(id);
+            }
+            for n in other.nodes {
+                self.nodes.push_front(n);
+            }
+        } else {
+            self.way_ids.extend(other.way_ids);
+            self.nodes.extend(other.nodes);
+        }
+    }
+
+    fn into_segment(self) -> Segment<'a> {
+        let mut segment = Segment {
+            way_ids: self.way_ids.into(),
+            nodes: self.nodes.into(),
+        };
+        if self.reversed {
+            segment.reverse();
+        }
+        segment
+    }
 }
 
 /// Join adjacent ways that have their extreme points
@@ -200,38 +494,56 @@ fn dist_haversine(p1: &Node, p2: &Node) -> f64 {
 /// - This is not "expected". We are trying to fix the way now
 /// - This is not done by ST_LineMerge()
 /// - I'm not sure if this conserves the direction from first to last
-fn join_ways(ways: &Vec<Vec<Node>>, tolerance: f64) -> Result<Vec<Vec<Node>>, ()> {
-    let mut joined = vec![ways[0].clone()];
-    for w in ways[1..].to_vec() {
-        let joined_len = joined.len();
-        let joinedlast = joined[joined_len - 1].clone();
-        if dist_haversine(&joinedlast[joinedlast.len() - 1], &w[0]) < tolerance {
-            joined[joined_len - 1].extend(w);
-        } else if dist_haversine(&joinedlast[joinedlast.len() - 1], &w[w.len() - 1]) < tolerance {
-            let mut wrev = w.clone();
-            wrev.reverse();
-            joined[joined_len - 1].extend(wrev);
-        } else if dist_haversine(&joinedlast[0], &w[0]) < tolerance {
-            joined[joined_len - 1].reverse();
-            joined[joined_len - 1].extend(w);
-        } else if dist_haversine(&joinedlast[0], &w[w.len() - 1]) < tolerance {
-            joined[joined_len - 1].reverse();
-            let mut wrev = w.clone();
-            wrev.reverse();
-            joined[joined_len - 1].extend(wrev);
+///
+/// Also returns the gaps that were joined (extreme points that are not the same node)
+fn join_ways(ways: Vec<Segment>, tolerance: f64) -> Result<(Vec<Segment>, Vec<Gap>), ()> {
+    let mut ways = ways.into_iter();
+    let mut joined = Vec::new();
+    let mut chain = match ways.next() {
+        Some(first) => Chain::new(first),
+        None => return Ok((joined, Vec::new())),
+    };
+    let mut gaps = Vec::new();
+    for mut w in ways {
+        let ((j_first_way, j_first), (j_last_way, j_last)) = (chain.first(), chain.last());
+        let (w_first, w_last) = (w.first(), w.last());
+        let (w_first_way, w_last_way) = (w.way_ids[0], w.way_ids[w.way_ids.len() - 1]);
+        let joined_gap = if dist_haversine(j_last, w_first) < tolerance {
+            gap(j_last_way, j_last, w_first_way, w_first)
+        } else if dist_haversine(j_last, w_last) < tolerance {
+            w.reverse();
+            gap(j_last_way, j_last, w_last_way, w_last)
+        } else if dist_haversine(j_first, w_first) < tolerance {
+            chain.reverse();
+            gap(j_first_way, j_first, w_first_way, w_first)
+        } else if dist_haversine(j_first, w_last) < tolerance {
+            chain.reverse();
+            w.reverse();
+            gap(j_first_way, j_first, w_last_way, w_last)
         } else {
-            joined.push(w);
+            joined.push(std::mem::replace(&mut chain, Chain::new(w)).into_segment());
+            continue;
+        };
+        chain.append(w);
+        if joined_gap.from_node != joined_gap.to_node {
+            gaps.push(joined_gap);
         }
     }
-    Ok(joined)
+    joined.push(chain.into_segment());
+    Ok((joined, gaps))
 }
 
-fn flatten_ways(
-    ways: &Vec<Vec<Node>>,
+/// if `partial` the parts of a broken merge are returned instead of an empty geometry
+fn flatten_ways<'a>(
+    ways: &[Segment<'a>],
     tolerance: f64,
-) -> Result<(Vec<Vec<Node>>, ParseStatus), ()> {
+    partial: bool,
+) -> Result<(Vec<Segment<'a>>, ParseStatus), ()> {
     if ways.is_empty() {
-        return Ok((Vec::new(), ParseStatus::new(501, "Broken")));
+        return Ok((
+            Vec::new(),
+            ParseStatus::new(ParseStatusKind::Broken, vec![ParseIssue::NoWays]),
+        ));
     }
     let passed = first_pass(ways)?;
     if passed.len() == 1 {
@@ -240,33 +552,180 @@ fn flatten_ways(
     let sorted = sort_ways(&passed)?;
     let sorted_passed = first_pass(&sorted)?;
     if sorted_passed.len() == 1 {
-        return Ok((sorted_passed, ParseStatus::new(101, "Sorted")));
+        let issues = vec![ParseIssue::WaysSorted {
+            way_ids: sorted_passed[0].way_ids.clone(),
+        }];
+        return Ok((
+            sorted_passed,
+            ParseStatus::new(ParseStatusKind::Sorted, issues),
+        ));
     }
-    let joined = join_ways(&passed, tolerance)?;
+    let (joined, gaps) = join_ways(passed, tolerance)?;
     if joined.len() == 1 {
-        return Ok((joined, ParseStatus::new(102, "Joined")));
+        let issues = gaps.into_iter().map(ParseIssue::GapJoined).collect();
+        return Ok((joined, ParseStatus::new(ParseStatusKind::Joined, issues)));
     }
-    let joined_sorted = join_ways(&sorted, tolerance)?;
+    let (joined_sorted, gaps) = join_ways(sorted, tolerance)?;
     if joined_sorted.len() == 1 {
-        return Ok((joined_sorted, ParseStatus::new(103, "Joined Sorted")));
+        let mut issues = vec![ParseIssue::WaysSorted {
+            way_ids: joined_sorted[0].way_ids.clone(),
+        }];
+        issues.extend(gaps.into_iter().map(ParseIssue::GapJoined));
+        return Ok((
+            joined_sorted,
+            ParseStatus::new(ParseStatusKind::JoinedSorted, issues),
+        ));
     }
-    Ok((Vec::new(), ParseStatus::new(501, "Broken")))
+    // keep the merge with less parts, report the gaps that could not be joined between each pair of consecutive parts
+    let best = if joined.len() < joined_sorted.len() {
+        joined
+    } else {
+        joined_sorted
+    };
+    let issues = best
+        .windows(2)
+        .map(|pair| ParseIssue::Gap(closest_gap(&pair[0], &pair[1])))
+        .collect();
+    let parts = if partial { best } else { Vec::new() };
+    Ok((parts, ParseStatus::new(ParseStatusKind::Broken, issues)))
 }
 
 /// assert closedness of a linestring within a tolerance
-/// if it is not closed but in tolerance, close it
-fn close_linestring(way: &Vec<Node>, tolerance: f64) -> Result<(Vec<Node>, ParseStatus), ()> {
-    let mut closed = way.to_owned();
-    let mut closed_status = ParseStatus::ok();
-    if closed[0] == closed[closed.len() - 1] {
-        return Ok((closed, closed_status));
-    }
-    if dist_haversine(&closed[0], &closed[closed.len() - 1]) <= tolerance {
+/// if it is not closed but in tolerance, close it (RingClosed issue)
+/// if it can't be closed the result is empty (RingNotClosed issue)
+fn close_linestring(way: &Segment, tolerance: f64) -> Result<(Vec<Node>, Option<ParseIssue>), ()> {
+    let mut closed = way.to_nodes();
+    let (first, last) = (way.first(), way.last());
+    if first == last {
+        return Ok((closed, None));
+    }
+    let ring_gap = gap(
+        way.way_ids[way.way_ids.len() - 1],
+        last,
+        way.way_ids[0],
+        first,
+    );
+    if ring_gap.distance <= tolerance {
         closed.push(closed[0].clone());
-        closed_status = ParseStatus::new(102, "Joined");
-        return Ok((closed, closed_status));
+        return Ok((closed, Some(ParseIssue::RingClosed(ring_gap))));
+    }
+    Ok((Vec::new(), Some(ParseIssue::RingNotClosed(ring_gap))))
+}
+
+/// flattens the ways and closes each resulting linestring if `closed`
+fn flatten_and_close(
+    ways: &[Segment],
+    tolerance: f64,
+    closed: bool,
+    partial: bool,
+) -> (Vec<Vec<Node>>, ParseStatus) {
+    let (f_ways, f_status) = flatten_ways(ways, tolerance, partial).unwrap();
+
+    // check and close if needed
+    if closed && f_status.kind != ParseStatusKind::Broken {
+        let mut f_ways_closed = Vec::new();
+        let mut f_status_closed = f_status;
+        for w in f_ways {
+            let (w_closed, w_issue) = close_linestring(&w, tolerance).unwrap();
+            let w_kind = match w_issue {
+                None => ParseStatusKind::Ok,
+                Some(ParseIssue::RingClosed(_)) => ParseStatusKind::Joined,
+                Some(_) => ParseStatusKind::Broken,
+            };
+            if w_kind == ParseStatusKind::Broken {
+                f_status_closed.kind = ParseStatusKind::Broken;
+            }
+            if w_kind != ParseStatusKind::Broken && f_status_closed.kind != ParseStatusKind::Broken
+            {
+                f_status_closed.kind = w_kind;
+            }
+            f_status_closed.issues.extend(w_issue);
+            f_ways_closed.push(w_closed);
+        }
+        (f_ways_closed, f_status_closed)
+    } else {
+        (f_ways.iter().map(Segment::to_nodes).collect(), f_status)
+    }
+}
+
+/// position of `point` along a multilinestring, None if it is empty
+fn locate_parts(parts: &[Vec<LonLat>], point: LonLat) -> Option<StopPosition> {
+    locate_parts_from(parts, point, 0_f64)
+}
+
+/// position of `point` along a multilinestring at `from` meters or more from its start,
+/// None if it is empty or shorter than `from`
+fn locate_parts_from(parts: &[Vec<LonLat>], point: LonLat, from: f64) -> Option<StopPosition> {
+    let mut start = 0_f64;
+    let mut best: Option<StopPosition> = None;
+    for part in parts {
+        let part_length = length(part);
+        if start + part_length >= from {
+            if let Some((distance, offset)) = locate_from(part, point, (from - start).max(0_f64)) {
+                if best.is_none_or(|b| offset < b.offset) {
+                    best = Some(StopPosition {
+                        distance: start + distance,
+                        offset,
+                    });
+                }
+            }
+        }
+        start += part_length;
+    }
+    best
+}
+
+/// positions of `points` along a multilinestring, in order: each point is located after the previous one
+/// unless the closest point of the whole line is more than SEQUENCE_TOLERANCE meters closer to it,
+/// so lines that pass twice by the same place are followed in order
+fn locate_sequence<I>(parts: &[Vec<LonLat>], points: I) -> Vec<Option<StopPosition>>
+where
+    I: IntoIterator<Item = LonLat>,
+{
+    let mut from = 0_f64;
+    points
+        .into_iter()
+        .map(|point| {
+            let closest = locate_parts(parts, point)?;
+            match locate_parts_from(parts, point, from) {
+                Some(next) if next.offset <= closest.offset + SEQUENCE_TOLERANCE => {
+                    from = next.distance;
+                    Some(next)
+                }
+                _ => Some(closest),
+            }
+        })
+        .collect()
+}
+
+fn reverse_parts(parts: &mut [Vec<Node>]) {
+    parts.reverse();
+    for part in parts.iter_mut() {
+        part.reverse();
+    }
+}
+
+/// direction in which vehicles of type `route` can travel a way with `tags`,
+/// Some(true) only forward, Some(false) only backward, None both ways
+/// `oneway:<route>` is used first, then `oneway:psv` and then `oneway`
+pub(super) fn oneway(tags: &HashMap<String, String>, route: Option<&str>) -> Option<bool> {
+    let keys = [
+        route.map(|r| format!("oneway:{}", r)),
+        Some("oneway:psv".to_string()),
+        Some("oneway".to_string()),
+    ];
+    let value = keys
+        .iter()
+        .flatten()
+        .find_map(|key| tags.get(key))
+        .map(String::as_str);
+    match value {
+        Some("yes") | Some("true") | Some("1") => Some(true),
+        Some("-1") | Some("reverse") => Some(false),
+        Some(_) => None,
+        None if tags.get("junction").map(String::as_str) == Some("roundabout") => Some(true),
+        None => None,
     }
-    Ok((Vec::new(), ParseStatus::new(501, "Broken")))
 }
 
 impl Relation {
@@ -279,27 +738,236 @@ impl Relation {
         tolerance: f64,
         closed: bool,
     ) -> Result<(Vec<Vec<Node>>, ParseStatus), ParseError> {
-        let ways: Vec<Vec<Node>> = self.ways.iter().map(|w| w.nodes.clone()).collect();
-        let (f_ways, f_status) = flatten_ways(&ways, tolerance).unwrap();
-
-        // check and close if needed
-        if closed && f_status.code != 501 {
-            let mut f_ways_closed = Vec::new();
-            let mut f_status_closed = f_status;
-            for w in f_ways {
-                let (w_closed, w_status) = close_linestring(&w, tolerance).unwrap();
-                if w_status.code == 501 {
-                    f_status_closed = ParseStatus::new(501, "Broken");
+        self.flatten(tolerance, closed, false)
+    }
+
+    /// same as flatten_ways() without closing, but when the ways can not be merged into one linestring (status 501)
+    /// the best partial merge is returned as a multilinestring instead of an empty geometry,
+    /// the gaps between its parts are in the status (see ParseStatus::gap_count())
+    pub fn flatten_ways_partial(
+        &self,
+        tolerance: f64,
+    ) -> Result<(Vec<Vec<Node>>, ParseStatus), ParseError> {
+        self.flatten(tolerance, false, true)
+    }
+
+    fn flatten(
+        &self,
+        tolerance: f64,
+        closed: bool,
+        partial: bool,
+    ) -> Result<(Vec<Vec<Node>>, ParseStatus), ParseError> {
+        let ways: Vec<Segment> = self
+            .ways
+            .iter()
+            .filter(|way| !way.nodes.is_empty())
+            .map(Segment::new)
+            .collect();
+        let (f_ways, mut f_status) = flatten_and_close(&ways, tolerance, closed, partial);
+        if !self.missing.is_empty() {
+            f_status
+                .issues
+                .insert(0, ParseIssue::MissingMembers(self.missing.clone()));
+        }
+        Ok((f_ways, f_status))
+    }
+
+    /// fills the gaps found flattening the ways with `tolerance` (the Gap issues of flatten_ways())
+    /// with the shortest path of the road graph, when it is at most `max_detour` meters longer than the gap
+    /// and it does not travel ways of the relation, the pieces of the traveled ways are inserted in `ways`
+    /// after the way where the gap starts and reported as GapFilled issues
+    pub fn fill_gaps(&mut self, gap_fill: &GapFill, tolerance: f64) -> Vec<ParseIssue> {
+        let gaps: Vec<Gap> = match self.flatten(tolerance, false, false) {
+            Ok((_, status)) => status
+                .issues
+                .into_iter()
+                .filter_map(|issue| match issue {
+                    ParseIssue::Gap(gap) => Some(gap),
+                    _ => None,
+                })
+                .collect(),
+            Err(_) => return Vec::new(),
+        };
+        let mut issues = Vec::new();
+        let mut inserted: HashMap<u64, Vec<Way>> = HashMap::new();
+        for gap in gaps {
+            let path = gap_fill.graph.route_shortest_path(
+                gap.from_node,
+                gap.to_node,
+                self.tags.get("route"),
+                gap.distance + gap_fill.max_detour,
+            );
+            // a path over the ways of the relation means that they are unsorted, not that ways are missing
+            let path = path.filter(|p| {
+                p.edge_way_ids
+                    .iter()
+                    .all(|id| !self.ways.iter().any(|w| w.id == *id))
+            });
+            if let Some(path) = path {
+                inserted
+                    .entry(gap.from_way)
+                    .or_default()
+                    .extend(path.ways());
+                issues.push(ParseIssue::GapFilled {
+                    way_ids: path.way_ids(),
+                    length: path.length,
+                    gap,
+                });
+            }
+        }
+        if !inserted.is_empty() {
+            let ways = std::mem::take(&mut self.ways);
+            for way in ways {
+                let id = way.id;
+                self.ways.push(way);
+                self.ways.extend(inserted.remove(&id).unwrap_or_default());
+            }
+        }
+        issues
+    }
+
+    /// flatten_ways() for public transport routes, the resulting geometry is oriented in the direction of travel
+    /// using the first / last stops, or the member order of the ways when there are no stops,
+    /// and the ways traveled against their oneway tags are reported in the status
+    /// if `partial` the geometry of broken routes is kept as in flatten_ways_partial()
+    pub fn flatten_route(
+        &self,
+        tolerance: f64,
+        partial: bool,
+    ) -> Result<(Vec<Vec<Node>>, ParseStatus), ParseError> {
+        let (mut f_ways, mut f_status) = self.flatten(tolerance, false, partial)?;
+        if self.should_reverse(&f_ways) {
+            reverse_parts(&mut f_ways);
+            f_status.issues.push(ParseIssue::Reversed);
+        }
+        f_status.issues.extend(self.against_oneway(&f_ways));
+        Ok((f_ways, f_status))
+    }
+
+    /// true if the flattened `parts` run from the last stop to the first one
+    /// (or from the last way to the first one if there are not two stops to compare)
+    fn should_reverse(&self, parts: &[Vec<Node>]) -> bool {
+        let lonlats: Vec<Vec<LonLat>> = parts
+            .iter()
+            .map(|part| part.iter().map(|n| (n.lon, n.lat)).collect())
+            .collect();
+        let stop_ids: Vec<u64> = self
+            .members
+            .iter()
+            .filter(|m| m.member_type == MemberType::Node && m.role.starts_with("stop"))
+            .map(|m| m.id)
+            .collect();
+        let stops: Vec<&Node> = if stop_ids.is_empty() {
+            self.stops.iter().collect()
+        } else {
+            self.stops
+                .iter()
+                .filter(|s| stop_ids.contains(&s.id))
+                .collect()
+        };
+        if stops.len() > 1 {
+            let first = stops[0];
+            let last = stops[stops.len() - 1];
+            let first_position = locate_parts(&lonlats, (first.lon, first.lat));
+            let last_position = locate_parts(&lonlats, (last.lon, last.lat));
+            if let (Some(first_position), Some(last_position)) = (first_position, last_position) {
+                if first_position.distance != last_position.distance {
+                    return first_position.distance > last_position.distance;
+                }
+            }
+        }
+
+        // compare where the first and the last way members are in the geometry
+        let route_way_ids: Vec<u64> = self
+            .members
+            .iter()
+            .filter(|m| m.member_type == MemberType::Way && !m.role.starts_with("platform"))
+            .map(|m| m.id)
+            .collect();
+        let way_nodes = |id: u64| {
+            self.ways
+                .iter()
+                .find(|w| w.id == id)
+                .map(|w| w.nodes.iter().map(|n| n.id).collect::<Vec<u64>>())
+        };
+        let node_ids: Vec<u64> = parts.iter().flatten().map(|n| n.id).collect();
+        let (first_way, last_way) = match (route_way_ids.first(), route_way_ids.last()) {
+            (Some(first), Some(last)) if first != last => (way_nodes(*first), way_nodes(*last)),
+            _ => return false,
+        };
+        let (first_way, last_way) = match (first_way, last_way) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return false,
+        };
+        let first_index = node_ids.iter().position(|id| first_way.contains(id));
+        let last_index = node_ids.iter().rposition(|id| last_way.contains(id));
+        match (first_index, last_index) {
+            (Some(first_index), Some(last_index)) => first_index > last_index,
+            _ => false,
+        }
+    }
+
+    /// osm way of each segment of the flattened `parts`, in the same shape as `parts`:
+    /// the way from each node to the next one, None for the last node and where the ways were joined across a gap
+    pub fn segment_ways(&self, parts: &[Vec<Node>]) -> Vec<Vec<Option<u64>>> {
+        let mut ways: HashMap<(u64, u64), u64> = HashMap::new();
+        for way in &self.ways {
+            for pair in way.nodes.windows(2) {
+                ways.entry((pair[0].id, pair[1].id)).or_insert(way.id);
+                ways.entry((pair[1].id, pair[0].id)).or_insert(way.id);
+            }
+        }
+        parts
+            .iter()
+            .map(|part| {
+                let mut part_ways: Vec<Option<u64>> = part
+                    .windows(2)
+                    .map(|pair| ways.get(&(pair[0].id, pair[1].id)).copied())
+                    .collect();
+                if !part.is_empty() {
+                    part_ways.push(None);
                 }
-                if w_status.code != 501 && f_status_closed.code != 501 {
-                    f_status_closed = w_status;
+                part_ways
+            })
+            .collect()
+    }
+
+    /// ways of the relation that the flattened `parts` travel against their oneway direction
+    fn against_oneway(&self, parts: &[Vec<Node>]) -> Vec<ParseIssue> {
+        let route = self.tags.get("route").map(String::as_str);
+        // allowed (from, to) node pairs of the oneway ways
+        let mut allowed: HashMap<(u64, u64), u64> = HashMap::new();
+        for way in &self.ways {
+            if let Some(forward) = oneway(&way.tags, route) {
+                for pair in way.nodes.windows(2) {
+                    let (a, b) = if forward {
+                        (pair[0].id, pair[1].id)
+                    } else {
+                        (pair[1].id, pair[0].id)
+                    };
+                    allowed.insert((a, b), way.id);
                 }
-                f_ways_closed.push(w_closed);
             }
-            Ok((f_ways_closed, f_status_closed))
-        } else {
-            Ok((f_ways, f_status))
         }
+        let mut issues = Vec::new();
+        let mut reported = Vec::new();
+        for pair in parts.iter().flat_map(|part| part.windows(2)) {
+            let (a, b) = (pair[0].id, pair[1].id);
+            if allowed.contains_key(&(a, b)) {
+                continue;
+            }
+            if let Some(way_id) = allowed.get(&(b, a)) {
+                if !reported.contains(way_id) {
+                    reported.push(*way_id);
+                    issues.push(ParseIssue::AgainstOneway {
+                        way_id: *way_id,
+                        from_node: a,
+                        to_node: b,
+                    });
+                }
+            }
+        }
+        issues
     }
 }
 
@@ -311,34 +979,29 @@ impl Way {
         &self,
         tolerance: f64,
         closed: bool,
-    ) -> Result<(Vec<Vec<Node>>, ParseStatus), ()> {
-        let ways: Vec<Vec<Node>> = vec![self.nodes.clone()];
-        let (f_ways, f_status) = flatten_ways(&ways, tolerance).unwrap();
-
-        // check and close if needed
-        if closed && f_status.code != 501 {
-            let mut f_ways_closed = Vec::new();
-            let mut f_status_closed = f_status;
-            for w in f_ways {
-                let (w_closed, w_status) = close_linestring(&w, tolerance).unwrap();
-                if w_status.code == 501 {
-                    f_status_closed = ParseStatus::new(501, "Broken");
-                }
-                if w_status.code != 501 && f_status_closed.code != 501 {
-                    f_status_closed = w_status;
-                }
-                f_ways_closed.push(w_closed);
-            }
-            Ok((f_ways_closed, f_status_closed))
-        } else {
-            Ok((f_ways, f_status))
-        }
+    ) -> Result<(Vec<Vec<Node>>, ParseStatus), ParseError> {
+        let ways: Vec<Segment> = Some(Segment::new(self))
+            .filter(|segment| !segment.nodes.is_empty())
+            .into_iter()
+            .collect();
+        Ok(flatten_and_close(&ways, tolerance, closed, false))
     }
 }
 
 impl Area {
     pub fn to_geojson(&self) -> String {
-        json!({
+        self.geojson().to_string()
+    }
+
+    /// geojson with the metrics() in the "metrics" property
+    pub fn to_geojson_with_metrics(&self) -> String {
+        let mut geojson = self.geojson();
+        geojson["properties"]["metrics"] = self.metrics().to_json();
+        geojson.to_string()
+    }
+
+    pub(crate) fn geojson(&self) -> Value {
+        let mut geojson = json!({
             "type": "Feature",
             "properties": {
                 "id": self.id,
@@ -346,47 +1009,177 @@ impl Area {
                 "tags": self.tags,
                 "info": self.info,
                 "parse_status": {
-                    "code": self.parse_status.code,
-                    "detail": self.parse_status.detail,
+                    "code": self.parse_status.code(),
+                    "detail": self.parse_status.detail(),
+                    "issues": self.parse_status.issues_json(),
                 }
             },
             "geometry": {
                 "type": "Polygon",
                 "coordinates": self.geometry
             }
-        })
-        .to_string()
+        });
+        if let Some(parent_id) = self.parent_id {
+            geojson["properties"]["parent_id"] = json!(parent_id);
+        }
+        geojson
     }
 }
 
 impl PublicTransport {
-    pub fn to_geojson(&self) -> String {
+    /// position of each stop along the geometry, in the same order as `stops`,
+    /// each stop is located after the previous one (see segments())
+    /// None if the geometry is empty
+    pub fn stop_positions(&self) -> Vec<Option<StopPosition>> {
+        locate_sequence(
+            &self.geometry,
+            self.stops.iter().map(|stop| (stop.lon, stop.lat)),
+        )
+    }
+
+    /// stops where the vehicles stop, in the order of the relation:
+    /// the nodes with a stop role (stop, stop_entry_only, stop_exit_only), or all the stops if there is none
+    pub fn stop_sequence(&self) -> Vec<&Node> {
+        let nodes: HashMap<u64, &Node> = self.stops.iter().map(|s| (s.id, s)).collect();
+        let sequence: Vec<&Node> = self
+            .members
+            .iter()
+            .filter(|m| m.member_type == MemberType::Node && m.role.starts_with("stop"))
+            .filter_map(|m| nodes.get(&m.id).copied())
+            .collect();
+        if sequence.is_empty() {
+            self.stops.iter().collect()
+        } else {
+            sequence
+        }
+    }
+
+    /// the geometry cut at each stop of stop_sequence(), one segment per pair of consecutive stops
+    /// (a stop repeated right after itself is skipped)
+    /// Each stop is located after the previous one, so routes that pass twice by the same place are cut in order,
+    /// unless the closest point of the whole line is more than SEQUENCE_TOLERANCE meters closer to the stop
+    pub fn segments(&self) -> Vec<StopSegment> {
+        let mut sequence = self.stop_sequence();
+        sequence.dedup_by_key(|stop| stop.id);
+        let positions = locate_sequence(
+            &self.geometry,
+            sequence.iter().map(|stop| (stop.lon, stop.lat)),
+        );
+        sequence
+            .windows(2)
+            .zip(positions.windows(2))
+            .map(|(pair, position)| {
+                let (start, end) = match (position[0], position[1]) {
+                    (Some(a), Some(b)) => (a.distance, b.distance),
+                    _ => (0_f64, 0_f64),
+                };
+                StopSegment {
+                    from: pair[0].id,
+                    to: pair[1].id,
+                    start,
+                    end,
+                    length: (end - start).abs(),
+                    geometry: if position[0].is_some() {
+                        slice(&self.geometry, start, end)
+                    } else {
+                        Vec::new()
+                    },
+                }
+            })
+            .collect()
+    }
+
+    /// geojson FeatureCollection with a LineString per segment,
+    /// the geometry is null when the stops are not on the line or both are at the same point
+    pub fn segments_geojson(&self) -> Value {
+        let names: HashMap<u64, &String> = self
+            .stops
+            .iter()
+            .filter_map(|stop| stop.tags.get("name").map(|name| (stop.id, name)))
+            .collect();
+        let features: Vec<Value> = self
+            .segments()
+            .iter()
+            .enumerate()
+            .map(|(i, segment)| {
+                let geometry = if segment.geometry.windows(2).any(|pair| pair[0] != pair[1]) {
+                    json!({
+                        "type": "LineString",
+                        "coordinates": segment.geometry,
+                    })
+                } else {
+                    Value::Null
+                };
+                json!({
+                    "type": "Feature",
+                    "geometry": geometry,
+                    "properties": {
+                        "route_id": self.id,
+                        "ref": self.tags.get("ref"),
+                        "sequence": i,
+                        "from": segment.from,
+                        "to": segment.to,
+                        "from_name": names.get(&segment.from),
+                        "to_name": names.get(&segment.to),
+                        "start": segment.start,
+                        "end": segment.end,
+                        "length": segment.length,
+                    },
+                })
+            })
+            .collect();
         json!({
+            "type": "FeatureCollection",
+            "features": features,
+        })
+    }
+
+    pub fn to_geojson(&self) -> String {
+        self.geojson().to_string()
+    }
+
+    /// geojson with the metrics() in the "metrics" property
+    pub fn to_geojson_with_metrics(&self) -> String {
+        let mut geojson = self.geojson();
+        geojson["properties"]["metrics"] = self.metrics().to_json();
+        geojson.to_string()
+    }
+
+    pub(crate) fn geojson(&self) -> Value {
+        let positions = self.stop_positions();
+        let geometry = match self.geometry.as_slice() {
+            [line] => json!({"type": "LineString", "coordinates": line}),
+            parts => json!({"type": "MultiLineString", "coordinates": parts}),
+        };
+        let mut geojson = json!({
             "type": "FeatureCollection",
             "properties": {
                 "id": self.id,
                 "tags": self.tags,
                 "info": self.info,
                 "parse_status": {
-                    "code": self.parse_status.code,
-                    "detail": self.parse_status.detail,
+                    "code": self.parse_status.code(),
+                    "detail": self.parse_status.detail(),
+                    "issues": self.parse_status.issues_json(),
+                    "gap_count": self.parse_status.gap_count(),
+                    "gap_length": self.parse_status.gap_length(),
+                    "gap_locations": self.parse_status.gap_locations(),
                 }
             },
             "features": [
                 {
                     "type": "Feature",
-                    "geometry": {
-                        "type": "LineString",
-                        "coordinates": self.geometry
-                    }
+                    "geometry": geometry
                 },
                 {
                     "type": "FeatureCollection",
-                    "features": self.stops.iter().map(|s| json!({
+                    "features": self.stops.iter().zip(&positions).map(|(s, p)| json!({
                         "type": "Feature",
                         "properties": {
                             "id": s.id,
                             "tags": s.tags,
+                            "distance": p.map(|p| p.distance),
+                            "offset": p.map(|p| p.offset),
                         },
                         "geometry": {
                             "type": "Point",
@@ -395,7 +1188,17 @@ impl PublicTransport {
                     })).collect::<Vec<_>>()
                 },
             ]
-        })
-        .to_string()
+        });
+        if let Some(admin) = &self.admin {
+            geojson["properties"]["admin_areas"] = admin_json(&admin.route);
+            let stops = &mut geojson["features"][1]["features"];
+            for (i, areas) in admin.stops.iter().enumerate() {
+                stops[i]["properties"]["admin_areas"] = admin_json(areas);
+            }
+        }
+        if !self.schedule.is_empty() {
+            geojson["properties"]["schedule"] = self.schedule.to_json();
+        }
+        geojson
     }
 }
@@ -0,0 +1,404 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::io::{self, Write};
+use std::sync::Arc;
+
+use osm_pbf_iter::Primitive;
+
+use super::map_blocks;
+use super::relation::{oneway, LonLat, Node, Way};
+use crate::geo::haversine;
+
+/// highway values that public transport vehicles can not use
+const NOT_ROADS: [&str; 10] = [
+    "footway",
+    "path",
+    "steps",
+    "cycleway",
+    "bridleway",
+    "pedestrian",
+    "corridor",
+    "platform",
+    "proposed",
+    "construction",
+];
+
+const RAILS: [&str; 6] = [
+    "rail",
+    "tram",
+    "subway",
+    "light_rail",
+    "monorail",
+    "narrow_gauge",
+];
+
+/// Kind of ways a route can travel on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Network {
+    /// highway=* ways usable by vehicles
+    Road,
+    /// railway=rail/tram/subway/light_rail/monorail/narrow_gauge ways
+    Rail,
+}
+
+impl Network {
+    /// network used by the vehicles of a `route` tag value
+    pub fn for_route(route: Option<&String>) -> Self {
+        match route.map(String::as_str) {
+            Some("train") | Some("subway") | Some("light_rail") | Some("monorail")
+            | Some("tram") => Network::Rail,
+            _ => Network::Road,
+        }
+    }
+
+    fn of_way(tags: &HashMap<&str, &str>) -> Option<Self> {
+        if let Some(highway) = tags.get("highway") {
+            if !NOT_ROADS.contains(highway) {
+                return Some(Network::Road);
+            }
+        }
+        match tags.get("railway") {
+            Some(railway) if RAILS.contains(railway) => Some(Network::Rail),
+            _ => None,
+        }
+    }
+}
+
+/// highway / railway way of the graph
+struct GraphWay {
+    id: u64,
+    network: Network,
+    oneway: Oneway,
+    nodes: Vec<u64>,
+}
+
+/// travel directions of a way, see relation::oneway()
+#[derive(Clone, Debug)]
+struct Oneway {
+    /// direction of the routes with an oneway:<route> tag
+    routes: Vec<(String, Option<bool>)>,
+    /// direction of the other routes, from the oneway:psv and oneway tags
+    default: Option<bool>,
+}
+
+impl Oneway {
+    fn new(tags: &HashMap<String, String>) -> Self {
+        Oneway {
+            routes: tags
+                .keys()
+                .filter_map(|key| key.strip_prefix("oneway:"))
+                .filter(|route| *route != "psv")
+                .map(|route| (route.to_string(), oneway(tags, Some(route))))
+                .collect(),
+            default: oneway(tags, None),
+        }
+    }
+
+    /// whether vehicles of type `route` can travel the way forward / backward
+    fn allows(&self, route: Option<&str>, forward: bool) -> bool {
+        let direction = route
+            .and_then(|route| self.routes.iter().find(|(r, _)| r == route))
+            .map_or(self.default, |(_, direction)| *direction);
+        direction.is_none_or(|direction| direction == forward)
+    }
+}
+
+struct Edge {
+    to: u64,
+    way_id: u64,
+    network: Network,
+    length: f64,
+    /// index of the way in RoadGraph::oneways
+    way: usize,
+    /// whether the edge follows the node order of the way
+    forward: bool,
+}
+
+/// A path found in the road graph
+#[derive(Clone, Debug)]
+pub struct GraphPath {
+    /// nodes of the path, without tags
+    pub nodes: Vec<Node>,
+    /// way of each edge of the path (one less than the nodes)
+    pub edge_way_ids: Vec<u64>,
+    /// length in meters
+    pub length: f64,
+}
+
+impl GraphPath {
+    /// ids of the ways traveled by the path, in order
+    pub fn way_ids(&self) -> Vec<u64> {
+        let mut way_ids: Vec<u64> = self.edge_way_ids.clone();
+        way_ids.dedup();
+        way_ids
+    }
+
+    /// the path split into one piece of way per traveled way
+    pub fn ways(&self) -> Vec<Way> {
+        let mut ways: Vec<Way> = Vec::new();
+        for (i, way_id) in self.edge_way_ids.iter().enumerate() {
+            match ways.last_mut() {
+                Some(way) if way.id == *way_id => way.nodes.push(self.nodes[i + 1].clone()),
+                _ => ways.push(Way {
+                    id: *way_id,
+                    tags: HashMap::new(),
+                    info: HashMap::new(),
+                    nodes: vec![self.nodes[i].clone(), self.nodes[i + 1].clone()],
+                }),
+            }
+        }
+        ways
+    }
+}
+
+/// entry of the A* open set, ordered by lowest estimate first
+struct State {
+    estimate: f64,
+    cost: f64,
+    node: u64,
+}
+
+impl PartialEq for State {
+    fn eq(&self, other: &Self) -> bool {
+        self.estimate == other.estimate
+    }
+}
+impl Eq for State {}
+impl PartialOrd for State {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for State {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+/// Routable graph of all the highway / railway ways of a pbf file, with the directions they can be traveled
+/// (oneway tags, with the oneway:psv and oneway:<route> exceptions evaluated for the route of each query)
+pub struct RoadGraph {
+    nodes: HashMap<u64, LonLat>,
+    edges: HashMap<u64, Vec<Edge>>,
+    oneways: Vec<Oneway>,
+}
+
+impl RoadGraph {
+    /// builds the graph parsing the pbf file in the `pbf_filename` path, in parallel with `cpus` threads
+    pub fn new(pbf_filename: &str, cpus: usize) -> Self {
+        eprint!("START Road graph ways, ");
+        io::stderr().flush().unwrap();
+        let ways: Vec<GraphWay> =
+            map_blocks(pbf_filename, cpus, |block, ways: &mut Vec<GraphWay>| {
+                for primitive in block.primitives() {
+                    if let Primitive::Way(way) = primitive {
+                        let tags: HashMap<&str, &str> = way.tags().collect();
+                        if let Some(network) = Network::of_way(&tags) {
+                            let oneway_tags: HashMap<String, String> = tags
+                                .iter()
+                                .filter(|(k, _)| k.starts_with("oneway") || **k == "junction")
+                                .map(|(k, v)| (k.to_string(), v.to_string()))
+                                .collect();
+                            ways.push(GraphWay {
+                                id: way.id,
+                                network,
+                                oneway: Oneway::new(&oneway_tags),
+                                nodes: way.refs().map(|id| id as u64).collect(),
+                            });
+                        }
+                    }
+                }
+            })
+            .into_iter()
+            .flatten()
+            .collect();
+        eprintln!("found {}", ways.len());
+
+        eprint!("START Road graph nodes, ");
+        io::stderr().flush().unwrap();
+        let node_ids: Arc<HashSet<u64>> =
+            Arc::new(ways.iter().flat_map(|w| w.nodes.iter().cloned()).collect());
+        let nodes: HashMap<u64, LonLat> = map_blocks(
+            pbf_filename,
+            cpus,
+            move |block, nodes: &mut HashMap<u64, LonLat>| {
+                for primitive in block.primitives() {
+                    if let Primitive::Node(node) = primitive {
+                        if node_ids.contains(&node.id) {
+                            nodes.insert(node.id, (node.lon, node.lat));
+                        }
+                    }
+                }
+            },
+        )
+        .into_iter()
+        .flatten()
+        .collect();
+        eprintln!("found {}", nodes.len());
+
+        let mut edges: HashMap<u64, Vec<Edge>> = HashMap::new();
+        let mut oneways = Vec::new();
+        for way in ways {
+            let index = oneways.len();
+            for pair in way.nodes.windows(2) {
+                let (a, b) = match (nodes.get(&pair[0]), nodes.get(&pair[1])) {
+                    (Some(a), Some(b)) => (*a, *b),
+                    _ => continue,
+                };
+                let length = haversine(a, b);
+                for (from, to, forward) in [(pair[0], pair[1], true), (pair[1], pair[0], false)] {
+                    edges.entry(from).or_default().push(Edge {
+                        to,
+                        way_id: way.id,
+                        network: way.network,
+                        length,
+                        way: index,
+                        forward,
+                    });
+                }
+            }
+            oneways.push(way.oneway);
+        }
+        RoadGraph {
+            nodes,
+            edges,
+            oneways,
+        }
+    }
+
+    /// number of nodes of the graph
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// shortest path (A*) from node `from` to node `to` over the ways of `network`,
+    /// None if there is no path or it is longer than `max_length` meters,
+    /// the ways are traveled following their oneway:psv / oneway tags
+    pub fn shortest_path(
+        &self,
+        from: u64,
+        to: u64,
+        network: Network,
+        max_length: f64,
+    ) -> Option<GraphPath> {
+        self.search(from, to, network, None, max_length)
+    }
+
+    /// same as shortest_path() for the vehicles of a `route` tag value, over the ways of its network
+    /// (see Network::for_route()) following their oneway:<route> tags before the oneway:psv / oneway ones
+    pub fn route_shortest_path(
+        &self,
+        from: u64,
+        to: u64,
+        route: Option<&String>,
+        max_length: f64,
+    ) -> Option<GraphPath> {
+        let network = Network::for_route(route);
+        self.search(from, to, network, route.map(String::as_str), max_length)
+    }
+
+    fn search(
+        &self,
+        from: u64,
+        to: u64,
+        network: Network,
+        route: Option<&str>,
+        max_length: f64,
+    ) -> Option<GraphPath> {
+        let target = *self.nodes.get(&to)?;
+        let heuristic = |node: u64| haversine(self.nodes[&node], target);
+        self.nodes.get(&from)?;
+
+        let mut costs: HashMap<u64, f64> = HashMap::new();
+        let mut previous: HashMap<u64, (u64, u64)> = HashMap::new();
+        let mut open = BinaryHeap::new();
+        costs.insert(from, 0_f64);
+        open.push(State {
+            estimate: heuristic(from),
+            cost: 0_f64,
+            node: from,
+        });
+        while let Some(State { cost, node, .. }) = open.pop() {
+            if node == to {
+                return Some(self.path(from, to, cost, &previous));
+            }
+            if cost > costs[&node] {
+                continue;
+            }
+            for edge in self.edges.get(&node).into_iter().flatten() {
+                if edge.network != network || !self.oneways[edge.way].allows(route, edge.forward) {
+                    continue;
+                }
+                let next_cost = cost + edge.length;
+                let estimate = next_cost + heuristic(edge.to);
+                if estimate > max_length {
+                    continue;
+                }
+                if costs.get(&edge.to).is_none_or(|c| next_cost < *c) {
+                    costs.insert(edge.to, next_cost);
+                    previous.insert(edge.to, (node, edge.way_id));
+                    open.push(State {
+                        estimate,
+                        cost: next_cost,
+                        node: edge.to,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    fn path(
+        &self,
+        from: u64,
+        to: u64,
+        length: f64,
+        previous: &HashMap<u64, (u64, u64)>,
+    ) -> GraphPath {
+        let mut node_ids = vec![to];
+        let mut edge_way_ids = Vec::new();
+        let mut node = to;
+        while node != from {
+            let (prev, way_id) = previous[&node];
+            node_ids.push(prev);
+            edge_way_ids.push(way_id);
+            node = prev;
+        }
+        node_ids.reverse();
+        edge_way_ids.reverse();
+        GraphPath {
+            nodes: node_ids
+                .into_iter()
+                .map(|id| Node {
+                    id,
+                    tags: HashMap::new(),
+                    lon: self.nodes[&id].0,
+                    lat: self.nodes[&id].1,
+                })
+                .collect(),
+            edge_way_ids,
+            length,
+        }
+    }
+}
+
+/// Options to fill the gaps of the routes with the shortest path over the road graph
+#[derive(Clone)]
+pub struct GapFill {
+    pub graph: Arc<RoadGraph>,
+    /// maximum extra length in meters of the path over the straight distance of the gap
+    pub max_detour: f64,
+}
+
+impl GapFill {
+    pub fn new(graph: RoadGraph, max_detour: f64) -> Self {
+        GapFill {
+            graph: Arc::new(graph),
+            max_detour,
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use osm_pbf_iter::{Primitive, RelationMemberType};
+
+use super::map_blocks;
+use super::relation::{Member, MemberType};
+
+/// public_transport=stop_area relation, groups the stop positions, platforms and station of one stop
+#[derive(Clone, Debug)]
+pub struct StopArea {
+    /// osm id
+    pub id: u64,
+    pub tags: HashMap<String, String>,
+    pub members: Vec<Member>,
+}
+
+impl StopArea {
+    /// parses all the stop areas of the pbf file in the `pbf_filename` path, in parallel with `cpus` threads,
+    /// sorted by id
+    pub fn parse(pbf_filename: &str, cpus: usize) -> Vec<StopArea> {
+        eprint!("START Stop areas, ");
+        io::stderr().flush().unwrap();
+        let mut stop_areas: Vec<StopArea> = map_blocks(
+            pbf_filename,
+            cpus,
+            |block, stop_areas: &mut Vec<StopArea>| {
+                for primitive in block.primitives() {
+                    if let Primitive::Relation(relation) = primitive {
+                        if !relation
+                            .tags()
+                            .any(|t| t == ("public_transport", "stop_area"))
+                        {
+                            continue;
+                        }
+                        stop_areas.push(StopArea {
+                            id: relation.id,
+                            tags: relation
+                                .tags()
+                                .map(|t| (t.0.to_string(), t.1.to_string()))
+                                .collect(),
+                            members: relation
+                                .members()
+                                .map(|member| Member {
+                                    id: member.1,
+                                    member_type: match member.2 {
+                                        RelationMemberType::Node => MemberType::Node,
+                                        RelationMemberType::Way => MemberType::Way,
+                                        RelationMemberType::Relation => MemberType::Relation,
+                                    },
+                                    role: member.0.to_string(),
+                                })
+                                .collect(),
+                        });
+                    }
+                }
+            },
+        )
+        .into_iter()
+        .flatten()
+        .collect();
+        stop_areas.sort_by_key(|stop_area| stop_area.id);
+        eprintln!("found {}", stop_areas.len());
+        stop_areas
+    }
+
+    /// ids of the node members (stop positions, platforms and stations)
+    pub fn node_ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.members
+            .iter()
+            .filter(|member| member.member_type == MemberType::Node)
+            .map(|member| member.id)
+    }
+}
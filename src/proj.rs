@@ -0,0 +1,250 @@
+//! Reprojection of WGS84 (lon, lat) coordinates to Web Mercator or UTM, without PROJ
+//!
+//! UTM uses the Krüger series of the transverse mercator (as in Karney 2011), accurate to less than a millimeter inside the zone
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+
+use crate::parser::relation::LonLat;
+
+/// WGS84 semi-major axis in meters
+const A: f64 = 6_378_137_f64;
+/// WGS84 flattening
+const F: f64 = 1_f64 / 298.257_223_563;
+/// UTM scale factor on the central meridian
+const K0: f64 = 0.9996;
+/// latitude limit of web mercator
+const MAX_MERCATOR_LAT: f64 = 85.051_128_779_806_59;
+
+const WGS84_GEOGCS: &str = "GEOGCS[\"WGS 84\",DATUM[\"WGS_1984\",SPHEROID[\"WGS 84\",6378137,298.257223563,\
+AUTHORITY[\"EPSG\",\"7030\"]],AUTHORITY[\"EPSG\",\"6326\"]],PRIMEM[\"Greenwich\",0,AUTHORITY[\"EPSG\",\"8901\"]],\
+UNIT[\"degree\",0.0174532925199433,AUTHORITY[\"EPSG\",\"9122\"]],AUTHORITY[\"EPSG\",\"4326\"]]";
+
+/// Coordinate reference system of the output geometries
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Crs {
+    /// EPSG:4326, (lon, lat) in degrees, as parsed
+    #[default]
+    Wgs84,
+    /// EPSG:3857, web mercator in meters
+    WebMercator,
+    /// EPSG:326zz (north) / 327zz (south), WGS84 / UTM zone in meters
+    Utm { zone: u8, north: bool },
+}
+
+impl Crs {
+    /// UTM zone of `point`, without the Norway / Svalbard exceptions
+    pub fn utm_for(point: LonLat) -> Self {
+        let zone = (((point.0 + 180_f64) / 6_f64).floor() as i64).rem_euclid(60) + 1;
+        Crs::Utm {
+            zone: zone as u8,
+            north: point.1 >= 0_f64,
+        }
+    }
+
+    pub fn epsg(self) -> u32 {
+        match self {
+            Crs::Wgs84 => 4326,
+            Crs::WebMercator => 3857,
+            Crs::Utm { zone, north: true } => 32600 + u32::from(zone),
+            Crs::Utm { zone, north: false } => 32700 + u32::from(zone),
+        }
+    }
+
+    pub fn name(self) -> String {
+        match self {
+            Crs::Wgs84 => "WGS 84".to_string(),
+            Crs::WebMercator => "WGS 84 / Pseudo-Mercator".to_string(),
+            Crs::Utm { zone, north } => {
+                format!(
+                    "WGS 84 / UTM zone {}{}",
+                    zone,
+                    if north { "N" } else { "S" }
+                )
+            }
+        }
+    }
+
+    /// OGC WKT 1 definition
+    pub fn wkt(self) -> String {
+        let projection = match self {
+            Crs::Wgs84 => return WGS84_GEOGCS.to_string(),
+            Crs::WebMercator => "PROJECTION[\"Mercator_1SP\"],PARAMETER[\"central_meridian\",0],\
+                PARAMETER[\"scale_factor\",1],PARAMETER[\"false_easting\",0],PARAMETER[\"false_northing\",0]"
+                .to_string(),
+            Crs::Utm { north, .. } => format!(
+                "PROJECTION[\"Transverse_Mercator\"],PARAMETER[\"latitude_of_origin\",0],\
+                PARAMETER[\"central_meridian\",{}],PARAMETER[\"scale_factor\",{}],\
+                PARAMETER[\"false_easting\",500000],PARAMETER[\"false_northing\",{}]",
+                self.central_meridian(),
+                K0,
+                if north { 0 } else { 10_000_000 }
+            ),
+        };
+        format!(
+            "PROJCS[\"{}\",{},{},UNIT[\"metre\",1,AUTHORITY[\"EPSG\",\"9001\"]],\
+            AXIS[\"Easting\",EAST],AXIS[\"Northing\",NORTH],AUTHORITY[\"EPSG\",\"{}\"]]",
+            self.name(),
+            WGS84_GEOGCS,
+            projection,
+            self.epsg()
+        )
+    }
+
+    fn central_meridian(self) -> f64 {
+        match self {
+            Crs::Utm { zone, .. } => f64::from(zone) * 6_f64 - 183_f64,
+            _ => 0_f64,
+        }
+    }
+
+    /// (x, y) of a WGS84 (lon, lat) point
+    pub fn project(self, point: LonLat) -> (f64, f64) {
+        match self {
+            Crs::Wgs84 => point,
+            Crs::WebMercator => {
+                let lat = point
+                    .1
+                    .clamp(-MAX_MERCATOR_LAT, MAX_MERCATOR_LAT)
+                    .to_radians();
+                (
+                    A * point.0.to_radians(),
+                    A * (std::f64::consts::FRAC_PI_4 + lat / 2_f64).tan().ln(),
+                )
+            }
+            Crs::Utm { north, .. } => {
+                let (x, y) = transverse_mercator(point.0 - self.central_meridian(), point.1);
+                (500_000_f64 + x, if north { y } else { y + 10_000_000_f64 })
+            }
+        }
+    }
+
+    pub fn project_geometry(self, geometry: &[Vec<LonLat>]) -> Vec<Vec<(f64, f64)>> {
+        geometry
+            .iter()
+            .map(|part| part.iter().map(|p| self.project(*p)).collect())
+            .collect()
+    }
+
+    /// projects the "coordinates" of every "geometry" inside a geojson value
+    pub fn project_geojson(self, geojson: &mut Value) {
+        if self == Crs::Wgs84 {
+            return;
+        }
+        match geojson {
+            Value::Object(object) => {
+                if let Some(geometry) = object.get_mut("geometry") {
+                    if let Some(coordinates) = geometry.get_mut("coordinates") {
+                        self.project_coordinates(coordinates);
+                    }
+                }
+                if let Some(Value::Array(features)) = object.get_mut("features") {
+                    for feature in features {
+                        self.project_geojson(feature);
+                    }
+                }
+            }
+            Value::Array(values) => {
+                for value in values {
+                    self.project_geojson(value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// projects a geojson object and declares the crs in a "crs" member (GeoJSON 2008) when it is not WGS84
+    pub fn reproject_geojson(self, geojson: &mut Value) {
+        if self == Crs::Wgs84 {
+            return;
+        }
+        self.project_geojson(geojson);
+        geojson["crs"] = json!({
+            "type": "name",
+            "properties": {"name": format!("urn:ogc:def:crs:EPSG::{}", self.epsg())},
+        });
+    }
+
+    fn project_coordinates(self, coordinates: &mut Value) {
+        if let Value::Array(values) = coordinates {
+            if let (Some(lon), Some(lat)) = (
+                values.first().and_then(Value::as_f64),
+                values.get(1).and_then(Value::as_f64),
+            ) {
+                let (x, y) = self.project((lon, lat));
+                values[0] = Value::from(x);
+                values[1] = Value::from(y);
+            } else {
+                for value in values {
+                    self.project_coordinates(value);
+                }
+            }
+        }
+    }
+}
+
+/// transverse mercator with the UTM scale factor, `lon` relative to the central meridian, both in degrees
+fn transverse_mercator(lon: f64, lat: f64) -> (f64, f64) {
+    let n = F / (2_f64 - F);
+    let a = A / (1_f64 + n) * (1_f64 + n * n / 4_f64 + n.powi(4) / 64_f64);
+    let alpha = [
+        n / 2_f64 - 2_f64 * n * n / 3_f64 + 5_f64 * n.powi(3) / 16_f64,
+        13_f64 * n * n / 48_f64 - 3_f64 * n.powi(3) / 5_f64,
+        61_f64 * n.powi(3) / 240_f64,
+    ];
+    let e = (F * (2_f64 - F)).sqrt();
+    let (lon, lat) = (lon.to_radians(), lat.to_radians());
+    let t = (lat.sin().atanh() - e * (e * lat.sin()).atanh()).sinh();
+    let xi = t.atan2(lon.cos());
+    let eta = (lon.sin() / (1_f64 + t * t).sqrt()).atanh();
+    let (mut x, mut y) = (eta, xi);
+    for (j, alpha) in alpha.iter().enumerate() {
+        let k = 2_f64 * (j + 1) as f64;
+        x += alpha * (k * xi).cos() * (k * eta).sinh();
+        y += alpha * (k * xi).sin() * (k * eta).cosh();
+    }
+    (K0 * a * x, K0 * a * y)
+}
+
+impl FromStr for Crs {
+    type Err = String;
+
+    /// "4326" / "wgs84", "3857" / "webmercator", "326zz" / "327zz" or "utm:<zone><n|s>", optionally prefixed with "epsg:"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_lowercase();
+        let code = lower.strip_prefix("epsg:").unwrap_or(&lower);
+        let error = || format!("unknown crs '{}'", s);
+        match code {
+            "4326" | "wgs84" => Ok(Crs::Wgs84),
+            "3857" | "webmercator" => Ok(Crs::WebMercator),
+            _ => {
+                let (zone, north) = if let Some(utm) = code.strip_prefix("utm:") {
+                    let north = match utm.chars().last() {
+                        Some('n') => true,
+                        Some('s') => false,
+                        _ => return Err(error()),
+                    };
+                    (utm[..utm.len() - 1].parse::<u8>(), north)
+                } else if let Some(zone) = code.strip_prefix("326") {
+                    (zone.parse::<u8>(), true)
+                } else if let Some(zone) = code.strip_prefix("327") {
+                    (zone.parse::<u8>(), false)
+                } else {
+                    return Err(error());
+                };
+                match zone {
+                    Ok(zone) if (1..=60).contains(&zone) => Ok(Crs::Utm { zone, north }),
+                    _ => Err(error()),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Crs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EPSG:{}", self.epsg())
+    }
+}
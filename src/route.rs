@@ -0,0 +1,209 @@
+//! Normalized route information of public transports: ref, name in a language, colour, route type,
+//! network, operator, from and to, read from the tags in the same way for every output
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+
+use crate::parser::relation::PublicTransport;
+
+/// named colours used in the `colour` tag (the CSS ones most found in osm), https://wiki.openstreetmap.org/wiki/Key:colour
+const NAMED_COLOURS: [(&str, &str); 28] = [
+    ("black", "000000"),
+    ("gray", "808080"),
+    ("grey", "808080"),
+    ("silver", "c0c0c0"),
+    ("white", "ffffff"),
+    ("maroon", "800000"),
+    ("red", "ff0000"),
+    ("orange", "ffa500"),
+    ("yellow", "ffff00"),
+    ("olive", "808000"),
+    ("lime", "00ff00"),
+    ("green", "008000"),
+    ("aqua", "00ffff"),
+    ("cyan", "00ffff"),
+    ("teal", "008080"),
+    ("blue", "0000ff"),
+    ("navy", "000080"),
+    ("purple", "800080"),
+    ("fuchsia", "ff00ff"),
+    ("magenta", "ff00ff"),
+    ("brown", "a52a2a"),
+    ("pink", "ffc0cb"),
+    ("violet", "ee82ee"),
+    ("gold", "ffd700"),
+    ("darkgreen", "006400"),
+    ("darkblue", "00008b"),
+    ("darkred", "8b0000"),
+    ("lightblue", "add8e6"),
+];
+
+/// osm `colour` value ("#rrggbb", "#rgb", "rrggbb" or a named colour) as lowercase "#rrggbb",
+/// None if it is not a colour
+pub fn normalize_colour(colour: &str) -> Option<String> {
+    let colour = colour.trim().to_lowercase();
+    let named = NAMED_COLOURS
+        .iter()
+        .find(|(name, _)| *name == colour.replace(['_', ' '], ""))
+        .map(|(_, hex)| hex.to_string());
+    let hex = match (named, colour.strip_prefix('#')) {
+        (Some(hex), _) => hex,
+        (None, Some(hex)) if hex.len() == 3 => hex.chars().flat_map(|c| vec![c, c]).collect(),
+        (None, Some(hex)) => hex.to_string(),
+        (None, None) => colour,
+    };
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(format!("#{}", hex))
+}
+
+/// Mode of transport, from the `route` tag
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RouteType {
+    Bus,
+    Trolleybus,
+    Minibus,
+    ShareTaxi,
+    Tram,
+    LightRail,
+    Subway,
+    Train,
+    Monorail,
+    Funicular,
+    Ferry,
+    Aerialway,
+    /// missing or unknown `route` value
+    Other,
+}
+
+impl RouteType {
+    /// the `route` tag value
+    pub fn name(self) -> &'static str {
+        match self {
+            RouteType::Bus => "bus",
+            RouteType::Trolleybus => "trolleybus",
+            RouteType::Minibus => "minibus",
+            RouteType::ShareTaxi => "share_taxi",
+            RouteType::Tram => "tram",
+            RouteType::LightRail => "light_rail",
+            RouteType::Subway => "subway",
+            RouteType::Train => "train",
+            RouteType::Monorail => "monorail",
+            RouteType::Funicular => "funicular",
+            RouteType::Ferry => "ferry",
+            RouteType::Aerialway => "aerialway",
+            RouteType::Other => "other",
+        }
+    }
+
+    /// GTFS route_type, bus for the other routes
+    pub fn gtfs_route_type(self) -> u16 {
+        match self {
+            RouteType::Tram | RouteType::LightRail => 0,
+            RouteType::Subway => 1,
+            RouteType::Train => 2,
+            RouteType::Ferry => 4,
+            RouteType::Aerialway => 6,
+            RouteType::Funicular => 7,
+            RouteType::Trolleybus => 11,
+            RouteType::Monorail => 12,
+            RouteType::Bus | RouteType::Minibus | RouteType::ShareTaxi | RouteType::Other => 3,
+        }
+    }
+}
+
+impl FromStr for RouteType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bus" => Ok(RouteType::Bus),
+            "trolleybus" => Ok(RouteType::Trolleybus),
+            "minibus" => Ok(RouteType::Minibus),
+            "share_taxi" => Ok(RouteType::ShareTaxi),
+            "tram" => Ok(RouteType::Tram),
+            "light_rail" => Ok(RouteType::LightRail),
+            "subway" => Ok(RouteType::Subway),
+            "train" => Ok(RouteType::Train),
+            "monorail" => Ok(RouteType::Monorail),
+            "funicular" => Ok(RouteType::Funicular),
+            "ferry" => Ok(RouteType::Ferry),
+            "aerialway" => Ok(RouteType::Aerialway),
+            _ => Err(format!("unknown route type '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for RouteType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl PublicTransport {
+    fn tag(&self, key: &str) -> Option<&str> {
+        self.tags
+            .get(key)
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+    }
+
+    /// `ref` tag
+    pub fn route_ref(&self) -> Option<&str> {
+        self.tag("ref")
+    }
+
+    /// name in the first of `languages` with a `name:<language>` tag, else the `name` tag
+    pub fn name(&self, languages: &[&str]) -> Option<&str> {
+        languages
+            .iter()
+            .find_map(|language| self.tag(&format!("name:{}", language)))
+            .or_else(|| self.tag("name"))
+    }
+
+    /// `colour` tag as "#rrggbb", None if it is missing or not a colour
+    pub fn colour(&self) -> Option<String> {
+        self.tag("colour").and_then(normalize_colour)
+    }
+
+    pub fn route_type(&self) -> RouteType {
+        self.tag("route")
+            .and_then(|route| route.parse().ok())
+            .unwrap_or(RouteType::Other)
+    }
+
+    pub fn network(&self) -> Option<&str> {
+        self.tag("network")
+    }
+
+    pub fn operator(&self) -> Option<&str> {
+        self.tag("operator")
+    }
+
+    /// `from` tag, the name of the first stop
+    pub fn from(&self) -> Option<&str> {
+        self.tag("from")
+    }
+
+    /// `to` tag, the name of the last stop
+    pub fn to(&self) -> Option<&str> {
+        self.tag("to")
+    }
+
+    /// {"ref", "name", "colour", "route_type", "network", "operator", "from", "to"} with the name in `languages`
+    pub fn flat_properties(&self, languages: &[&str]) -> Value {
+        json!({
+            "ref": self.route_ref(),
+            "name": self.name(languages),
+            "colour": self.colour(),
+            "route_type": self.route_type().name(),
+            "network": self.network(),
+            "operator": self.operator(),
+            "from": self.from(),
+            "to": self.to(),
+        })
+    }
+}
@@ -0,0 +1,307 @@
+//! Static packed Hilbert R-tree, the same layout used by the FlatGeobuf index
+//! https://flatgeobuf.org/#spatial-index
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// Axis aligned bounding box
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BBox {
+    pub min_x: f64,
+    pub min_y: f64,
+    pub max_x: f64,
+    pub max_y: f64,
+}
+
+impl BBox {
+    /// empty box, expanding it with anything results in that thing
+    pub fn empty() -> Self {
+        BBox {
+            min_x: f64::INFINITY,
+            min_y: f64::INFINITY,
+            max_x: f64::NEG_INFINITY,
+            max_y: f64::NEG_INFINITY,
+        }
+    }
+
+    pub fn expand(&mut self, other: &BBox) {
+        self.min_x = self.min_x.min(other.min_x);
+        self.min_y = self.min_y.min(other.min_y);
+        self.max_x = self.max_x.max(other.max_x);
+        self.max_y = self.max_y.max(other.max_y);
+    }
+
+    pub fn expand_point(&mut self, x: f64, y: f64) {
+        self.min_x = self.min_x.min(x);
+        self.min_y = self.min_y.min(y);
+        self.max_x = self.max_x.max(x);
+        self.max_y = self.max_y.max(y);
+    }
+
+    pub fn intersects(&self, other: &BBox) -> bool {
+        self.min_x <= other.max_x
+            && self.max_x >= other.min_x
+            && self.min_y <= other.max_y
+            && self.max_y >= other.min_y
+    }
+}
+
+/// 2D hilbert curve value of a point in a 0xFFFF x 0xFFFF grid
+/// http://threadlocalmutex.com/?p=126
+fn hilbert(x: u32, y: u32) -> u32 {
+    let mut a = x ^ y;
+    let mut b = 0xFFFF ^ a;
+    let mut c = 0xFFFF ^ (x | y);
+    let mut d = x & (y ^ 0xFFFF);
+
+    let mut aa = a | (b >> 1);
+    let mut bb = (a >> 1) ^ a;
+    let mut cc = ((c >> 1) ^ (b & (d >> 1))) ^ c;
+    let mut dd = ((a & (c >> 1)) ^ (d >> 1)) ^ d;
+
+    a = aa;
+    b = bb;
+    c = cc;
+    d = dd;
+    aa = (a & (a >> 2)) ^ (b & (b >> 2));
+    bb = (a & (b >> 2)) ^ (b & ((a ^ b) >> 2));
+    cc ^= (a & (c >> 2)) ^ (b & (d >> 2));
+    dd ^= (b & (c >> 2)) ^ ((a ^ b) & (d >> 2));
+
+    a = aa;
+    b = bb;
+    c = cc;
+    d = dd;
+    aa = (a & (a >> 4)) ^ (b & (b >> 4));
+    bb = (a & (b >> 4)) ^ (b & ((a ^ b) >> 4));
+    cc ^= (a & (c >> 4)) ^ (b & (d >> 4));
+    dd ^= (b & (c >> 4)) ^ ((a ^ b) & (d >> 4));
+
+    a = aa;
+    b = bb;
+    c = cc;
+    d = dd;
+    cc ^= (a & (c >> 8)) ^ (b & (d >> 8));
+    dd ^= (b & (c >> 8)) ^ ((a ^ b) & (d >> 8));
+
+    a = cc ^ (cc >> 1);
+    b = dd ^ (dd >> 1);
+
+    let mut i0 = x ^ y;
+    let mut i1 = b | (0xFFFF ^ (i0 | a));
+
+    i0 = (i0 | (i0 << 8)) & 0x00FF_00FF;
+    i0 = (i0 | (i0 << 4)) & 0x0F0F_0F0F;
+    i0 = (i0 | (i0 << 2)) & 0x3333_3333;
+    i0 = (i0 | (i0 << 1)) & 0x5555_5555;
+
+    i1 = (i1 | (i1 << 8)) & 0x00FF_00FF;
+    i1 = (i1 | (i1 << 4)) & 0x0F0F_0F0F;
+    i1 = (i1 | (i1 << 2)) & 0x3333_3333;
+    i1 = (i1 | (i1 << 1)) & 0x5555_5555;
+
+    (i1 << 1) | i0
+}
+
+/// Returns the indexes of `boxes` sorted by the hilbert value of their centers (descending, as FlatGeobuf does)
+pub fn hilbert_order(boxes: &[BBox]) -> Vec<usize> {
+    let mut extent = BBox::empty();
+    for b in boxes {
+        extent.expand(b);
+    }
+    let width = extent.max_x - extent.min_x;
+    let height = extent.max_y - extent.min_y;
+    let hilbert_max = f64::from(0xFFFF_u32);
+    let values: Vec<u32> = boxes
+        .iter()
+        .map(|b| {
+            let x = if width == 0.0 {
+                0
+            } else {
+                (hilbert_max * ((b.min_x + b.max_x) / 2.0 - extent.min_x) / width).floor() as u32
+            };
+            let y = if height == 0.0 {
+                0
+            } else {
+                (hilbert_max * ((b.min_y + b.max_y) / 2.0 - extent.min_y) / height).floor() as u32
+            };
+            hilbert(x, y)
+        })
+        .collect();
+    let mut order: Vec<usize> = (0..boxes.len()).collect();
+    order.sort_by(|a, b| values[*b].cmp(&values[*a]));
+    order
+}
+
+/// Packed R-tree over a list of items that are already in hilbert order
+/// Nodes are stored root first, leaves last, the offset of a leaf is the value given on build()
+/// and the offset of an inner node is the position of its first child
+pub struct PackedRTree {
+    nodes: Vec<(BBox, u64)>,
+    node_size: usize,
+    level_bounds: Vec<(usize, usize)>,
+}
+
+/// entry of the nearest search queue, ordered by lowest distance first
+struct Candidate {
+    distance: f64,
+    position: usize,
+    leaf: bool,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.distance == other.distance
+    }
+}
+impl Eq for Candidate {}
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.distance.total_cmp(&self.distance)
+    }
+}
+
+impl PackedRTree {
+    /// ranges of node positions of each level, leaves first
+    fn level_bounds(num_items: usize, node_size: usize) -> Vec<(usize, usize)> {
+        let mut n = num_items;
+        let mut num_nodes = n;
+        let mut level_num_nodes = vec![n];
+        loop {
+            n = n.div_ceil(node_size);
+            num_nodes += n;
+            level_num_nodes.push(n);
+            if n == 1 {
+                break;
+            }
+        }
+        let mut bounds = Vec::with_capacity(level_num_nodes.len());
+        let mut n = num_nodes;
+        for size in level_num_nodes {
+            bounds.push((n - size, n));
+            n -= size;
+        }
+        bounds
+    }
+
+    /// builds the tree from `items` (bbox, offset) already sorted in hilbert order
+    /// `items` must not be empty
+    pub fn build(items: &[(BBox, u64)], node_size: usize) -> Self {
+        let node_size = node_size.max(2);
+        let level_bounds = Self::level_bounds(items.len(), node_size);
+        let num_nodes = level_bounds[0].1;
+        let mut nodes = vec![(BBox::empty(), 0u64); num_nodes];
+        let leaves_start = level_bounds[0].0;
+        nodes[leaves_start..].clone_from_slice(items);
+        for i in 0..level_bounds.len() - 1 {
+            let (mut pos, end) = level_bounds[i];
+            let mut newpos = level_bounds[i + 1].0;
+            while pos < end {
+                let mut node = (BBox::empty(), pos as u64);
+                for _ in 0..node_size {
+                    if pos >= end {
+                        break;
+                    }
+                    node.0.expand(&nodes[pos].0);
+                    pos += 1;
+                }
+                nodes[newpos] = node;
+                newpos += 1;
+            }
+        }
+        PackedRTree {
+            nodes,
+            node_size,
+            level_bounds,
+        }
+    }
+
+    /// node positions of the children of the inner node at `position`
+    fn children(&self, position: usize) -> std::ops::Range<usize> {
+        let level = self
+            .level_bounds
+            .iter()
+            .position(|(start, end)| position >= *start && position < *end)
+            .unwrap();
+        let first = self.nodes[position].1 as usize;
+        first..(first + self.node_size).min(self.level_bounds[level - 1].1)
+    }
+
+    fn is_leaf(&self, position: usize) -> bool {
+        position >= self.level_bounds[0].0
+    }
+
+    /// offsets of the leaves whose bbox intersects `bbox`
+    pub fn search(&self, bbox: &BBox) -> Vec<u64> {
+        let mut offsets = Vec::new();
+        let mut stack = vec![0_usize];
+        while let Some(position) = stack.pop() {
+            if !self.nodes[position].0.intersects(bbox) {
+                continue;
+            }
+            if self.is_leaf(position) {
+                offsets.push(self.nodes[position].1);
+            } else {
+                stack.extend(self.children(position));
+            }
+        }
+        offsets
+    }
+
+    /// offsets and distances of the `k` leaves nearest to something, closest first
+    /// `bbox_distance` must never be more than the `distance` of the leaves inside the bbox
+    pub fn nearest<B, D>(&self, k: usize, bbox_distance: B, mut distance: D) -> Vec<(u64, f64)>
+    where
+        B: Fn(&BBox) -> f64,
+        D: FnMut(u64) -> f64,
+    {
+        let mut found = Vec::new();
+        let mut queue = BinaryHeap::new();
+        queue.push(Candidate {
+            distance: bbox_distance(&self.nodes[0].0),
+            position: 0,
+            leaf: false,
+        });
+        while let Some(candidate) = queue.pop() {
+            if found.len() >= k {
+                break;
+            }
+            let position = candidate.position;
+            if candidate.leaf {
+                found.push((self.nodes[position].1, candidate.distance));
+            } else if self.is_leaf(position) {
+                queue.push(Candidate {
+                    distance: distance(self.nodes[position].1),
+                    position,
+                    leaf: true,
+                });
+            } else {
+                for child in self.children(position) {
+                    queue.push(Candidate {
+                        distance: bbox_distance(&self.nodes[child].0),
+                        position: child,
+                        leaf: false,
+                    });
+                }
+            }
+        }
+        found
+    }
+
+    /// serialized tree, 40 bytes per node (min_x, min_y, max_x, max_y as f64 and offset as u64, little endian)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.nodes.len() * 40);
+        for (bbox, offset) in &self.nodes {
+            for v in &[bbox.min_x, bbox.min_y, bbox.max_x, bbox.max_y] {
+                out.extend_from_slice(&v.to_le_bytes());
+            }
+            out.extend_from_slice(&offset.to_le_bytes());
+        }
+        out
+    }
+}
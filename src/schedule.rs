@@ -0,0 +1,428 @@
+//! Service information of public transports from the `interval`, `interval:conditional`, `duration`
+//! and `opening_hours` tags, https://wiki.openstreetmap.org/wiki/Key:interval
+//!
+//! Only the common subset of the opening_hours syntax is supported: rules separated by `;` with
+//! an optional day selector (`Mo-Fr`, `Sa,Su`, `PH`), and time ranges (`06:00-22:00,23:00-01:00`),
+//! `off` / `closed` or nothing for the whole day, and `24/7`
+
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+
+use crate::parser::parse_status::ParseIssue;
+
+/// minutes in a day
+const DAY: u32 = 24 * 60;
+
+/// Day of the week, or public holiday
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Day {
+    Mo,
+    Tu,
+    We,
+    Th,
+    Fr,
+    Sa,
+    Su,
+    Ph,
+}
+
+impl Day {
+    /// the days of the week, Monday first, and the public holidays
+    pub const ALL: [Day; 8] = [
+        Day::Mo,
+        Day::Tu,
+        Day::We,
+        Day::Th,
+        Day::Fr,
+        Day::Sa,
+        Day::Su,
+        Day::Ph,
+    ];
+
+    /// opening_hours abbreviation
+    pub fn name(self) -> &'static str {
+        match self {
+            Day::Mo => "Mo",
+            Day::Tu => "Tu",
+            Day::We => "We",
+            Day::Th => "Th",
+            Day::Fr => "Fr",
+            Day::Sa => "Sa",
+            Day::Su => "Su",
+            Day::Ph => "PH",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Day> {
+        Day::ALL.iter().copied().find(|day| day.name() == name)
+    }
+}
+
+/// Set of days
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Days(u8);
+
+impl Days {
+    /// every day of the week and the public holidays
+    pub fn all() -> Self {
+        Days(0xff)
+    }
+
+    pub fn contains(self, day: Day) -> bool {
+        self.0 & (1 << day as u8) != 0
+    }
+
+    pub fn insert(&mut self, day: Day) {
+        self.0 |= 1 << day as u8;
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn days(self) -> Vec<Day> {
+        Day::ALL
+            .iter()
+            .copied()
+            .filter(|day| self.contains(*day))
+            .collect()
+    }
+
+    fn to_json(self) -> Value {
+        json!(self.days().iter().map(|day| day.name()).collect::<Vec<_>>())
+    }
+}
+
+/// Time of the day range, in minutes from midnight
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeWindow {
+    pub start: u32,
+    /// after start, over 1440 if the window ends after midnight
+    pub end: u32,
+}
+
+impl TimeWindow {
+    /// the whole day
+    pub fn day() -> Self {
+        TimeWindow { start: 0, end: DAY }
+    }
+
+    /// true if the minute from midnight is in the window
+    pub fn contains(self, minute: u32) -> bool {
+        self.start <= minute && minute < self.end
+    }
+
+    fn to_json(self) -> Value {
+        json!([format_time(self.start), format_time(self.end)])
+    }
+}
+
+/// Days and times when the vehicles run, a rule of the `opening_hours` tag
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServiceTime {
+    pub days: Days,
+    /// empty if the rule is `off` on `days`
+    pub windows: Vec<TimeWindow>,
+}
+
+/// Minutes between vehicles on some days and time of the day, from `interval:conditional`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Headway {
+    pub days: Days,
+    pub window: TimeWindow,
+    pub minutes: f64,
+}
+
+/// Typed service information of a public transport, empty if the tags are not set
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Schedule {
+    /// minutes between vehicles, `interval` tag
+    pub interval: Option<f64>,
+    /// minutes between vehicles that override the interval on some days and times, `interval:conditional` tag
+    pub headways: Vec<Headway>,
+    /// minutes from the first stop to the last one, `duration` tag
+    pub duration: Option<f64>,
+    /// rules of the `opening_hours` tag, the later ones override the earlier ones on their days,
+    /// None if the route runs all the time
+    pub service: Option<Vec<ServiceTime>>,
+}
+
+/// "HH:MM" minutes from midnight, hours can go over 24 for the end of the ranges after midnight
+fn parse_time(value: &str) -> Option<u32> {
+    let (hours, minutes) = value.split_once(':')?;
+    if minutes.len() != 2 {
+        return None;
+    }
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+    if hours > 48 || minutes >= 60 {
+        return None;
+    }
+    Some(hours * 60 + minutes)
+}
+
+fn format_time(minutes: u32) -> String {
+    format!("{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+/// interval or duration value in minutes: "10", "00:10" or "01:30:00"
+fn parse_duration(value: &str) -> Result<f64, String> {
+    let parts: Vec<&str> = value.trim().split(':').collect();
+    let numbers: Option<Vec<u32>> = parts.iter().map(|part| part.parse().ok()).collect();
+    // None if the hours overflow
+    let hours_minutes =
+        |hours: u32, minutes: u32| hours.checked_mul(60).and_then(|h| h.checked_add(minutes));
+    let minutes = match numbers.as_deref() {
+        Some([minutes]) => Some(*minutes as f64),
+        Some([hours, minutes]) if *minutes < 60 => {
+            hours_minutes(*hours, *minutes).map(|minutes| minutes as f64)
+        }
+        Some([hours, minutes, seconds]) if *minutes < 60 && *seconds < 60 => {
+            hours_minutes(*hours, *minutes).map(|minutes| minutes as f64 + *seconds as f64 / 60_f64)
+        }
+        _ => None,
+    };
+    match minutes {
+        Some(minutes) if minutes > 0_f64 => Ok(minutes),
+        _ => Err(format!(
+            "'{}' is not a duration (mm, hh:mm or hh:mm:ss)",
+            value
+        )),
+    }
+}
+
+/// "Mo-Fr,PH" days
+fn parse_days(value: &str) -> Option<Days> {
+    let mut days = Days::default();
+    for selector in value.split(',') {
+        match selector.split_once('-') {
+            Some((from, to)) => {
+                let from = Day::from_name(from).filter(|day| *day != Day::Ph)? as u8;
+                let to = Day::from_name(to).filter(|day| *day != Day::Ph)? as u8;
+                // ranges like Fr-Mo go through the weekend
+                let mut day = from;
+                loop {
+                    days.insert(Day::ALL[day as usize]);
+                    if day == to {
+                        break;
+                    }
+                    day = (day + 1) % 7;
+                }
+            }
+            None => days.insert(Day::from_name(selector)?),
+        }
+    }
+    Some(days)
+}
+
+/// "06:00-09:00,16:00-19:00" time windows
+fn parse_windows(value: &str) -> Option<Vec<TimeWindow>> {
+    value
+        .split(',')
+        .map(|range| {
+            let (start, end) = range.split_once('-')?;
+            let start = parse_time(start).filter(|start| *start < DAY)?;
+            let mut end = parse_time(end)?;
+            if end <= start {
+                end += DAY;
+            }
+            Some(TimeWindow { start, end })
+        })
+        .collect()
+}
+
+/// one opening_hours rule: "Mo-Fr 06:00-22:00", "Sa,Su 07:00-12:00,14:00-20:00", "PH off", "24/7"
+fn parse_rule(value: &str) -> Result<ServiceTime, String> {
+    // "06:00-09:00, 16:00-19:00" is a single time selector
+    let value = value.trim().replace(", ", ",");
+    if value == "24/7" {
+        return Ok(ServiceTime {
+            days: Days::all(),
+            windows: vec![TimeWindow::day()],
+        });
+    }
+    let mut tokens = value.split_whitespace().peekable();
+    let days = match tokens.peek().and_then(|token| parse_days(token)) {
+        Some(days) => {
+            tokens.next();
+            days
+        }
+        None => Days::all(),
+    };
+    let windows = match tokens.next() {
+        None => vec![TimeWindow::day()],
+        Some("off") | Some("closed") => Vec::new(),
+        Some(token) => {
+            parse_windows(token).ok_or_else(|| format!("unsupported rule '{}'", value))?
+        }
+    };
+    if tokens.next().is_some() {
+        return Err(format!("unsupported rule '{}'", value));
+    }
+    Ok(ServiceTime { days, windows })
+}
+
+/// splits at the `;` that are not between parentheses
+fn split_rules(value: &str) -> Vec<&str> {
+    let mut rules = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ';' if depth == 0 => {
+                rules.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    rules.push(&value[start..]);
+    rules
+        .into_iter()
+        .map(|rule| rule.trim())
+        .filter(|rule| !rule.is_empty())
+        .collect()
+}
+
+fn parse_opening_hours(value: &str) -> Result<Vec<ServiceTime>, String> {
+    split_rules(value).into_iter().map(parse_rule).collect()
+}
+
+/// "00:10 @ (Mo-Fr 07:00-09:00); 00:20 @ (Sa,Su)"
+fn parse_conditional(value: &str) -> Result<Vec<Headway>, String> {
+    let mut headways = Vec::new();
+    for rule in split_rules(value) {
+        let (interval, condition) = rule
+            .split_once('@')
+            .ok_or_else(|| format!("'{}' has no @ condition", rule))?;
+        let minutes = parse_duration(interval)?;
+        let condition = condition.trim();
+        let condition = condition
+            .strip_prefix('(')
+            .and_then(|c| c.strip_suffix(')'))
+            .unwrap_or(condition);
+        for service in parse_opening_hours(condition)? {
+            for window in service.windows {
+                headways.push(Headway {
+                    days: service.days,
+                    window,
+                    minutes,
+                });
+            }
+        }
+    }
+    Ok(headways)
+}
+
+impl Schedule {
+    /// schedule of the tags of a public transport, and an InvalidSchedule issue per tag that could not be parsed
+    pub fn from_tags(tags: &HashMap<String, String>) -> (Schedule, Vec<ParseIssue>) {
+        let mut schedule = Schedule::default();
+        let mut issues = Vec::new();
+        let mut parse = |key: &str, parse: &mut dyn FnMut(&str) -> Result<(), String>| {
+            if let Some(value) = tags.get(key) {
+                if let Err(message) = parse(value) {
+                    issues.push(ParseIssue::InvalidSchedule {
+                        key: key.to_string(),
+                        value: value.clone(),
+                        message,
+                    });
+                }
+            }
+        };
+        parse("interval", &mut |value| {
+            schedule.interval = Some(parse_duration(value)?);
+            Ok(())
+        });
+        parse("interval:conditional", &mut |value| {
+            schedule.headways = parse_conditional(value)?;
+            Ok(())
+        });
+        parse("duration", &mut |value| {
+            schedule.duration = Some(parse_duration(value)?);
+            Ok(())
+        });
+        parse("opening_hours", &mut |value| {
+            schedule.service = Some(parse_opening_hours(value)?);
+            Ok(())
+        });
+        (schedule, issues)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &Schedule::default()
+    }
+
+    /// time windows of service on `day`, the whole day if there is no opening_hours
+    pub fn service_windows(&self, day: Day) -> Vec<TimeWindow> {
+        match &self.service {
+            None => vec![TimeWindow::day()],
+            Some(rules) => rules
+                .iter()
+                .rev()
+                .find(|rule| rule.days.contains(day))
+                .map(|rule| rule.windows.clone())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// minutes between vehicles on `day` at `minute` from its midnight (over 1440 for the service after midnight),
+    /// None out of service or without interval
+    pub fn headway_at(&self, day: Day, minute: u32) -> Option<f64> {
+        if !self
+            .service_windows(day)
+            .iter()
+            .any(|window| window.contains(minute))
+        {
+            return None;
+        }
+        self.headways
+            .iter()
+            .rev()
+            .find(|headway| headway.days.contains(day) && headway.window.contains(minute))
+            .map(|headway| headway.minutes)
+            .or(self.interval)
+    }
+
+    /// (time window, minutes between vehicles) of `day` sorted by start, as the GTFS frequencies:
+    /// the conditional headways of the day if there are any, else the interval over the service windows
+    pub fn frequencies(&self, day: Day) -> Vec<(TimeWindow, f64)> {
+        let mut frequencies: Vec<(TimeWindow, f64)> = self
+            .headways
+            .iter()
+            .filter(|headway| headway.days.contains(day))
+            .map(|headway| (headway.window, headway.minutes))
+            .collect();
+        if frequencies.is_empty() {
+            if let Some(interval) = self.interval {
+                frequencies = self
+                    .service_windows(day)
+                    .into_iter()
+                    .map(|window| (window, interval))
+                    .collect();
+            }
+        }
+        frequencies.sort_by_key(|(window, _)| window.start);
+        frequencies
+    }
+
+    /// {"interval", "duration", "headways": [{"days", "window", "minutes"}], "service": [{"days", "windows"}]}
+    pub fn to_json(&self) -> Value {
+        json!({
+            "interval": self.interval,
+            "duration": self.duration,
+            "headways": self.headways.iter().map(|headway| json!({
+                "days": headway.days.to_json(),
+                "window": headway.window.to_json(),
+                "minutes": headway.minutes,
+            })).collect::<Vec<_>>(),
+            "service": self.service.as_ref().map(|rules| rules.iter().map(|rule| json!({
+                "days": rule.days.to_json(),
+                "windows": rule.windows.iter().map(|window| window.to_json()).collect::<Vec<_>>(),
+            })).collect::<Vec<_>>()),
+        })
+    }
+}
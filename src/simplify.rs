@@ -0,0 +1,384 @@
+//! Line and polygon simplification with tolerances in meters
+//!
+//! Coordinates are projected on a local equirectangular plane centered on the mean latitude of the geometry,
+//! good enough for the size of a route or an administrative area
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::geo::Plane;
+use crate::parser::relation::{Area, LonLat, PublicTransport};
+use crate::rtree::{hilbert_order, BBox, PackedRTree};
+
+const NODE_SIZE: usize = 16;
+
+/// Simplification algorithm
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    /// removes the vertices closer than the tolerance to the simplified line
+    DouglasPeucker,
+    /// removes the vertices that form triangles smaller than tolerance² with their neighbours
+    Visvalingam,
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dp" | "douglas-peucker" => Ok(Algorithm::DouglasPeucker),
+            "vw" | "visvalingam" => Ok(Algorithm::Visvalingam),
+            _ => Err(format!("unknown simplification algorithm '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Algorithm::DouglasPeucker => write!(f, "douglas-peucker"),
+            Algorithm::Visvalingam => write!(f, "visvalingam"),
+        }
+    }
+}
+
+type Point = (f64, f64);
+
+fn segment_distance(p: Point, a: Point, b: Point) -> f64 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let len2 = dx * dx + dy * dy;
+    if len2 == 0.0 {
+        return ((p.0 - a.0).powi(2) + (p.1 - a.1).powi(2)).sqrt();
+    }
+    let t = (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / len2).clamp(0.0, 1.0);
+    ((p.0 - a.0 - t * dx).powi(2) + (p.1 - a.1 - t * dy).powi(2)).sqrt()
+}
+
+/// vertices kept by douglas-peucker, the `fixed` ones (and both ends) are always kept
+fn douglas_peucker_keep(points: &[Point], tolerance: f64, fixed: &[bool]) -> Vec<bool> {
+    let mut keep = fixed.to_vec();
+    if points.len() < 3 {
+        return vec![true; points.len()];
+    }
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    // each pair of consecutive kept vertices is simplified on its own
+    let mut stack: Vec<(usize, usize)> = Vec::new();
+    let mut first = 0;
+    for (i, k) in keep.iter().enumerate().skip(1) {
+        if *k {
+            stack.push((first, i));
+            first = i;
+        }
+    }
+    while let Some((first, last)) = stack.pop() {
+        let mut max = 0.0;
+        let mut index = first;
+        for i in first + 1..last {
+            let d = segment_distance(points[i], points[first], points[last]);
+            if d > max {
+                max = d;
+                index = i;
+            }
+        }
+        if max > tolerance {
+            keep[index] = true;
+            stack.push((first, index));
+            stack.push((index, last));
+        }
+    }
+    keep
+}
+
+/// douglas-peucker on plane coordinates, as used by the vector tiles
+pub(crate) fn douglas_peucker(points: &[Point], tolerance: f64) -> Vec<Point> {
+    let keep = douglas_peucker_keep(points, tolerance, &vec![false; points.len()]);
+    filter(points, &keep)
+}
+
+fn triangle_area(a: Point, b: Point, c: Point) -> f64 {
+    ((b.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (b.1 - a.1)).abs() / 2.0
+}
+
+/// entry of the visvalingam heap, smallest area first
+struct Candidate {
+    area: f64,
+    index: usize,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.area == other.area && self.index == other.index
+    }
+}
+impl Eq for Candidate {}
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .area
+            .total_cmp(&self.area)
+            .then(other.index.cmp(&self.index))
+    }
+}
+
+/// vertices kept by visvalingam-whyatt, removing triangles smaller than `min_area`
+/// the `fixed` ones (and both ends) are always kept
+fn visvalingam_keep(points: &[Point], min_area: f64, fixed: &[bool]) -> Vec<bool> {
+    let n = points.len();
+    let mut keep = vec![true; n];
+    if n < 3 {
+        return keep;
+    }
+    let mut prev: Vec<usize> = (0..n).map(|i| i.wrapping_sub(1)).collect();
+    let mut next: Vec<usize> = (1..=n).collect();
+    let mut areas = vec![f64::INFINITY; n];
+    let mut heap = BinaryHeap::new();
+    for i in 1..n - 1 {
+        if !fixed[i] {
+            areas[i] = triangle_area(points[i - 1], points[i], points[i + 1]);
+            heap.push(Candidate {
+                area: areas[i],
+                index: i,
+            });
+        }
+    }
+    while let Some(Candidate { area, index }) = heap.pop() {
+        if !keep[index] || area != areas[index] {
+            // removed or outdated entry
+            continue;
+        }
+        if area >= min_area {
+            break;
+        }
+        keep[index] = false;
+        let (p, q) = (prev[index], next[index]);
+        next[p] = q;
+        prev[q] = p;
+        for i in [p, q] {
+            if i == 0 || i == n - 1 || fixed[i] {
+                continue;
+            }
+            // the area of a neighbour never gets smaller than the one just removed
+            areas[i] = triangle_area(points[prev[i]], points[i], points[next[i]]).max(area);
+            heap.push(Candidate {
+                area: areas[i],
+                index: i,
+            });
+        }
+    }
+    keep
+}
+
+fn filter<T: Copy>(points: &[T], keep: &[bool]) -> Vec<T> {
+    points
+        .iter()
+        .zip(keep)
+        .filter(|(_, k)| **k)
+        .map(|(p, _)| *p)
+        .collect()
+}
+
+fn keep(algorithm: Algorithm, points: &[Point], tolerance: f64, fixed: &[bool]) -> Vec<bool> {
+    match algorithm {
+        Algorithm::DouglasPeucker => douglas_peucker_keep(points, tolerance, fixed),
+        Algorithm::Visvalingam => visvalingam_keep(points, tolerance * tolerance, fixed),
+    }
+}
+
+/// simplified `line`, the vertices at the `fixed` coordinates are kept
+pub fn simplify_line(
+    line: &[LonLat],
+    algorithm: Algorithm,
+    tolerance: f64,
+    fixed: &[LonLat],
+) -> Vec<LonLat> {
+    filter(line, &simplify_line_keep(line, algorithm, tolerance, fixed))
+}
+
+/// vertices of `line` kept by simplify_line()
+fn simplify_line_keep(
+    line: &[LonLat],
+    algorithm: Algorithm,
+    tolerance: f64,
+    fixed: &[LonLat],
+) -> Vec<bool> {
+    let plane = Plane::new(line.iter());
+    let points: Vec<Point> = line.iter().map(|p| plane.project(p)).collect();
+    let fixed: HashSet<(u64, u64)> = fixed
+        .iter()
+        .map(|p| (p.0.to_bits(), p.1.to_bits()))
+        .collect();
+    let fixed_mask: Vec<bool> = line
+        .iter()
+        .map(|p| fixed.contains(&(p.0.to_bits(), p.1.to_bits())))
+        .collect();
+    keep(algorithm, &points, tolerance, &fixed_mask)
+}
+
+fn simplify_ring(ring: &[Point], algorithm: Algorithm, tolerance: f64) -> Vec<bool> {
+    // split the ring at its farthest vertex from the first one, so it can not collapse into a line
+    let mut fixed = vec![false; ring.len()];
+    let farthest = (0..ring.len())
+        .max_by(|a, b| {
+            let d = |i: usize| (ring[i].0 - ring[0].0).hypot(ring[i].1 - ring[0].1);
+            d(*a).total_cmp(&d(*b))
+        })
+        .unwrap_or(0);
+    fixed[farthest] = true;
+    keep(algorithm, ring, tolerance, &fixed)
+}
+
+fn orientation(a: Point, b: Point, c: Point) -> f64 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+fn on_segment(p: Point, a: Point, b: Point) -> bool {
+    p.0 >= a.0.min(b.0) && p.0 <= a.0.max(b.0) && p.1 >= a.1.min(b.1) && p.1 <= a.1.max(b.1)
+}
+
+fn segments_intersect(a: Point, b: Point, c: Point, d: Point) -> bool {
+    let (o1, o2) = (orientation(a, b, c), orientation(a, b, d));
+    let (o3, o4) = (orientation(c, d, a), orientation(c, d, b));
+    if ((o1 > 0.0 && o2 < 0.0) || (o1 < 0.0 && o2 > 0.0))
+        && ((o3 > 0.0 && o4 < 0.0) || (o3 < 0.0 && o4 > 0.0))
+    {
+        return true;
+    }
+    (o1 == 0.0 && on_segment(c, a, b))
+        || (o2 == 0.0 && on_segment(d, a, b))
+        || (o3 == 0.0 && on_segment(a, c, d))
+        || (o4 == 0.0 && on_segment(b, c, d))
+}
+
+/// true if the rings are valid: closed, at least 4 vertices, and the `changed` segments
+/// (the ones that replace removed vertices) do not cross any other segment,
+/// the segments are looked up in an R-tree so only the ones near a changed segment are checked
+fn valid_rings(rings: &[Vec<Point>], changed: &[Vec<bool>]) -> bool {
+    if rings.iter().any(|r| r.len() < 4 || r[0] != r[r.len() - 1]) {
+        return false;
+    }
+    let segments: Vec<(usize, usize, Point, Point)> = rings
+        .iter()
+        .enumerate()
+        .flat_map(|(ri, r)| {
+            r.windows(2)
+                .enumerate()
+                .map(move |(si, s)| (ri, si, s[0], s[1]))
+        })
+        .collect();
+    let bbox = |a: Point, b: Point| BBox {
+        min_x: a.0.min(b.0),
+        min_y: a.1.min(b.1),
+        max_x: a.0.max(b.0),
+        max_y: a.1.max(b.1),
+    };
+    let boxes: Vec<BBox> = segments.iter().map(|(_, _, a, b)| bbox(*a, *b)).collect();
+    let items: Vec<(BBox, u64)> = hilbert_order(&boxes)
+        .into_iter()
+        .map(|i| (boxes[i], i as u64))
+        .collect();
+    let tree = PackedRTree::build(&items, NODE_SIZE);
+    for (i, (ri, si, a, b)) in segments.iter().enumerate() {
+        if !changed[*ri][*si] {
+            continue;
+        }
+        for j in tree.search(&boxes[i]) {
+            let (rj, sj, c, d) = segments[j as usize];
+            if j as usize == i {
+                continue;
+            }
+            if *ri == rj {
+                let last = rings[*ri].len() - 2;
+                // consecutive segments share a vertex
+                if sj + 1 == *si || *si + 1 == sj || (sj.min(*si) == 0 && sj.max(*si) == last) {
+                    continue;
+                }
+            }
+            if segments_intersect(*a, *b, c, d) {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// for each segment of the ring simplified with `keep`, true if it replaces removed vertices
+fn changed_segments(keep: &[bool]) -> Vec<bool> {
+    let kept: Vec<usize> = (0..keep.len()).filter(|i| keep[*i]).collect();
+    kept.windows(2).map(|w| w[1] > w[0] + 1).collect()
+}
+
+/// simplified polygon `rings` (closed), the topology is preserved:
+/// rings stay closed and the simplification does not make them cross themselves nor each other,
+/// the tolerance is halved until that holds, the original rings are returned if it never does
+pub fn simplify_polygon(
+    rings: &[Vec<LonLat>],
+    algorithm: Algorithm,
+    tolerance: f64,
+) -> Vec<Vec<LonLat>> {
+    if rings.iter().any(|r| r.len() < 4 || r[0] != r[r.len() - 1]) {
+        // not a valid polygon to begin with
+        return rings.to_vec();
+    }
+    let plane = Plane::new(rings.iter().flatten());
+    let points: Vec<Vec<Point>> = rings
+        .iter()
+        .map(|r| r.iter().map(|p| plane.project(p)).collect())
+        .collect();
+    let mut tolerance = tolerance;
+    for _ in 0..8 {
+        let keeps: Vec<Vec<bool>> = points
+            .iter()
+            .map(|r| simplify_ring(r, algorithm, tolerance))
+            .collect();
+        let simplified: Vec<Vec<Point>> = points
+            .iter()
+            .zip(&keeps)
+            .map(|(r, k)| filter(r, k))
+            .collect();
+        let changed: Vec<Vec<bool>> = keeps.iter().map(|k| changed_segments(k)).collect();
+        if valid_rings(&simplified, &changed) {
+            return rings
+                .iter()
+                .zip(&keeps)
+                .map(|(r, k)| filter(r, k))
+                .collect();
+        }
+        tolerance /= 2.0;
+    }
+    rings.to_vec()
+}
+
+impl PublicTransport {
+    /// simplifies the geometry with a `tolerance` in meters,
+    /// the vertices where the stops are (stop positions on the line) are kept,
+    /// the way of each simplified segment is the way of its first original segment
+    pub fn simplify(&mut self, algorithm: Algorithm, tolerance: f64) {
+        let stops: Vec<LonLat> = self.stops.iter().map(|s| (s.lon, s.lat)).collect();
+        for (i, part) in self.geometry.iter_mut().enumerate() {
+            let keep = simplify_line_keep(part, algorithm, tolerance, &stops);
+            if let Some(ways) = self
+                .segment_ways
+                .get_mut(i)
+                .filter(|w| w.len() == keep.len())
+            {
+                *ways = filter(ways, &keep);
+            }
+            *part = filter(part, &keep);
+        }
+    }
+}
+
+impl Area {
+    /// simplifies the geometry with a `tolerance` in meters, see simplify_polygon()
+    pub fn simplify(&mut self, algorithm: Algorithm, tolerance: f64) {
+        self.geometry = simplify_polygon(&self.geometry, algorithm, tolerance);
+    }
+}
@@ -0,0 +1,187 @@
+//! Registry of the stops of all the parsed public transports: each node once with the routes that serve it,
+//! grouped into stations by the public_transport=stop_area relations
+
+use std::collections::{BTreeSet, HashMap};
+
+use serde_json::{json, Value};
+
+use crate::parser::relation::{Node, PublicTransport};
+use crate::parser::stop_area::StopArea;
+
+/// Stop node (stop position or platform) with the public transports that serve it
+#[derive(Clone, Debug)]
+pub struct Stop {
+    pub node: Node,
+    /// ids of the public transports with this node as a stop, sorted
+    pub routes: Vec<u64>,
+    /// id of the stop area containing the node, the lowest one if there are several
+    pub stop_area: Option<u64>,
+}
+
+/// Stops deduplicated across public transports
+pub struct StopRegistry {
+    stops: Vec<Stop>,
+    positions: HashMap<u64, usize>,
+    stop_areas: Vec<StopArea>,
+    /// stop area position of each node member
+    node_stop_areas: HashMap<u64, usize>,
+    /// positions in stops of the stops of each stop area
+    stop_area_stops: HashMap<u64, Vec<usize>>,
+    /// ref tag of each public transport
+    refs: HashMap<u64, String>,
+}
+
+impl StopRegistry {
+    /// empty registry, the stop areas group the stops added later
+    pub fn new(stop_areas: Vec<StopArea>) -> Self {
+        let mut stop_areas = stop_areas;
+        stop_areas.sort_by_key(|stop_area| stop_area.id);
+        let mut node_stop_areas = HashMap::new();
+        for (i, stop_area) in stop_areas.iter().enumerate() {
+            for node_id in stop_area.node_ids() {
+                node_stop_areas.entry(node_id).or_insert(i);
+            }
+        }
+        StopRegistry {
+            stops: Vec::new(),
+            positions: HashMap::new(),
+            stop_areas,
+            node_stop_areas,
+            stop_area_stops: HashMap::new(),
+            refs: HashMap::new(),
+        }
+    }
+
+    /// adds the stops of `pt`, the ones already found in other public transports are not duplicated
+    pub fn add_public_transport(&mut self, pt: &PublicTransport) {
+        if let Some(route_ref) = pt.route_ref() {
+            self.refs.insert(pt.id, route_ref.to_string());
+        }
+        for node in &pt.stops {
+            let position = match self.positions.get(&node.id) {
+                Some(position) => *position,
+                None => {
+                    let position = self.stops.len();
+                    let stop_area = self
+                        .node_stop_areas
+                        .get(&node.id)
+                        .map(|i| self.stop_areas[*i].id);
+                    if let Some(stop_area) = stop_area {
+                        self.stop_area_stops
+                            .entry(stop_area)
+                            .or_default()
+                            .push(position);
+                    }
+                    self.positions.insert(node.id, position);
+                    self.stops.push(Stop {
+                        node: node.clone(),
+                        routes: Vec::new(),
+                        stop_area,
+                    });
+                    position
+                }
+            };
+            let routes = &mut self.stops[position].routes;
+            if let Err(i) = routes.binary_search(&pt.id) {
+                routes.insert(i, pt.id);
+            }
+        }
+    }
+
+    /// stops in the order they were found
+    pub fn stops(&self) -> &[Stop] {
+        &self.stops
+    }
+
+    pub fn len(&self) -> usize {
+        self.stops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stops.is_empty()
+    }
+
+    pub fn stop(&self, node_id: u64) -> Option<&Stop> {
+        self.positions.get(&node_id).map(|i| &self.stops[*i])
+    }
+
+    /// ids of the public transports that stop at the node, empty if it is not a stop
+    pub fn routes_serving(&self, node_id: u64) -> &[u64] {
+        self.stop(node_id)
+            .map(|stop| stop.routes.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// all the stop areas, sorted by id
+    pub fn stop_areas(&self) -> &[StopArea] {
+        &self.stop_areas
+    }
+
+    /// stop area that groups the node with other stops
+    pub fn stop_area_of(&self, node_id: u64) -> Option<&StopArea> {
+        self.node_stop_areas
+            .get(&node_id)
+            .map(|i| &self.stop_areas[*i])
+    }
+
+    /// stops of the stop area `stop_area_id` served by some public transport
+    pub fn stop_area_stops(&self, stop_area_id: u64) -> Vec<&Stop> {
+        self.stop_area_stops
+            .get(&stop_area_id)
+            .map(|positions| positions.iter().map(|i| &self.stops[*i]).collect())
+            .unwrap_or_default()
+    }
+
+    /// ids of the public transports that stop at any stop of the stop area, sorted
+    pub fn stop_area_routes(&self, stop_area_id: u64) -> Vec<u64> {
+        self.stop_area_stops(stop_area_id)
+            .iter()
+            .flat_map(|stop| stop.routes.iter().cloned())
+            .collect::<BTreeSet<u64>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// ref tags of the public transports `routes`, sorted and without duplicates
+    pub fn route_refs(&self, routes: &[u64]) -> Vec<String> {
+        routes
+            .iter()
+            .filter_map(|id| self.refs.get(id).cloned())
+            .collect::<BTreeSet<String>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// geojson FeatureCollection with a point per stop,
+    /// with the ids and refs of the routes serving it and its stop area
+    pub fn to_geojson(&self) -> Value {
+        let features: Vec<Value> = self
+            .stops
+            .iter()
+            .map(|stop| {
+                let stop_area = self.stop_area_of(stop.node.id);
+                json!({
+                    "type": "Feature",
+                    "geometry": {
+                        "type": "Point",
+                        "coordinates": [stop.node.lon, stop.node.lat],
+                    },
+                    "properties": {
+                        "id": stop.node.id,
+                        "tags": stop.node.tags,
+                        "routes": stop.routes,
+                        "route_refs": self.route_refs(&stop.routes),
+                        "stop_area": stop_area.map(|stop_area| json!({
+                            "id": stop_area.id,
+                            "name": stop_area.tags.get("name"),
+                        })),
+                    },
+                })
+            })
+            .collect();
+        json!({
+            "type": "FeatureCollection",
+            "features": features,
+        })
+    }
+}
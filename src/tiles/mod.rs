@@ -0,0 +1,407 @@
+//! Mapbox Vector Tiles of the public transport network, written as MBTiles or as a z/x/y directory
+mod mvt;
+
+use std::collections::{BTreeSet, HashMap};
+use std::f64::consts::PI;
+use std::io::Write;
+use std::path::Path;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rusqlite::{params, Connection};
+use serde_json::json;
+
+use crate::parser::relation::PublicTransport;
+use crate::simplify::douglas_peucker;
+use crate::writer::WriteError;
+use mvt::{Feature, Value};
+
+/// highest zoom level TileSet accepts, tile numbers at z are 0..2^z in a u32
+pub const MAX_ZOOM: u8 = 24;
+
+/// Tile generation options
+#[derive(Clone, Debug)]
+pub struct TileOptions {
+    pub min_zoom: u8,
+    pub max_zoom: u8,
+    /// tile size in tile coordinates
+    pub extent: u32,
+    /// extra space around each tile where lines are kept, in tile coordinates
+    pub buffer: u32,
+    /// douglas-peucker tolerance applied on each zoom level, in tile coordinates
+    pub simplify: f64,
+}
+
+impl Default for TileOptions {
+    fn default() -> Self {
+        TileOptions {
+            min_zoom: 0,
+            max_zoom: 14,
+            extent: 4096,
+            buffer: 64,
+            simplify: 1.0,
+        }
+    }
+}
+
+type Point = (f64, f64);
+
+struct Route {
+    id: u64,
+    properties: Vec<(&'static str, Value)>,
+    /// web mercator coordinates in the 0..1 range
+    geometry: Vec<Vec<Point>>,
+}
+
+struct Stop {
+    name: Option<String>,
+    /// web mercator coordinates in the 0..1 range
+    point: Point,
+    refs: BTreeSet<String>,
+}
+
+/// Collects public transports and then generates the tiles with a `routes` and a `stops` layer
+/// Stops are deduplicated by osm id, with the refs of all the routes that use them
+pub struct TileSet {
+    options: TileOptions,
+    routes: Vec<Route>,
+    stops: HashMap<u64, Stop>,
+    /// (min_lon, min_lat, max_lon, max_lat) of everything added
+    bounds: Option<(f64, f64, f64, f64)>,
+}
+
+/// lon/lat to web mercator in the 0..1 range, (0, 0) is the top left corner
+fn world(lon: f64, lat: f64) -> Point {
+    let lat = lat.clamp(-85.051_128_78, 85.051_128_78);
+    let sin = lat.to_radians().sin();
+    let x = (lon + 180.0) / 360.0;
+    let y = 0.5 - ((1.0 + sin) / (1.0 - sin)).ln() / (4.0 * PI);
+    (x, y)
+}
+
+/// Liang-Barsky clipping of the segment a-b to the rectangle, None if it is outside
+fn clip_segment(a: Point, b: Point, min: Point, max: Point) -> Option<(Point, Point)> {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let mut t0 = 0.0_f64;
+    let mut t1 = 1.0_f64;
+    for (p, q) in &[
+        (-dx, a.0 - min.0),
+        (dx, max.0 - a.0),
+        (-dy, a.1 - min.1),
+        (dy, max.1 - a.1),
+    ] {
+        if *p == 0.0 {
+            if *q < 0.0 {
+                return None;
+            }
+        } else {
+            let r = q / p;
+            if *p < 0.0 {
+                t0 = t0.max(r);
+            } else {
+                t1 = t1.min(r);
+            }
+        }
+    }
+    if t0 > t1 {
+        return None;
+    }
+    Some((
+        (a.0 + t0 * dx, a.1 + t0 * dy),
+        (a.0 + t1 * dx, a.1 + t1 * dy),
+    ))
+}
+
+/// clips a linestring to the rectangle, it may result in several parts
+fn clip_line(line: &[Point], min: Point, max: Point) -> Vec<Vec<Point>> {
+    let mut parts: Vec<Vec<Point>> = Vec::new();
+    let mut current: Vec<Point> = Vec::new();
+    for w in line.windows(2) {
+        match clip_segment(w[0], w[1], min, max) {
+            Some((a, b)) => {
+                if current.last() != Some(&a) {
+                    if current.len() > 1 {
+                        parts.push(current);
+                    }
+                    current = vec![a];
+                }
+                current.push(b);
+            }
+            None => {
+                if current.len() > 1 {
+                    parts.push(current);
+                }
+                current = Vec::new();
+            }
+        }
+    }
+    if current.len() > 1 {
+        parts.push(current);
+    }
+    parts
+}
+
+/// rounds to tile coordinates relative to `origin`, removing repeated points
+fn to_tile_coords(line: &[Point], origin: Point) -> Vec<(i32, i32)> {
+    let mut coords: Vec<(i32, i32)> = Vec::with_capacity(line.len());
+    for p in line {
+        let c = (
+            (p.0 - origin.0).round() as i32,
+            (p.1 - origin.1).round() as i32,
+        );
+        if coords.last() != Some(&c) {
+            coords.push(c);
+        }
+    }
+    coords
+}
+
+impl TileSet {
+    /// Invalid error if max_zoom is over MAX_ZOOM or min_zoom is over max_zoom
+    pub fn new(options: TileOptions) -> Result<Self, WriteError> {
+        if options.max_zoom > MAX_ZOOM {
+            return Err(WriteError::Invalid(format!(
+                "max zoom {} is over {}",
+                options.max_zoom, MAX_ZOOM
+            )));
+        }
+        if options.min_zoom > options.max_zoom {
+            return Err(WriteError::Invalid(format!(
+                "min zoom {} is over max zoom {}",
+                options.min_zoom, options.max_zoom
+            )));
+        }
+        Ok(TileSet {
+            options,
+            routes: Vec::new(),
+            stops: HashMap::new(),
+            bounds: None,
+        })
+    }
+
+    fn extend_bounds(&mut self, lon: f64, lat: f64) {
+        self.bounds = Some(match self.bounds {
+            None => (lon, lat, lon, lat),
+            Some((minx, miny, maxx, maxy)) => {
+                (minx.min(lon), miny.min(lat), maxx.max(lon), maxy.max(lat))
+            }
+        });
+    }
+
+    /// adds the geometry of `pt` to the routes layer and its stops to the stops layer
+    pub fn add_public_transport(&mut self, pt: &PublicTransport) {
+        let mut properties = vec![("id", Value::UInt(pt.id))];
+        for key in &["ref", "name", "colour", "route", "network"] {
+            if let Some(value) = pt.tags.get(*key) {
+                properties.push((key, Value::String(value.clone())));
+            }
+        }
+        for (lon, lat) in pt.geometry.iter().flatten() {
+            self.extend_bounds(*lon, *lat);
+        }
+        let geometry = pt
+            .geometry
+            .iter()
+            .filter(|part| part.len() > 1)
+            .map(|part| part.iter().map(|(lon, lat)| world(*lon, *lat)).collect())
+            .collect::<Vec<Vec<Point>>>();
+        if !geometry.is_empty() {
+            self.routes.push(Route {
+                id: pt.id,
+                properties,
+                geometry,
+            });
+        }
+
+        let route_ref = pt.tags.get("ref").cloned();
+        for stop in &pt.stops {
+            self.extend_bounds(stop.lon, stop.lat);
+            let entry = self.stops.entry(stop.id).or_insert_with(|| Stop {
+                name: stop.tags.get("name").cloned(),
+                point: world(stop.lon, stop.lat),
+                refs: BTreeSet::new(),
+            });
+            if let Some(route_ref) = &route_ref {
+                entry.refs.insert(route_ref.clone());
+            }
+        }
+    }
+
+    /// encodes all the tiles of zoom `z`, tiles are (x, y, mvt bytes) in XYZ scheme
+    fn zoom_tiles(&self, z: u8) -> Vec<(u32, u32, Vec<u8>)> {
+        let extent = f64::from(self.options.extent);
+        let buffer = f64::from(self.options.buffer);
+        let tiles_count = 1u32 << z;
+        let scale = f64::from(tiles_count) * extent;
+        let max_tile = f64::from(tiles_count - 1);
+        let tile_of = |v: f64| (v / extent).floor().max(0.0).min(max_tile) as u32;
+        let mut tiles: HashMap<(u32, u32), (Vec<Feature>, Vec<Feature>)> = HashMap::new();
+
+        for route in &self.routes {
+            let lines: Vec<Vec<Point>> = route
+                .geometry
+                .iter()
+                .map(|part| {
+                    let scaled: Vec<Point> =
+                        part.iter().map(|p| (p.0 * scale, p.1 * scale)).collect();
+                    douglas_peucker(&scaled, self.options.simplify)
+                })
+                .collect();
+            let (mut minx, mut miny) = (f64::INFINITY, f64::INFINITY);
+            let (mut maxx, mut maxy) = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+            for p in lines.iter().flatten() {
+                minx = minx.min(p.0);
+                miny = miny.min(p.1);
+                maxx = maxx.max(p.0);
+                maxy = maxy.max(p.1);
+            }
+            for tx in tile_of(minx - buffer)..=tile_of(maxx + buffer) {
+                for ty in tile_of(miny - buffer)..=tile_of(maxy + buffer) {
+                    let origin = (f64::from(tx) * extent, f64::from(ty) * extent);
+                    let min = (origin.0 - buffer, origin.1 - buffer);
+                    let max = (origin.0 + extent + buffer, origin.1 + extent + buffer);
+                    let geometry: Vec<Vec<(i32, i32)>> = lines
+                        .iter()
+                        .flat_map(|line| clip_line(line, min, max))
+                        .map(|part| to_tile_coords(&part, origin))
+                        .filter(|part| part.len() > 1)
+                        .collect();
+                    if !geometry.is_empty() {
+                        tiles.entry((tx, ty)).or_default().0.push(Feature {
+                            id: route.id,
+                            geometry_type: mvt::LINESTRING,
+                            geometry,
+                            properties: route.properties.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        for (id, stop) in &self.stops {
+            let p = (stop.point.0 * scale, stop.point.1 * scale);
+            let (tx, ty) = (tile_of(p.0), tile_of(p.1));
+            let origin = (f64::from(tx) * extent, f64::from(ty) * extent);
+            let mut properties = vec![("id", Value::UInt(*id))];
+            if let Some(name) = &stop.name {
+                properties.push(("name", Value::String(name.clone())));
+            }
+            if !stop.refs.is_empty() {
+                let refs: Vec<&str> = stop.refs.iter().map(String::as_str).collect();
+                properties.push(("refs", Value::String(refs.join(";"))));
+            }
+            tiles.entry((tx, ty)).or_default().1.push(Feature {
+                id: *id,
+                geometry_type: mvt::POINT,
+                geometry: vec![to_tile_coords(&[p], origin)],
+                properties,
+            });
+        }
+
+        tiles
+            .into_iter()
+            .map(|((x, y), (routes, stops))| {
+                let bytes = mvt::tile(
+                    &[("routes", &routes), ("stops", &stops)],
+                    self.options.extent,
+                );
+                (x, y, bytes)
+            })
+            .collect()
+    }
+
+    /// tilejson like metadata
+    fn metadata(&self) -> Vec<(&'static str, String)> {
+        let (minx, miny, maxx, maxy) = self.bounds.unwrap_or((-180.0, -85.0, 180.0, 85.0));
+        let vector_layers = json!({
+            "vector_layers": [
+                {
+                    "id": "routes",
+                    "fields": {"id": "Number", "ref": "String", "name": "String", "colour": "String", "route": "String", "network": "String"},
+                    "minzoom": self.options.min_zoom,
+                    "maxzoom": self.options.max_zoom,
+                },
+                {
+                    "id": "stops",
+                    "fields": {"id": "Number", "name": "String", "refs": "String"},
+                    "minzoom": self.options.min_zoom,
+                    "maxzoom": self.options.max_zoom,
+                },
+            ]
+        });
+        vec![
+            ("name", "osmptparser".to_string()),
+            ("format", "pbf".to_string()),
+            ("type", "overlay".to_string()),
+            ("minzoom", self.options.min_zoom.to_string()),
+            ("maxzoom", self.options.max_zoom.to_string()),
+            ("bounds", format!("{},{},{},{}", minx, miny, maxx, maxy)),
+            (
+                "center",
+                format!(
+                    "{},{},{}",
+                    (minx + maxx) / 2.0,
+                    (miny + maxy) / 2.0,
+                    self.options.min_zoom
+                ),
+            ),
+            ("json", vector_layers.to_string()),
+        ]
+    }
+
+    /// writes the tiles into an MBTiles (sqlite) file at `path`, tiles are gzip compressed
+    pub fn write_mbtiles(&self, path: &Path) -> Result<(), WriteError> {
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE metadata (name TEXT, value TEXT);
+            CREATE TABLE tiles (zoom_level INTEGER, tile_column INTEGER, tile_row INTEGER, tile_data BLOB);
+            CREATE UNIQUE INDEX tile_index ON tiles (zoom_level, tile_column, tile_row);
+            BEGIN;",
+        )?;
+        for (name, value) in self.metadata() {
+            conn.execute(
+                "INSERT INTO metadata (name, value) VALUES (?1, ?2)",
+                params![name, value],
+            )?;
+        }
+        for z in self.options.min_zoom..=self.options.max_zoom {
+            for (x, y, bytes) in self.zoom_tiles(z) {
+                let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+                gz.write_all(&bytes)?;
+                // mbtiles uses the TMS scheme, y goes from bottom to top
+                let tms_y = (1u32 << z) - 1 - y;
+                conn.prepare_cached(
+                    "INSERT INTO tiles (zoom_level, tile_column, tile_row, tile_data) VALUES (?1, ?2, ?3, ?4)",
+                )?
+                .execute(params![z, x, tms_y, gz.finish()?])?;
+            }
+        }
+        conn.execute_batch("COMMIT")?;
+        Ok(())
+    }
+
+    /// writes the tiles as `path`/z/x/y.pbf files (uncompressed) plus a `path`/metadata.json
+    pub fn write_directory(&self, path: &Path) -> Result<(), WriteError> {
+        for z in self.options.min_zoom..=self.options.max_zoom {
+            for (x, y, bytes) in self.zoom_tiles(z) {
+                let dir = path.join(z.to_string()).join(x.to_string());
+                std::fs::create_dir_all(&dir)?;
+                std::fs::write(dir.join(format!("{}.pbf", y)), bytes)?;
+            }
+        }
+        std::fs::create_dir_all(path)?;
+        let metadata: serde_json::Map<String, serde_json::Value> = self
+            .metadata()
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), serde_json::Value::String(v)))
+            .collect();
+        std::fs::write(
+            path.join("metadata.json"),
+            serde_json::Value::Object(metadata).to_string(),
+        )?;
+        Ok(())
+    }
+}
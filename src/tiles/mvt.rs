@@ -0,0 +1,155 @@
+//! Minimal Mapbox Vector Tile encoder
+//! https://github.com/mapbox/vector-tile-spec/blob/master/2.1/vector_tile.proto
+
+use std::collections::HashMap;
+
+pub const POINT: u32 = 1;
+pub const LINESTRING: u32 = 2;
+
+const MOVE_TO: u32 = 1;
+const LINE_TO: u32 = 2;
+
+/// Attribute value of a feature
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Value {
+    String(String),
+    UInt(u64),
+}
+
+/// Feature in tile coordinates (0..extent)
+#[derive(Clone, Debug)]
+pub struct Feature {
+    pub id: u64,
+    pub geometry_type: u32,
+    /// points for POINT (one per part), linestrings for LINESTRING
+    pub geometry: Vec<Vec<(i32, i32)>>,
+    pub properties: Vec<(&'static str, Value)>,
+}
+
+fn varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn key(out: &mut Vec<u8>, field: u32, wire_type: u32) {
+    varint(out, u64::from((field << 3) | wire_type));
+}
+
+fn bytes_field(out: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    key(out, field, 2);
+    varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn varint_field(out: &mut Vec<u8>, field: u32, value: u64) {
+    key(out, field, 0);
+    varint(out, value);
+}
+
+fn packed_field(out: &mut Vec<u8>, field: u32, values: &[u32]) {
+    let mut packed = Vec::with_capacity(values.len());
+    for v in values {
+        varint(&mut packed, u64::from(*v));
+    }
+    bytes_field(out, field, &packed);
+}
+
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+fn command(id: u32, count: usize) -> u32 {
+    (id & 0x7) | ((count as u32) << 3)
+}
+
+/// geometry command stream of a feature, positions are delta encoded from the previous point
+fn geometry_commands(feature: &Feature) -> Vec<u32> {
+    let mut commands = Vec::new();
+    let mut cursor = (0i32, 0i32);
+    let mut push_point = |commands: &mut Vec<u32>, p: (i32, i32)| {
+        commands.push(zigzag(p.0 - cursor.0));
+        commands.push(zigzag(p.1 - cursor.1));
+        cursor = p;
+    };
+    if feature.geometry_type == POINT {
+        let points: Vec<(i32, i32)> = feature.geometry.iter().flatten().cloned().collect();
+        commands.push(command(MOVE_TO, points.len()));
+        for p in points {
+            push_point(&mut commands, p);
+        }
+    } else {
+        for line in &feature.geometry {
+            commands.push(command(MOVE_TO, 1));
+            push_point(&mut commands, line[0]);
+            commands.push(command(LINE_TO, line.len() - 1));
+            for p in &line[1..] {
+                push_point(&mut commands, *p);
+            }
+        }
+    }
+    commands
+}
+
+/// encodes one layer, keys and values are deduplicated inside the layer
+fn layer(name: &str, features: &[Feature], extent: u32) -> Vec<u8> {
+    let mut keys: Vec<&str> = Vec::new();
+    let mut key_index: HashMap<&str, u32> = HashMap::new();
+    let mut values: Vec<&Value> = Vec::new();
+    let mut value_index: HashMap<&Value, u32> = HashMap::new();
+    let mut encoded_features = Vec::with_capacity(features.len());
+    for feature in features {
+        let mut tags = Vec::with_capacity(feature.properties.len() * 2);
+        for (k, v) in &feature.properties {
+            let ki = *key_index.entry(k).or_insert_with(|| {
+                keys.push(k);
+                keys.len() as u32 - 1
+            });
+            let vi = *value_index.entry(v).or_insert_with(|| {
+                values.push(v);
+                values.len() as u32 - 1
+            });
+            tags.push(ki);
+            tags.push(vi);
+        }
+        let mut f = Vec::new();
+        varint_field(&mut f, 1, feature.id);
+        packed_field(&mut f, 2, &tags);
+        varint_field(&mut f, 3, u64::from(feature.geometry_type));
+        packed_field(&mut f, 4, &geometry_commands(feature));
+        encoded_features.push(f);
+    }
+
+    let mut out = Vec::new();
+    varint_field(&mut out, 15, 2);
+    bytes_field(&mut out, 1, name.as_bytes());
+    for f in encoded_features {
+        bytes_field(&mut out, 2, &f);
+    }
+    for k in keys {
+        bytes_field(&mut out, 3, k.as_bytes());
+    }
+    for v in values {
+        let mut value = Vec::new();
+        match v {
+            Value::String(s) => bytes_field(&mut value, 1, s.as_bytes()),
+            Value::UInt(u) => varint_field(&mut value, 5, *u),
+        }
+        bytes_field(&mut out, 4, &value);
+    }
+    varint_field(&mut out, 5, u64::from(extent));
+    out
+}
+
+/// encodes a tile with the given (name, features) layers, empty layers are skipped
+pub fn tile(layers: &[(&str, &[Feature])], extent: u32) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (name, features) in layers {
+        if !features.is_empty() {
+            bytes_field(&mut out, 3, &layer(name, features, extent));
+        }
+    }
+    out
+}
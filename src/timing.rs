@@ -0,0 +1,320 @@
+//! Running time estimates of public transports, from the speed of the vehicles on the ways they travel
+//! (maxspeed or highway class) and a dwell time at each stop, for the cities without timetables
+
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+
+use crate::geo::haversine;
+use crate::parser::relation::PublicTransport;
+
+/// Speeds of a type of vehicle
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpeedProfile {
+    /// speed in km/h by highway value, used on the ways without a usable maxspeed tag
+    pub highway: HashMap<String, f64>,
+    /// speed in km/h on the ways without maxspeed nor a known highway value (rails, gaps)
+    pub default_speed: f64,
+    /// fraction of the maxspeed the vehicles run at, traffic and acceleration included
+    pub maxspeed_factor: f64,
+    /// maximum speed of the vehicles in km/h
+    pub max_speed: f64,
+    /// seconds stopped at each stop, except the first and the last one
+    pub dwell_time: f64,
+}
+
+impl SpeedProfile {
+    fn road(default_speed: f64, max_speed: f64, dwell_time: f64) -> Self {
+        let highway = [
+            ("motorway", 70_f64),
+            ("motorway_link", 50_f64),
+            ("trunk", 55_f64),
+            ("trunk_link", 40_f64),
+            ("primary", 35_f64),
+            ("primary_link", 30_f64),
+            ("secondary", 30_f64),
+            ("secondary_link", 25_f64),
+            ("tertiary", 25_f64),
+            ("tertiary_link", 20_f64),
+            ("unclassified", 20_f64),
+            ("residential", 20_f64),
+            ("busway", 40_f64),
+            ("bus_guideway", 40_f64),
+            ("service", 15_f64),
+            ("living_street", 10_f64),
+        ]
+        .iter()
+        .map(|(highway, speed)| (highway.to_string(), *speed))
+        .collect();
+        SpeedProfile {
+            highway,
+            default_speed,
+            maxspeed_factor: 0.7,
+            max_speed,
+            dwell_time,
+        }
+    }
+
+    fn rail(default_speed: f64, max_speed: f64, dwell_time: f64) -> Self {
+        SpeedProfile {
+            highway: HashMap::new(),
+            default_speed,
+            maxspeed_factor: 0.8,
+            max_speed,
+            dwell_time,
+        }
+    }
+
+    /// speed in km/h on a way with `tags`, None for the parts of the route that are not on a way
+    pub fn speed(&self, tags: Option<&HashMap<String, String>>) -> f64 {
+        let speed = tags
+            .and_then(|tags| tags.get("maxspeed"))
+            .and_then(|maxspeed| parse_maxspeed(maxspeed))
+            .map(|maxspeed| maxspeed * self.maxspeed_factor)
+            .or_else(|| {
+                tags.and_then(|tags| tags.get("highway"))
+                    .and_then(|highway| self.highway.get(highway))
+                    .copied()
+            })
+            .unwrap_or(self.default_speed);
+        speed.min(self.max_speed)
+    }
+
+    /// profile with the fields of `json` that are set replaced
+    fn merge(&self, json: &Value) -> Result<Self, String> {
+        let object = json
+            .as_object()
+            .ok_or_else(|| format!("speed profile must be an object, found {}", json))?;
+        let mut profile = self.clone();
+        for (key, value) in object {
+            let positive = |value: &Value| {
+                value
+                    .as_f64()
+                    .filter(|n| *n > 0_f64)
+                    .ok_or_else(|| format!("{} must be a positive number, found {}", key, value))
+            };
+            match key.as_str() {
+                "default_speed" => profile.default_speed = positive(value)?,
+                "maxspeed_factor" => profile.maxspeed_factor = positive(value)?,
+                "max_speed" => profile.max_speed = positive(value)?,
+                "dwell_time" => {
+                    profile.dwell_time =
+                        value.as_f64().filter(|n| *n >= 0_f64).ok_or_else(|| {
+                            format!("dwell_time must be 0 or a positive number, found {}", value)
+                        })?
+                }
+                "highway" => {
+                    let highways = value
+                        .as_object()
+                        .ok_or_else(|| format!("highway must be an object, found {}", value))?;
+                    for (highway, speed) in highways {
+                        profile.highway.insert(highway.clone(), positive(speed)?);
+                    }
+                }
+                _ => return Err(format!("unknown speed profile field '{}'", key)),
+            }
+        }
+        Ok(profile)
+    }
+}
+
+/// "50", "30 mph" or "50 km/h" in km/h, None for the implicit values ("EC:urban", "walk", "none"...)
+fn parse_maxspeed(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let (number, factor) = match value.strip_suffix("mph") {
+        Some(number) => (number, 1.609_344),
+        None => (value.strip_suffix("km/h").unwrap_or(value), 1_f64),
+    };
+    number
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|speed| *speed > 0_f64)
+        .map(|speed| speed * factor)
+}
+
+/// Speed profiles by route type (value of the `route` tag)
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpeedProfiles {
+    pub profiles: HashMap<String, SpeedProfile>,
+    /// profile of the route types without one
+    pub fallback: SpeedProfile,
+}
+
+impl Default for SpeedProfiles {
+    fn default() -> Self {
+        let profiles = vec![
+            ("bus", SpeedProfile::road(20_f64, 80_f64, 20_f64)),
+            ("trolleybus", SpeedProfile::road(20_f64, 60_f64, 20_f64)),
+            ("tram", SpeedProfile::rail(20_f64, 60_f64, 20_f64)),
+            ("light_rail", SpeedProfile::rail(35_f64, 80_f64, 30_f64)),
+            ("monorail", SpeedProfile::rail(35_f64, 80_f64, 30_f64)),
+            ("subway", SpeedProfile::rail(40_f64, 80_f64, 30_f64)),
+            ("train", SpeedProfile::rail(60_f64, 160_f64, 60_f64)),
+        ]
+        .into_iter()
+        .map(|(route, profile)| (route.to_string(), profile))
+        .collect();
+        SpeedProfiles {
+            profiles,
+            fallback: SpeedProfile::road(20_f64, 80_f64, 20_f64),
+        }
+    }
+}
+
+impl SpeedProfiles {
+    /// profile of the vehicles of a `route` tag value
+    pub fn profile(&self, route: Option<&String>) -> &SpeedProfile {
+        route
+            .and_then(|route| self.profiles.get(route))
+            .unwrap_or(&self.fallback)
+    }
+
+    /// default profiles changed with a json object by route type, for example
+    /// {"bus": {"dwell_time": 30, "highway": {"primary": 40}}, "ferry": {"default_speed": 15}}
+    /// the fields not set keep the default of the route type (or of the fallback for new types)
+    pub fn from_json(json: &Value) -> Result<Self, String> {
+        let object = json
+            .as_object()
+            .ok_or_else(|| format!("speed profiles must be an object, found {}", json))?;
+        let mut profiles = SpeedProfiles::default();
+        for (route, value) in object {
+            let profile = profiles
+                .profiles
+                .get(route)
+                .unwrap_or(&profiles.fallback)
+                .merge(value)
+                .map_err(|e| format!("{}: {}", route, e))?;
+            profiles.profiles.insert(route.clone(), profile);
+        }
+        Ok(profiles)
+    }
+}
+
+/// Estimated running time between two consecutive stops
+#[derive(Clone, Debug, PartialEq)]
+pub struct SegmentTime {
+    /// node id of the first stop
+    pub from: u64,
+    /// node id of the next stop
+    pub to: u64,
+    /// meters, along the route or in a straight line if the route has no geometry
+    pub length: f64,
+    /// seconds
+    pub running_time: f64,
+}
+
+/// Estimated travel time of a public transport along the stops of its segments()
+#[derive(Clone, Debug, PartialEq)]
+pub struct TravelTime {
+    pub segments: Vec<SegmentTime>,
+    /// seconds stopped at each stop, except the first and the last one
+    pub dwell_time: f64,
+    /// seconds, sum of the running time of the segments
+    pub running_time: f64,
+    /// seconds, running time plus the dwell time of the intermediate stops
+    pub total_time: f64,
+}
+
+impl TravelTime {
+    /// (arrival, departure) at each stop in seconds from the departure of the first stop,
+    /// in the order of the segments, empty without segments
+    pub fn stop_times(&self) -> Vec<(f64, f64)> {
+        if self.segments.is_empty() {
+            return Vec::new();
+        }
+        let mut times = vec![(0_f64, 0_f64)];
+        let mut time = 0_f64;
+        for (i, segment) in self.segments.iter().enumerate() {
+            time += segment.running_time;
+            let dwell = if i + 1 < self.segments.len() {
+                self.dwell_time
+            } else {
+                0_f64
+            };
+            times.push((time, time + dwell));
+            time += dwell;
+        }
+        times
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "running_time": self.running_time,
+            "dwell_time": self.dwell_time,
+            "total_time": self.total_time,
+            "segments": self.segments.iter().map(|segment| json!({
+                "from": segment.from,
+                "to": segment.to,
+                "length": segment.length,
+                "running_time": segment.running_time,
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl PublicTransport {
+    /// running time of each of the segments() and total travel time with the profile of the route type,
+    /// the segments without geometry are estimated in a straight line at the default speed
+    pub fn travel_time(&self, profiles: &SpeedProfiles) -> TravelTime {
+        let profile = profiles.profile(self.tags.get("route"));
+        // (start, end, speed in m/s) of each piece of the geometry along the route
+        let mut pieces: Vec<(f64, f64, f64)> = Vec::new();
+        let mut start = 0_f64;
+        for (i, part) in self.geometry.iter().enumerate() {
+            for (j, pair) in part.windows(2).enumerate() {
+                let way_id = self
+                    .segment_ways
+                    .get(i)
+                    .and_then(|ways| ways.get(j))
+                    .copied()
+                    .flatten();
+                let speed = profile.speed(way_id.and_then(|id| self.way_tags.get(&id)));
+                let end = start + haversine(pair[0], pair[1]);
+                pieces.push((start, end, speed / 3.6));
+                start = end;
+            }
+        }
+        let stops: HashMap<u64, (f64, f64)> = self
+            .stops
+            .iter()
+            .map(|stop| (stop.id, (stop.lon, stop.lat)))
+            .collect();
+        let segments: Vec<SegmentTime> = self
+            .segments()
+            .iter()
+            .map(|segment| {
+                let (length, running_time) = if segment.geometry.is_empty() {
+                    let length = haversine(stops[&segment.from], stops[&segment.to]);
+                    (length, length / (profile.default_speed / 3.6))
+                } else {
+                    let (from, to) = if segment.start <= segment.end {
+                        (segment.start, segment.end)
+                    } else {
+                        (segment.end, segment.start)
+                    };
+                    let running_time = pieces
+                        .iter()
+                        .filter(|(start, end, _)| *end > from && *start < to)
+                        .map(|(start, end, speed)| (end.min(to) - start.max(from)) / speed)
+                        .sum();
+                    (segment.length, running_time)
+                };
+                SegmentTime {
+                    from: segment.from,
+                    to: segment.to,
+                    length,
+                    running_time,
+                }
+            })
+            .collect();
+        let running_time: f64 = segments.iter().map(|s| s.running_time).sum();
+        let stops_dwelling = segments.len().saturating_sub(1);
+        TravelTime {
+            segments,
+            dwell_time: profile.dwell_time,
+            running_time,
+            total_time: running_time + profile.dwell_time * stops_dwelling as f64,
+        }
+    }
+}
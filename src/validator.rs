@@ -0,0 +1,646 @@
+//! PTv2 rules checks of public transports, to know what has to be fixed by hand in osm
+//! https://wiki.openstreetmap.org/wiki/Public_transport
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+
+use crate::geo::haversine;
+use crate::parser::parse_status::ParseIssue;
+use crate::parser::relation::{LonLat, Member, MemberType, Node, PublicTransport, StopPosition};
+use crate::writer::csv_field;
+
+/// How bad a finding is, errors break the PTv2 schema, warnings are conventions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// PTv2 rule that was checked
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rule {
+    /// `public_transport:version=2` must be present
+    PtVersion,
+    /// stops and platforms must be before the ways in the member list
+    MemberOrder,
+    /// a name, from or to tag is missing
+    MissingTag,
+    /// the name should mention the from and to values
+    NameFromTo,
+    /// the relation has no stop nor platform members
+    NoStops,
+    /// stop positions must be nodes of the route ways
+    StopNotOnRoute,
+    /// platforms must be near a stop position of the route
+    PlatformFarFromStop,
+    /// the first stop must be at the start of the route
+    FirstStopNotAtStart,
+    /// the last stop must be at the end of the route
+    LastStopNotAtEnd,
+    /// stops must follow the order of the route line
+    StopOrder,
+    /// stops and platforms must be near the route line
+    StopFarFromLine,
+    /// the ways do not form a continuous line
+    Gap,
+    /// the ways do not form a continuous line but the gap can be filled with other ways
+    GapFilled,
+    /// the route must not travel oneway ways in the wrong direction
+    AgainstOneway,
+    /// members of the relation are not in the pbf file, the other checks may be wrong
+    Incomplete,
+    /// the interval, interval:conditional, duration or opening_hours tag can not be parsed
+    InvalidSchedule,
+}
+
+impl Rule {
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::PtVersion => "public_transport_version",
+            Rule::MemberOrder => "member_order",
+            Rule::MissingTag => "missing_tag",
+            Rule::NameFromTo => "name_from_to",
+            Rule::NoStops => "no_stops",
+            Rule::StopNotOnRoute => "stop_not_on_route",
+            Rule::PlatformFarFromStop => "platform_far_from_stop",
+            Rule::FirstStopNotAtStart => "first_stop_not_at_start",
+            Rule::LastStopNotAtEnd => "last_stop_not_at_end",
+            Rule::StopOrder => "stop_order",
+            Rule::StopFarFromLine => "stop_far_from_line",
+            Rule::Gap => "gap",
+            Rule::GapFilled => "gap_filled",
+            Rule::AgainstOneway => "against_oneway",
+            Rule::Incomplete => "incomplete_relation",
+            Rule::InvalidSchedule => "invalid_schedule",
+        }
+    }
+}
+
+/// Problem found in a public transport, `osm_type` / `osm_id` is the object to fix
+#[derive(Clone, Debug, PartialEq)]
+pub struct Finding {
+    pub rule: Rule,
+    pub severity: Severity,
+    pub osm_type: MemberType,
+    pub osm_id: u64,
+    pub message: String,
+    /// where the problem is, when it is a point (e.g. a gap)
+    pub location: Option<LonLat>,
+}
+
+impl Finding {
+    /// url of the object to fix in openstreetmap.org
+    pub fn url(&self) -> String {
+        osm_url(self.osm_type, self.osm_id)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "rule": self.rule.name(),
+            "severity": self.severity.name(),
+            "osm_type": self.osm_type.name(),
+            "osm_id": self.osm_id,
+            "url": self.url(),
+            "message": self.message,
+            "location": self.location.map(|(lon, lat)| vec![lon, lat]),
+        })
+    }
+}
+
+/// Findings of one public transport
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub route_id: u64,
+    pub route_ref: Option<String>,
+    pub route_name: Option<String>,
+    pub findings: Vec<Finding>,
+}
+
+impl Report {
+    /// true if there are no errors (there may be warnings)
+    pub fn is_valid(&self) -> bool {
+        self.findings.iter().all(|f| f.severity != Severity::Error)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "route_id": self.route_id,
+            "url": osm_url(MemberType::Relation, self.route_id),
+            "ref": self.route_ref,
+            "name": self.route_name,
+            "valid": self.is_valid(),
+            "findings": self.findings.iter().map(|f| f.to_json()).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Thresholds used by the checks, in meters
+#[derive(Clone, Debug)]
+pub struct ValidateOptions {
+    /// maximum distance from a platform to its closest stop position
+    pub platform_distance: f64,
+    /// maximum distance from the first / last stop to the start / end of the route
+    pub end_distance: f64,
+    /// maximum distance from a stop or platform to the route line
+    pub line_distance: f64,
+}
+
+impl Default for ValidateOptions {
+    fn default() -> Self {
+        ValidateOptions {
+            platform_distance: 50.0,
+            end_distance: 100.0,
+            line_distance: 50.0,
+        }
+    }
+}
+
+fn osm_url(osm_type: MemberType, osm_id: u64) -> String {
+    format!(
+        "https://www.openstreetmap.org/{}/{}",
+        osm_type.name(),
+        osm_id
+    )
+}
+
+fn is_stop_role(role: &str) -> bool {
+    role.starts_with("stop")
+}
+
+fn is_platform_role(role: &str) -> bool {
+    role.starts_with("platform")
+}
+
+/// stops of `sequence` (id, distance along the route) that are not in the longest sequence of increasing distances
+/// if the first stop is farther than the last one the route is taken as reversed and the distances as decreasing
+fn out_of_order(sequence: &[(u64, f64)]) -> Vec<(u64, f64)> {
+    if sequence.len() < 2 {
+        return Vec::new();
+    }
+    let reversed = sequence[0].1 > sequence[sequence.len() - 1].1;
+    let distances: Vec<f64> = sequence
+        .iter()
+        .map(|(_, d)| if reversed { -d } else { *d })
+        .collect();
+    // patience sorting, `tails[k]` is the index of the smallest tail of an increasing subsequence of length k + 1
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = vec![None; distances.len()];
+    for (i, d) in distances.iter().enumerate() {
+        let k = tails.partition_point(|&t| distances[t] <= *d);
+        previous[i] = if k > 0 { Some(tails[k - 1]) } else { None };
+        if k == tails.len() {
+            tails.push(i);
+        } else {
+            tails[k] = i;
+        }
+    }
+    let mut in_order = vec![false; distances.len()];
+    let mut i = tails.last().copied();
+    while let Some(index) = i {
+        in_order[index] = true;
+        i = previous[index];
+    }
+    sequence
+        .iter()
+        .zip(in_order)
+        .filter(|(_, in_order)| !in_order)
+        .map(|(s, _)| *s)
+        .collect()
+}
+
+struct Checker<'a> {
+    pt: &'a PublicTransport,
+    options: &'a ValidateOptions,
+    nodes: HashMap<u64, &'a Node>,
+    findings: Vec<Finding>,
+}
+
+impl<'a> Checker<'a> {
+    fn add(
+        &mut self,
+        rule: Rule,
+        severity: Severity,
+        osm_type: MemberType,
+        osm_id: u64,
+        message: String,
+    ) {
+        self.findings.push(Finding {
+            rule,
+            severity,
+            osm_type,
+            osm_id,
+            message,
+            location: None,
+        });
+    }
+
+    fn add_relation(&mut self, rule: Rule, severity: Severity, message: String) {
+        self.add(rule, severity, MemberType::Relation, self.pt.id, message);
+    }
+
+    /// nodes with a stop role (or `platform` role if `platforms`), in member order
+    fn role_nodes(&self, platforms: bool) -> Vec<&'a Node> {
+        let is_role = if platforms {
+            is_platform_role
+        } else {
+            is_stop_role
+        };
+        self.pt
+            .members
+            .iter()
+            .filter(|m| m.member_type == MemberType::Node && is_role(&m.role))
+            .filter_map(|m| self.nodes.get(&m.id).copied())
+            .collect()
+    }
+
+    fn check_tags(&mut self) {
+        let tags = &self.pt.tags;
+        match tags.get("public_transport:version").map(String::as_str) {
+            Some("2") => {}
+            Some(version) => self.add_relation(
+                Rule::PtVersion,
+                Severity::Error,
+                format!("public_transport:version is {}, expected 2", version),
+            ),
+            None => self.add_relation(
+                Rule::PtVersion,
+                Severity::Error,
+                "public_transport:version=2 is missing".to_string(),
+            ),
+        }
+        for key in &["name", "from", "to"] {
+            if !tags.contains_key(*key) {
+                self.add_relation(
+                    Rule::MissingTag,
+                    Severity::Warning,
+                    format!("{} tag is missing", key),
+                );
+            }
+        }
+        if let (Some(name), Some(from), Some(to)) =
+            (tags.get("name"), tags.get("from"), tags.get("to"))
+        {
+            let name_lower = name.to_lowercase();
+            for (key, value) in &[("from", from), ("to", to)] {
+                if !name_lower.contains(&value.to_lowercase()) {
+                    self.add_relation(
+                        Rule::NameFromTo,
+                        Severity::Warning,
+                        format!("name '{}' does not include {} '{}'", name, key, value),
+                    );
+                }
+            }
+        }
+    }
+
+    /// reports the smallest set of members that breaks the order,
+    /// either the route ways placed before a stop or the stops placed after a route way
+    fn check_member_order(&mut self) {
+        let members = &self.pt.members;
+        let is_stop = |role: &str| is_stop_role(role) || is_platform_role(role);
+        let is_way = |m: &Member| m.member_type == MemberType::Way && !is_stop(&m.role);
+        let first_way = members.iter().position(is_way);
+        let last_stop = members.iter().rposition(|m| is_stop(&m.role));
+        let (first_way, last_stop) = match (first_way, last_stop) {
+            (Some(first_way), Some(last_stop)) if first_way < last_stop => (first_way, last_stop),
+            _ => return,
+        };
+        let ways_before: Vec<&Member> = members[..last_stop].iter().filter(|m| is_way(m)).collect();
+        let stops_after: Vec<&Member> = members[first_way..]
+            .iter()
+            .filter(|m| is_stop(&m.role))
+            .collect();
+        let misplaced: Vec<(MemberType, u64, String)> = if ways_before.len() <= stops_after.len() {
+            ways_before
+                .iter()
+                .map(|m| {
+                    (
+                        m.member_type,
+                        m.id,
+                        format!("way {} is before stop / platform members", m.id),
+                    )
+                })
+                .collect()
+        } else {
+            stops_after
+                .iter()
+                .map(|m| {
+                    (
+                        m.member_type,
+                        m.id,
+                        format!(
+                            "{} {} with role '{}' is after the ways",
+                            m.member_type.name(),
+                            m.id,
+                            m.role
+                        ),
+                    )
+                })
+                .collect()
+        };
+        for (member_type, id, message) in misplaced {
+            self.add(Rule::MemberOrder, Severity::Error, member_type, id, message);
+        }
+    }
+
+    fn check_stops(&mut self) {
+        let stops = self.role_nodes(false);
+        let platforms = self.role_nodes(true);
+        if stops.is_empty() && platforms.is_empty() {
+            self.add_relation(
+                Rule::NoStops,
+                Severity::Error,
+                "relation has no stop nor platform members".to_string(),
+            );
+            return;
+        }
+
+        for platform in &platforms {
+            let closest = stops
+                .iter()
+                .map(|s| haversine((s.lon, s.lat), (platform.lon, platform.lat)))
+                .fold(f64::INFINITY, f64::min);
+            if closest.is_finite() && closest > self.options.platform_distance {
+                self.add(
+                    Rule::PlatformFarFromStop,
+                    Severity::Warning,
+                    MemberType::Node,
+                    platform.id,
+                    format!(
+                        "platform is {:.0}m away from the closest stop position",
+                        closest
+                    ),
+                );
+            }
+        }
+
+        // the rest of the checks need the route geometry
+        let geometry = &self.pt.geometry;
+        let vertices: Vec<&LonLat> = geometry.iter().flatten().collect();
+        if vertices.is_empty() {
+            return;
+        }
+        for stop in &stops {
+            if !self.pt.way_nodes.contains(&stop.id) {
+                self.add(
+                    Rule::StopNotOnRoute,
+                    Severity::Error,
+                    MemberType::Node,
+                    stop.id,
+                    "stop position is not a node of the route ways".to_string(),
+                );
+            }
+        }
+
+        let ends = if stops.is_empty() { &platforms } else { &stops };
+        let start = *vertices[0];
+        let end = *vertices[vertices.len() - 1];
+        let first = ends[0];
+        let last = ends[ends.len() - 1];
+        // the geometry may be reversed, so any end of the route is accepted
+        let end_distance = |node: &Node| {
+            haversine((node.lon, node.lat), start).min(haversine((node.lon, node.lat), end))
+        };
+        let first_distance = end_distance(first);
+        if first_distance > self.options.end_distance {
+            self.add(
+                Rule::FirstStopNotAtStart,
+                Severity::Warning,
+                MemberType::Node,
+                first.id,
+                format!(
+                    "first stop is {:.0}m away from the ends of the route",
+                    first_distance
+                ),
+            );
+        }
+        let last_distance = end_distance(last);
+        if last_distance > self.options.end_distance {
+            self.add(
+                Rule::LastStopNotAtEnd,
+                Severity::Warning,
+                MemberType::Node,
+                last.id,
+                format!(
+                    "last stop is {:.0}m away from the ends of the route",
+                    last_distance
+                ),
+            );
+        }
+
+        // in the order of the relation, a node is there once per time it is a member (e.g. loops)
+        let located: Vec<(u64, StopPosition)> = self
+            .pt
+            .stops
+            .iter()
+            .zip(self.pt.stop_positions())
+            .filter_map(|(stop, position)| position.map(|p| (stop.id, p)))
+            .collect();
+        let positions: HashMap<u64, StopPosition> = located.iter().copied().collect();
+        for node in stops.iter().chain(&platforms) {
+            if let Some(position) = positions.get(&node.id) {
+                if position.offset > self.options.line_distance {
+                    self.add(
+                        Rule::StopFarFromLine,
+                        Severity::Warning,
+                        MemberType::Node,
+                        node.id,
+                        format!("stop is {:.0}m away from the route line", position.offset),
+                    );
+                }
+            }
+        }
+
+        // each stop takes the next position of its node, so a repeated stop keeps its place in the sequence
+        let mut next = 0;
+        let sequence: Vec<(u64, f64)> = ends
+            .iter()
+            .filter_map(|n| {
+                let i = next + located[next..].iter().position(|(id, _)| *id == n.id)?;
+                next = i + 1;
+                Some((n.id, located[i].1.distance))
+            })
+            .collect();
+        for (id, distance) in out_of_order(&sequence) {
+            self.add(
+                Rule::StopOrder,
+                Severity::Error,
+                MemberType::Node,
+                id,
+                format!(
+                    "stop at {:.0}m along the route is out of order with the other stops",
+                    distance
+                ),
+            );
+        }
+    }
+
+    fn check_parse_status(&mut self) {
+        for issue in &self.pt.parse_status.issues {
+            match issue {
+                ParseIssue::MissingMembers(missing) => self.add_relation(
+                    Rule::Incomplete,
+                    Severity::Warning,
+                    format!(
+                        "{} ways and {} nodes are not in the pbf file",
+                        missing.ways.len(),
+                        missing.nodes.len()
+                    ),
+                ),
+                ParseIssue::Gap(gap) => self.findings.push(Finding {
+                    rule: Rule::Gap,
+                    severity: Severity::Error,
+                    osm_type: MemberType::Way,
+                    osm_id: gap.from_way,
+                    message: format!(
+                        "gap of {:.0}m between way {} and way {}",
+                        gap.distance, gap.from_way, gap.to_way
+                    ),
+                    location: Some(gap.location),
+                }),
+                ParseIssue::GapFilled {
+                    gap,
+                    way_ids,
+                    length,
+                } => self.findings.push(Finding {
+                    rule: Rule::GapFilled,
+                    severity: Severity::Warning,
+                    osm_type: MemberType::Way,
+                    osm_id: gap.from_way,
+                    message: format!(
+                        "gap of {:.0}m between way {} and way {} can be filled with {:.0}m of ways {:?}",
+                        gap.distance, gap.from_way, gap.to_way, length, way_ids
+                    ),
+                    location: Some(gap.location),
+                }),
+                ParseIssue::AgainstOneway {
+                    way_id,
+                    from_node,
+                    to_node,
+                } => self.findings.push(Finding {
+                    rule: Rule::AgainstOneway,
+                    severity: Severity::Error,
+                    osm_type: MemberType::Way,
+                    osm_id: *way_id,
+                    message: format!(
+                        "route goes from node {} to node {} against the oneway direction of way {}",
+                        from_node, to_node, way_id
+                    ),
+                    location: None,
+                }),
+                ParseIssue::InvalidSchedule {
+                    key,
+                    value,
+                    message,
+                } => self.add_relation(
+                    Rule::InvalidSchedule,
+                    Severity::Warning,
+                    format!("{}={} can not be parsed: {}", key, value, message),
+                ),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Checks the PTv2 rules on `pt` with the default thresholds
+pub fn validate(pt: &PublicTransport) -> Report {
+    validate_with(pt, &ValidateOptions::default())
+}
+
+/// Checks the PTv2 rules on `pt`
+pub fn validate_with(pt: &PublicTransport, options: &ValidateOptions) -> Report {
+    let mut checker = Checker {
+        pt,
+        options,
+        nodes: pt.stops.iter().map(|n| (n.id, n)).collect(),
+        findings: Vec::new(),
+    };
+    checker.check_tags();
+    checker.check_member_order();
+    checker.check_stops();
+    checker.check_parse_status();
+    Report {
+        route_id: pt.id,
+        route_ref: pt.tags.get("ref").cloned(),
+        route_name: pt.tags.get("name").cloned(),
+        findings: checker.findings,
+    }
+}
+
+/// Output formats of the reports
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReportFormat {
+    /// json list with one object per route, with its findings
+    Json,
+    /// one row per finding
+    Csv,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(ReportFormat::Json),
+            "csv" => Ok(ReportFormat::Csv),
+            _ => Err(format!("unknown report format '{}'", s)),
+        }
+    }
+}
+
+/// Writes the reports in `format`
+pub fn write_reports<W: Write>(
+    reports: &[Report],
+    format: ReportFormat,
+    mut out: W,
+) -> io::Result<()> {
+    match format {
+        ReportFormat::Json => {
+            let reports: Vec<Value> = reports.iter().map(|r| r.to_json()).collect();
+            serde_json::to_writer_pretty(&mut out, &reports)?;
+            writeln!(out)?;
+        }
+        ReportFormat::Csv => {
+            writeln!(
+                out,
+                "route_id,route_ref,route_name,rule,severity,osm_type,osm_id,url,lon,lat,message"
+            )?;
+            for report in reports {
+                for finding in &report.findings {
+                    let (lon, lat) = match finding.location {
+                        Some((lon, lat)) => (lon.to_string(), lat.to_string()),
+                        None => (String::new(), String::new()),
+                    };
+                    let row = [
+                        report.route_id.to_string(),
+                        csv_field(report.route_ref.as_deref().unwrap_or("")),
+                        csv_field(report.route_name.as_deref().unwrap_or("")),
+                        finding.rule.name().to_string(),
+                        finding.severity.name().to_string(),
+                        finding.osm_type.name().to_string(),
+                        finding.osm_id.to_string(),
+                        finding.url(),
+                        lon,
+                        lat,
+                        csv_field(&finding.message),
+                    ];
+                    writeln!(out, "{}", row.join(","))?;
+                }
+            }
+        }
+    }
+    out.flush()
+}
@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use flatbuffers::{FlatBufferBuilder, WIPOffset};
+
+use super::{WriteError, Writer};
+use crate::parser::parse_status::ParseStatus;
+use crate::parser::relation::{Area, LonLat, PublicTransport};
+use crate::proj::Crs;
+use crate::rtree::{hilbert_order, BBox, PackedRTree};
+
+/// https://github.com/flatgeobuf/flatgeobuf/blob/master/src/fbs/header.fbs
+const MAGIC_BYTES: [u8; 8] = [0x66, 0x67, 0x62, 0x03, 0x66, 0x67, 0x62, 0x00];
+const INDEX_NODE_SIZE: u16 = 16;
+
+// flatbuffers vtable slots, 4 + 2 * field index
+const HEADER_NAME: u16 = 4;
+const HEADER_ENVELOPE: u16 = 6;
+const HEADER_GEOMETRY_TYPE: u16 = 8;
+const HEADER_COLUMNS: u16 = 18;
+const HEADER_FEATURES_COUNT: u16 = 20;
+const HEADER_INDEX_NODE_SIZE: u16 = 22;
+const HEADER_CRS: u16 = 24;
+const COLUMN_NAME: u16 = 4;
+const COLUMN_TYPE: u16 = 6;
+const CRS_ORG: u16 = 4;
+const CRS_CODE: u16 = 6;
+const FEATURE_GEOMETRY: u16 = 4;
+const FEATURE_PROPERTIES: u16 = 6;
+const GEOMETRY_ENDS: u16 = 4;
+const GEOMETRY_XY: u16 = 6;
+const GEOMETRY_TYPE: u16 = 16;
+
+// GeometryType enum
+const UNKNOWN: u8 = 0;
+const POLYGON: u8 = 3;
+const MULTILINESTRING: u8 = 5;
+
+// ColumnType enum
+const COLUMN_ULONG: u8 = 8;
+const COLUMN_STRING: u8 = 11;
+
+/// columns present in every feature, tags are added as string columns after these
+/// (see tag_column())
+const FIXED_COLUMNS: [(&str, u8); 4] = [
+    ("osm_id", COLUMN_ULONG),
+    ("osm_type", COLUMN_STRING),
+    ("parse_status_code", COLUMN_ULONG),
+    ("parse_status_detail", COLUMN_STRING),
+];
+
+/// Writes a FlatGeobuf file with public transport lines and area polygons, and its packed hilbert R-tree index
+///
+/// Features are serialized as they arrive into a temporary file next to the output,
+/// on finish() they are sorted in hilbert order and copied after the header and the index.
+/// Every osm tag is a string property, columns are created as new tag keys show up.
+/// Tag keys equal to a fixed column name (osm_id, osm_type, ...) are written as "tag:<key>".
+/// Features without geometry are skipped since they can't be indexed.
+pub struct FgbWriter {
+    path: PathBuf,
+    tmp_path: PathBuf,
+    tmp: BufWriter<File>,
+    tmp_len: u64,
+    /// bbox, position and size in the temporary file of each feature
+    features: Vec<(BBox, u64, usize)>,
+    columns: Vec<(String, u8)>,
+    column_index: HashMap<String, u16>,
+    geometry_type: Option<u8>,
+    crs: Crs,
+}
+
+/// column name of the tag `key`, keys that collide with the fixed columns get a "tag:" prefix,
+/// and so do the keys that already have it, so two keys never share a column
+fn tag_column(key: &str) -> String {
+    if key.starts_with("tag:") || FIXED_COLUMNS.iter().any(|(name, _)| *name == key) {
+        format!("tag:{}", key)
+    } else {
+        key.to_string()
+    }
+}
+
+fn push_ulong(properties: &mut Vec<u8>, column: u16, value: u64) {
+    properties.extend_from_slice(&column.to_le_bytes());
+    properties.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_string(properties: &mut Vec<u8>, column: u16, value: &str) {
+    properties.extend_from_slice(&column.to_le_bytes());
+    properties.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    properties.extend_from_slice(value.as_bytes());
+}
+
+/// size prefixed Feature flatbuffer
+fn feature_bytes(geometry_type: u8, geometry: &[Vec<LonLat>], properties: &[u8]) -> Vec<u8> {
+    let mut fbb = FlatBufferBuilder::new();
+    let xy: Vec<f64> = geometry
+        .iter()
+        .flatten()
+        .flat_map(|(lon, lat)| vec![*lon, *lat])
+        .collect();
+    let xy = fbb.create_vector(&xy);
+    // ends are only needed for multi part geometries
+    let ends = if geometry.len() > 1 {
+        let ends: Vec<u32> = geometry
+            .iter()
+            .scan(0u32, |end, part| {
+                *end += part.len() as u32;
+                Some(*end)
+            })
+            .collect();
+        Some(fbb.create_vector(&ends))
+    } else {
+        None
+    };
+    let start = fbb.start_table();
+    if let Some(ends) = ends {
+        fbb.push_slot_always(GEOMETRY_ENDS, ends);
+    }
+    fbb.push_slot_always(GEOMETRY_XY, xy);
+    fbb.push_slot(GEOMETRY_TYPE, geometry_type, UNKNOWN);
+    let geometry = fbb.end_table(start);
+    let properties = fbb.create_vector(properties);
+    let start = fbb.start_table();
+    fbb.push_slot_always(FEATURE_GEOMETRY, geometry);
+    fbb.push_slot_always(FEATURE_PROPERTIES, properties);
+    let feature = fbb.end_table(start);
+    fbb.finish_size_prefixed(feature, None);
+    fbb.finished_data().to_vec()
+}
+
+impl FgbWriter {
+    /// creates the FlatGeobuf file at `path`, the temporary file is `path` + ".tmp"
+    pub fn create(path: &Path) -> Result<Self, WriteError> {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        Ok(FgbWriter {
+            path: path.to_path_buf(),
+            tmp: BufWriter::new(File::create(&tmp_path)?),
+            tmp_path,
+            tmp_len: 0,
+            features: Vec::new(),
+            columns: FIXED_COLUMNS
+                .iter()
+                .map(|(name, t)| (name.to_string(), *t))
+                .collect(),
+            column_index: FIXED_COLUMNS
+                .iter()
+                .enumerate()
+                .map(|(i, (name, _))| (name.to_string(), i as u16))
+                .collect(),
+            geometry_type: None,
+            crs: Crs::Wgs84,
+        })
+    }
+
+    /// reprojects the geometries to `crs`, declared in the header
+    pub fn with_crs(mut self, crs: Crs) -> Self {
+        self.crs = crs;
+        self
+    }
+
+    fn column(&mut self, name: &str) -> Result<u16, WriteError> {
+        if let Some(i) = self.column_index.get(name) {
+            return Ok(*i);
+        }
+        if self.columns.len() > u16::MAX as usize {
+            return Err(WriteError::Invalid(format!(
+                "too many tag keys for fgb columns, can't add '{}'",
+                name
+            )));
+        }
+        let i = self.columns.len() as u16;
+        self.columns.push((name.to_string(), COLUMN_STRING));
+        self.column_index.insert(name.to_string(), i);
+        Ok(i)
+    }
+
+    fn write_feature(
+        &mut self,
+        geometry_type: u8,
+        geometry: &[Vec<LonLat>],
+        osm_id: u64,
+        osm_type: char,
+        tags: &HashMap<String, String>,
+        parse_status: &ParseStatus,
+    ) -> Result<(), WriteError> {
+        let geometry = &self.crs.project_geometry(geometry);
+        let mut bbox = BBox::empty();
+        for (x, y) in geometry.iter().flatten() {
+            bbox.expand_point(*x, *y);
+        }
+        if bbox == BBox::empty() {
+            return Ok(());
+        }
+        let mut properties = Vec::new();
+        push_ulong(&mut properties, 0, osm_id);
+        push_string(&mut properties, 1, &osm_type.to_string());
+        push_ulong(&mut properties, 2, parse_status.code());
+        push_string(&mut properties, 3, parse_status.detail());
+        let mut keys: Vec<&String> = tags.keys().collect();
+        keys.sort();
+        for key in keys {
+            let column = self.column(&tag_column(key))?;
+            push_string(&mut properties, column, &tags[key]);
+        }
+
+        let bytes = feature_bytes(geometry_type, geometry, &properties);
+        self.tmp.write_all(&bytes)?;
+        self.features.push((bbox, self.tmp_len, bytes.len()));
+        self.tmp_len += bytes.len() as u64;
+        self.geometry_type = match self.geometry_type {
+            None => Some(geometry_type),
+            Some(t) if t == geometry_type => Some(t),
+            Some(_) => Some(UNKNOWN),
+        };
+        Ok(())
+    }
+
+    /// size prefixed Header flatbuffer
+    fn header_bytes(&self, extent: Option<BBox>) -> Vec<u8> {
+        let mut fbb = FlatBufferBuilder::new();
+        let name = fbb.create_string("osmptparser");
+        let envelope = extent.map(|e| fbb.create_vector(&[e.min_x, e.min_y, e.max_x, e.max_y]));
+        let columns: Vec<WIPOffset<_>> = self
+            .columns
+            .iter()
+            .map(|(name, column_type)| {
+                let name = fbb.create_string(name);
+                let start = fbb.start_table();
+                fbb.push_slot_always(COLUMN_NAME, name);
+                fbb.push_slot(COLUMN_TYPE, *column_type, 0u8);
+                fbb.end_table(start)
+            })
+            .collect();
+        let columns = fbb.create_vector(&columns);
+        let org = fbb.create_string("EPSG");
+        let start = fbb.start_table();
+        fbb.push_slot_always(CRS_ORG, org);
+        fbb.push_slot(CRS_CODE, self.crs.epsg() as i32, 0i32);
+        let crs = fbb.end_table(start);
+
+        let start = fbb.start_table();
+        fbb.push_slot_always(HEADER_NAME, name);
+        if let Some(envelope) = envelope {
+            fbb.push_slot_always(HEADER_ENVELOPE, envelope);
+        }
+        fbb.push_slot(
+            HEADER_GEOMETRY_TYPE,
+            self.geometry_type.unwrap_or(UNKNOWN),
+            UNKNOWN,
+        );
+        fbb.push_slot_always(HEADER_COLUMNS, columns);
+        fbb.push_slot(HEADER_FEATURES_COUNT, self.features.len() as u64, 0u64);
+        let index_node_size = if self.features.is_empty() {
+            0
+        } else {
+            INDEX_NODE_SIZE
+        };
+        fbb.push_slot_always(HEADER_INDEX_NODE_SIZE, index_node_size);
+        fbb.push_slot_always(HEADER_CRS, crs);
+        let header = fbb.end_table(start);
+        fbb.finish_size_prefixed(header, None);
+        fbb.finished_data().to_vec()
+    }
+}
+
+impl Writer for FgbWriter {
+    fn write_public_transport(&mut self, pt: &PublicTransport) -> Result<(), WriteError> {
+        self.write_feature(
+            MULTILINESTRING,
+            &pt.geometry,
+            pt.id,
+            'r',
+            &pt.tags,
+            &pt.parse_status,
+        )
+    }
+
+    fn write_area(&mut self, area: &Area) -> Result<(), WriteError> {
+        self.write_feature(
+            POLYGON,
+            &area.geometry,
+            area.id,
+            area.id_type,
+            &area.tags,
+            &area.parse_status,
+        )
+    }
+
+    fn finish(&mut self) -> Result<(), WriteError> {
+        self.tmp.flush()?;
+        let boxes: Vec<BBox> = self.features.iter().map(|f| f.0).collect();
+        let order = hilbert_order(&boxes);
+
+        let mut out = BufWriter::new(File::create(&self.path)?);
+        out.write_all(&MAGIC_BYTES)?;
+        let extent = if boxes.is_empty() {
+            None
+        } else {
+            let mut extent = BBox::empty();
+            for b in &boxes {
+                extent.expand(b);
+            }
+            Some(extent)
+        };
+        out.write_all(&self.header_bytes(extent))?;
+
+        if !order.is_empty() {
+            // leaves point to the position of the feature in the features section
+            let mut offset = 0u64;
+            let items: Vec<(BBox, u64)> = order
+                .iter()
+                .map(|i| {
+                    let item = (self.features[*i].0, offset);
+                    offset += self.features[*i].2 as u64;
+                    item
+                })
+                .collect();
+            out.write_all(&PackedRTree::build(&items, INDEX_NODE_SIZE as usize).to_bytes())?;
+        }
+
+        let mut tmp = File::open(&self.tmp_path)?;
+        let mut buffer = Vec::new();
+        for i in order {
+            let (_, position, size) = self.features[i];
+            buffer.resize(size, 0);
+            tmp.seek(SeekFrom::Start(position))?;
+            tmp.read_exact(&mut buffer)?;
+            out.write_all(&buffer)?;
+        }
+        out.flush()?;
+        std::fs::remove_file(&self.tmp_path)?;
+        Ok(())
+    }
+}
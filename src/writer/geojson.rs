@@ -0,0 +1,103 @@
+use std::io::Write;
+
+use serde_json::Value;
+
+use super::{WriteError, Writer};
+use crate::parser::relation::{Area, PublicTransport};
+use crate::proj::Crs;
+
+/// Writes a json list with one geojson per public transport / area
+pub struct GeoJsonWriter<W: Write> {
+    out: W,
+    first: bool,
+    metrics: bool,
+    crs: Crs,
+    /// languages of the name of the flat properties, None without flat properties
+    flat_properties: Option<Vec<String>>,
+}
+
+impl<W: Write> GeoJsonWriter<W> {
+    pub fn new(out: W) -> Self {
+        GeoJsonWriter {
+            out,
+            first: true,
+            metrics: false,
+            crs: Crs::Wgs84,
+            flat_properties: None,
+        }
+    }
+
+    /// adds the route / area metrics to the properties of each geojson
+    pub fn with_metrics(mut self, metrics: bool) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// reprojects the geometries to `crs`, declared in a "crs" member (GeoJSON 2008) when it is not WGS84
+    /// the metrics and the gap locations of the parse status stay in WGS84
+    pub fn with_crs(mut self, crs: Crs) -> Self {
+        self.crs = crs;
+        self
+    }
+
+    /// adds the normalized ref, name, colour, route_type, network, operator, from and to of each public transport
+    /// to its properties (see PublicTransport::flat_properties()), the name in the first of `languages` that has one
+    pub fn with_flat_properties(mut self, languages: Option<Vec<String>>) -> Self {
+        self.flat_properties = languages;
+        self
+    }
+
+    fn write_feature(&mut self, mut feature: Value, metrics: Value) -> Result<(), WriteError> {
+        if self.metrics {
+            feature["properties"]["metrics"] = metrics;
+        }
+        self.crs.reproject_geojson(&mut feature);
+        if self.first {
+            writeln!(self.out, "[")?;
+            self.first = false;
+        } else {
+            writeln!(self.out, ",")?;
+        }
+        write!(self.out, "  {}", feature)?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Writer for GeoJsonWriter<W> {
+    fn write_public_transport(&mut self, pt: &PublicTransport) -> Result<(), WriteError> {
+        let metrics = if self.metrics {
+            pt.metrics().to_json()
+        } else {
+            Value::Null
+        };
+        let mut feature = pt.geojson();
+        if let Some(languages) = &self.flat_properties {
+            let languages: Vec<&str> = languages.iter().map(|l| l.as_str()).collect();
+            if let Value::Object(properties) = pt.flat_properties(&languages) {
+                for (key, value) in properties {
+                    feature["properties"][key] = value;
+                }
+            }
+        }
+        self.write_feature(feature, metrics)
+    }
+
+    fn write_area(&mut self, area: &Area) -> Result<(), WriteError> {
+        let metrics = if self.metrics {
+            area.metrics().to_json()
+        } else {
+            Value::Null
+        };
+        self.write_feature(area.geojson(), metrics)
+    }
+
+    fn finish(&mut self) -> Result<(), WriteError> {
+        if self.first {
+            write!(self.out, "[")?;
+        }
+        writeln!(self.out)?;
+        writeln!(self.out, "]")?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use rusqlite::{params, Connection};
+
+use super::{wkb, WriteError, Writer};
+use crate::geo::bbox;
+use crate::parser::relation::{Area, LonLat, PublicTransport};
+use crate::proj::Crs;
+
+/// "GPKG" in ascii, see http://www.geopackage.org/spec/#_file_format
+const APPLICATION_ID: i32 = 0x4750_4B47;
+const USER_VERSION: i32 = 10200;
+
+/// layer name, geometry type and specific columns of each feature table
+const LAYERS: [(&str, &str, &str); 3] = [
+    (
+        "routes",
+        "MULTILINESTRING",
+        "parse_status_code INTEGER, parse_status_detail TEXT, parse_status_issues TEXT",
+    ),
+    (
+        "stops",
+        "POINT",
+        "route_id INTEGER, distance REAL, distance_to_line REAL",
+    ),
+    (
+        "areas",
+        "POLYGON",
+        "osm_type TEXT, parse_status_code INTEGER, parse_status_detail TEXT, parse_status_issues TEXT, parent_id INTEGER",
+    ),
+];
+
+/// Writes a GeoPackage with a `routes` line layer, a `stops` point layer and an `areas` polygon layer
+/// All the osm tags are stored in a json `tags` column, with the most common ones also promoted to columns
+pub struct GpkgWriter {
+    conn: Connection,
+    crs: Crs,
+    extents: HashMap<&'static str, (f64, f64, f64, f64)>,
+}
+
+/// GeoPackageBinary encoding: header with srs and envelope followed by the WKB
+/// empty geometries are stored as NULL
+fn gpkg_geometry(geometry: &[Vec<LonLat>], wkb: Vec<u8>, srs_id: i32) -> Option<Vec<u8>> {
+    let (minx, miny, maxx, maxy) = bbox(geometry)?;
+    let mut out = Vec::with_capacity(wkb.len() + 40);
+    out.extend_from_slice(b"GP");
+    // version 0
+    out.push(0);
+    // flags: little endian, envelope [minx, maxx, miny, maxy]
+    out.push(0b0000_0011);
+    out.extend_from_slice(&srs_id.to_le_bytes());
+    for v in &[minx, maxx, miny, maxy] {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+    out.extend(wkb);
+    Some(out)
+}
+
+impl GpkgWriter {
+    /// creates the GeoPackage file at `path`, overwriting it if it exists
+    pub fn create(path: &Path) -> Result<Self, WriteError> {
+        Self::create_with_crs(path, Crs::Wgs84)
+    }
+
+    /// same as create() with the geometries reprojected to `crs`
+    pub fn create_with_crs(path: &Path, crs: Crs) -> Result<Self, WriteError> {
+        let srs_id = crs.epsg() as i32;
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "application_id", APPLICATION_ID)?;
+        conn.pragma_update(None, "user_version", USER_VERSION)?;
+        conn.execute_batch(
+            "CREATE TABLE gpkg_spatial_ref_sys (
+                srs_name TEXT NOT NULL,
+                srs_id INTEGER NOT NULL PRIMARY KEY,
+                organization TEXT NOT NULL,
+                organization_coordsys_id INTEGER NOT NULL,
+                definition TEXT NOT NULL,
+                description TEXT
+            );
+            CREATE TABLE gpkg_contents (
+                table_name TEXT NOT NULL PRIMARY KEY,
+                data_type TEXT NOT NULL,
+                identifier TEXT UNIQUE,
+                description TEXT DEFAULT '',
+                last_change DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+                min_x DOUBLE,
+                min_y DOUBLE,
+                max_x DOUBLE,
+                max_y DOUBLE,
+                srs_id INTEGER,
+                CONSTRAINT fk_gc_r_srs_id FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys(srs_id)
+            );
+            CREATE TABLE gpkg_geometry_columns (
+                table_name TEXT NOT NULL,
+                column_name TEXT NOT NULL,
+                geometry_type_name TEXT NOT NULL,
+                srs_id INTEGER NOT NULL,
+                z TINYINT NOT NULL,
+                m TINYINT NOT NULL,
+                CONSTRAINT pk_geom_cols PRIMARY KEY (table_name, column_name),
+                CONSTRAINT fk_gc_tn FOREIGN KEY (table_name) REFERENCES gpkg_contents(table_name),
+                CONSTRAINT fk_gc_srs FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys (srs_id)
+            );
+            INSERT INTO gpkg_spatial_ref_sys VALUES
+                ('Undefined cartesian SRS', -1, 'NONE', -1, 'undefined', 'undefined cartesian coordinate reference system'),
+                ('Undefined geographic SRS', 0, 'NONE', 0, 'undefined', 'undefined geographic coordinate reference system');",
+        )?;
+        conn.execute(
+            "INSERT INTO gpkg_spatial_ref_sys VALUES ('WGS 84 geodetic', 4326, 'EPSG', 4326, ?1, 'longitude/latitude coordinates in decimal degrees on the WGS 84 spheroid')",
+            params![Crs::Wgs84.wkt()],
+        )?;
+        if crs != Crs::Wgs84 {
+            conn.execute(
+                "INSERT INTO gpkg_spatial_ref_sys VALUES (?1, ?2, 'EPSG', ?2, ?3, NULL)",
+                params![crs.name(), srs_id, crs.wkt()],
+            )?;
+        }
+        for (layer, geometry_type, columns) in LAYERS.iter() {
+            conn.execute_batch(&format!(
+                "CREATE TABLE {layer} (
+                    fid INTEGER PRIMARY KEY AUTOINCREMENT,
+                    geom {geometry_type},
+                    osm_id INTEGER NOT NULL,
+                    name TEXT,
+                    ref TEXT,
+                    operator TEXT,
+                    network TEXT,
+                    tags TEXT,
+                    info TEXT,
+                    {columns}
+                );",
+                layer = layer,
+                geometry_type = geometry_type,
+                columns = columns,
+            ))?;
+            conn.execute(
+                "INSERT INTO gpkg_contents (table_name, data_type, identifier, srs_id) VALUES (?1, 'features', ?1, ?2)",
+                params![layer, srs_id],
+            )?;
+            conn.execute(
+                "INSERT INTO gpkg_geometry_columns VALUES (?1, 'geom', ?2, ?3, 0, 0)",
+                params![layer, geometry_type, srs_id],
+            )?;
+        }
+        // everything is written in one transaction, committed on finish()
+        conn.execute_batch("BEGIN")?;
+        Ok(GpkgWriter {
+            conn,
+            crs,
+            extents: HashMap::new(),
+        })
+    }
+
+    fn extend_extent(&mut self, layer: &'static str, geometry: &[Vec<LonLat>]) {
+        if let Some((minx, miny, maxx, maxy)) = bbox(geometry) {
+            let extent = self
+                .extents
+                .entry(layer)
+                .or_insert((minx, miny, maxx, maxy));
+            *extent = (
+                extent.0.min(minx),
+                extent.1.min(miny),
+                extent.2.max(maxx),
+                extent.3.max(maxy),
+            );
+        }
+    }
+}
+
+impl Writer for GpkgWriter {
+    fn write_public_transport(&mut self, pt: &PublicTransport) -> Result<(), WriteError> {
+        let srs_id = self.crs.epsg() as i32;
+        let geometry = self.crs.project_geometry(&pt.geometry);
+        self.conn
+            .prepare_cached(
+                "INSERT INTO routes (geom, osm_id, name, ref, operator, network, tags, info, parse_status_code, parse_status_detail, parse_status_issues)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            )?
+            .execute(params![
+                gpkg_geometry(&geometry, wkb::multilinestring(&geometry), srs_id),
+                pt.id as i64,
+                pt.tags.get("name"),
+                pt.tags.get("ref"),
+                pt.tags.get("operator"),
+                pt.tags.get("network"),
+                serde_json::to_string(&pt.tags).unwrap(),
+                serde_json::to_string(&pt.info).unwrap(),
+                pt.parse_status.code() as i64,
+                pt.parse_status.detail(),
+                pt.parse_status.issues_json().to_string(),
+            ])?;
+        self.extend_extent("routes", &geometry);
+        for (stop, position) in pt.stops.iter().zip(pt.stop_positions()) {
+            let point = vec![vec![self.crs.project((stop.lon, stop.lat))]];
+            self.conn
+                .prepare_cached(
+                    "INSERT INTO stops (geom, osm_id, name, ref, operator, network, tags, route_id, distance, distance_to_line)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                )?
+                .execute(params![
+                    gpkg_geometry(&point, wkb::point(point[0][0]), srs_id),
+                    stop.id as i64,
+                    stop.tags.get("name"),
+                    stop.tags.get("ref"),
+                    stop.tags.get("operator"),
+                    stop.tags.get("network"),
+                    serde_json::to_string(&stop.tags).unwrap(),
+                    pt.id as i64,
+                    position.map(|p| p.distance),
+                    position.map(|p| p.offset),
+                ])?;
+            self.extend_extent("stops", &point);
+        }
+        Ok(())
+    }
+
+    fn write_area(&mut self, area: &Area) -> Result<(), WriteError> {
+        let geometry = self.crs.project_geometry(&area.geometry);
+        self.conn
+            .prepare_cached(
+                "INSERT INTO areas (geom, osm_id, osm_type, name, ref, operator, network, tags, info, parse_status_code, parse_status_detail, parse_status_issues, parent_id)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            )?
+            .execute(params![
+                gpkg_geometry(&geometry, wkb::polygon(&geometry), self.crs.epsg() as i32),
+                area.id as i64,
+                area.id_type.to_string(),
+                area.tags.get("name"),
+                area.tags.get("ref"),
+                area.tags.get("operator"),
+                area.tags.get("network"),
+                serde_json::to_string(&area.tags).unwrap(),
+                serde_json::to_string(&area.info).unwrap(),
+                area.parse_status.code() as i64,
+                area.parse_status.detail(),
+                area.parse_status.issues_json().to_string(),
+                area.parent_id.map(|id| id as i64),
+            ])?;
+        self.extend_extent("areas", &geometry);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), WriteError> {
+        for (layer, (minx, miny, maxx, maxy)) in self.extents.iter() {
+            self.conn.execute(
+                "UPDATE gpkg_contents SET min_x = ?2, min_y = ?3, max_x = ?4, max_y = ?5 WHERE table_name = ?1",
+                params![layer, minx, miny, maxx, maxy],
+            )?;
+        }
+        self.conn.execute_batch("COMMIT")?;
+        Ok(())
+    }
+}
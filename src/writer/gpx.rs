@@ -0,0 +1,166 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write as FmtWrite;
+use std::io::Write;
+
+use super::{xml_escape, WriteError, Writer};
+use crate::parser::relation::{Area, LonLat, Node, PublicTransport};
+
+struct Waypoint {
+    lat: f64,
+    lon: f64,
+    name: Option<String>,
+    refs: BTreeSet<String>,
+}
+
+/// Writes a GPX 1.1 file, each public transport is a track and its stops are waypoints,
+/// areas are tracks with one segment per ring
+///
+/// GPX requires every waypoint before the first track, so everything is kept in memory and written on finish().
+/// Stops shared by several routes are written once, with the refs of all of them in the comment
+pub struct GpxWriter<W: Write> {
+    out: W,
+    waypoints: Vec<(u64, Waypoint)>,
+    waypoint_index: HashMap<u64, usize>,
+    tracks: String,
+}
+
+fn write_segments(tracks: &mut String, geometry: &[Vec<LonLat>]) {
+    for part in geometry.iter().filter(|part| !part.is_empty()) {
+        tracks.push_str("    <trkseg>\n");
+        for (lon, lat) in part {
+            writeln!(tracks, "      <trkpt lat=\"{}\" lon=\"{}\"/>", lat, lon).unwrap();
+        }
+        tracks.push_str("    </trkseg>\n");
+    }
+}
+
+fn write_track(
+    tracks: &mut String,
+    name: Option<&String>,
+    description: &str,
+    link: &str,
+    track_type: Option<&String>,
+    geometry: &[Vec<LonLat>],
+) {
+    tracks.push_str("  <trk>\n");
+    if let Some(name) = name {
+        writeln!(tracks, "    <name>{}</name>", xml_escape(name)).unwrap();
+    }
+    writeln!(tracks, "    <desc>{}</desc>", xml_escape(description)).unwrap();
+    tracks.push_str("    <src>OpenStreetMap</src>\n");
+    writeln!(tracks, "    <link href=\"{}\"/>", link).unwrap();
+    if let Some(track_type) = track_type {
+        writeln!(tracks, "    <type>{}</type>", xml_escape(track_type)).unwrap();
+    }
+    write_segments(tracks, geometry);
+    tracks.push_str("  </trk>\n");
+}
+
+impl<W: Write> GpxWriter<W> {
+    pub fn new(out: W) -> Self {
+        GpxWriter {
+            out,
+            waypoints: Vec::new(),
+            waypoint_index: HashMap::new(),
+            tracks: String::new(),
+        }
+    }
+
+    fn add_stop(&mut self, stop: &Node, route_ref: Option<&String>) {
+        let i = match self.waypoint_index.get(&stop.id) {
+            Some(i) => *i,
+            None => {
+                self.waypoint_index.insert(stop.id, self.waypoints.len());
+                self.waypoints.push((
+                    stop.id,
+                    Waypoint {
+                        lat: stop.lat,
+                        lon: stop.lon,
+                        name: stop.tags.get("name").cloned(),
+                        refs: BTreeSet::new(),
+                    },
+                ));
+                self.waypoints.len() - 1
+            }
+        };
+        if let Some(route_ref) = route_ref {
+            self.waypoints[i].1.refs.insert(route_ref.clone());
+        }
+    }
+}
+
+impl<W: Write> Writer for GpxWriter<W> {
+    fn write_public_transport(&mut self, pt: &PublicTransport) -> Result<(), WriteError> {
+        let route_ref = pt.tags.get("ref");
+        for stop in &pt.stops {
+            self.add_stop(stop, route_ref);
+        }
+        let mut description = String::new();
+        for key in &["ref", "from", "to", "operator", "network"] {
+            if let Some(value) = pt.tags.get(*key) {
+                if !description.is_empty() {
+                    description.push_str(", ");
+                }
+                write!(description, "{}: {}", key, value).unwrap();
+            }
+        }
+        write_track(
+            &mut self.tracks,
+            pt.tags.get("name"),
+            &description,
+            &format!("https://www.openstreetmap.org/relation/{}", pt.id),
+            pt.tags.get("route"),
+            &pt.geometry,
+        );
+        Ok(())
+    }
+
+    fn write_area(&mut self, area: &Area) -> Result<(), WriteError> {
+        let osm_type = if area.id_type == 'w' {
+            "way"
+        } else {
+            "relation"
+        };
+        write_track(
+            &mut self.tracks,
+            area.tags.get("name"),
+            &format!("{} {}", osm_type, area.id),
+            &format!("https://www.openstreetmap.org/{}/{}", osm_type, area.id),
+            None,
+            &area.geometry,
+        );
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), WriteError> {
+        writeln!(self.out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+        writeln!(
+            self.out,
+            "<gpx version=\"1.1\" creator=\"osmptparser\" xmlns=\"http://www.topografix.com/GPX/1/1\">"
+        )?;
+        for (id, waypoint) in &self.waypoints {
+            writeln!(
+                self.out,
+                "  <wpt lat=\"{}\" lon=\"{}\">",
+                waypoint.lat, waypoint.lon
+            )?;
+            let name = waypoint.name.clone().unwrap_or_else(|| id.to_string());
+            writeln!(self.out, "    <name>{}</name>", xml_escape(&name))?;
+            if !waypoint.refs.is_empty() {
+                let refs = waypoint.refs.iter().cloned().collect::<Vec<_>>().join(";");
+                writeln!(self.out, "    <cmt>routes: {}</cmt>", xml_escape(&refs))?;
+            }
+            writeln!(
+                self.out,
+                "    <link href=\"https://www.openstreetmap.org/node/{}\"/>",
+                id
+            )?;
+            writeln!(self.out, "    <type>stop</type>")?;
+            writeln!(self.out, "  </wpt>")?;
+        }
+        self.out.write_all(self.tracks.as_bytes())?;
+        writeln!(self.out, "</gpx>")?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::{csv_field, WriteError, Writer};
+use crate::geo::haversine;
+use crate::parser::relation::{Area, PublicTransport};
+use crate::timing::SpeedProfiles;
+
+/// service of all the trips, the estimated timetable does not know the service days
+const SERVICE_ID: &str = "always";
+
+/// seconds as GTFS HH:MM:SS, hours can go over 24
+fn gtfs_time(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Writes a GTFS feed into a directory, with a route and a trip per public transport
+/// and its estimated stop times (see PublicTransport::travel_time()) from 00:00:00
+///
+/// The rows are kept in memory and the files are written on finish().
+/// Public transports with less than two stops are skipped, areas are ignored
+pub struct GtfsWriter {
+    directory: PathBuf,
+    timezone: String,
+    speed_profiles: SpeedProfiles,
+    /// agency name and id, by operator / network
+    agencies: Vec<String>,
+    agency_index: HashMap<String, usize>,
+    /// node ids already in stops.txt
+    stop_ids: HashSet<u64>,
+    stops: String,
+    routes: String,
+    trips: String,
+    stop_times: String,
+    shapes: String,
+}
+
+impl GtfsWriter {
+    /// creates `directory` if it does not exist
+    pub fn create(directory: &Path) -> Result<Self, WriteError> {
+        std::fs::create_dir_all(directory)?;
+        Ok(GtfsWriter {
+            directory: directory.to_path_buf(),
+            timezone: "UTC".to_string(),
+            speed_profiles: SpeedProfiles::default(),
+            agencies: Vec::new(),
+            agency_index: HashMap::new(),
+            stop_ids: HashSet::new(),
+            stops: "stop_id,stop_name,stop_lat,stop_lon\n".to_string(),
+            routes: "route_id,agency_id,route_short_name,route_long_name,route_type,route_color\n"
+                .to_string(),
+            trips: "route_id,service_id,trip_id,trip_headsign,shape_id\n".to_string(),
+            stop_times: "trip_id,arrival_time,departure_time,stop_id,stop_sequence,timepoint,shape_dist_traveled\n"
+                .to_string(),
+            shapes: "shape_id,shape_pt_lat,shape_pt_lon,shape_pt_sequence,shape_dist_traveled\n"
+                .to_string(),
+        })
+    }
+
+    /// agency_timezone of the agencies (tz database name), UTC by default
+    pub fn with_timezone(mut self, timezone: &str) -> Self {
+        self.timezone = timezone.to_string();
+        self
+    }
+
+    /// profiles used to estimate the stop times
+    pub fn with_speed_profiles(mut self, speed_profiles: SpeedProfiles) -> Self {
+        self.speed_profiles = speed_profiles;
+        self
+    }
+
+    /// agency_id of the operator (or network) of `pt`
+    fn agency(&mut self, pt: &PublicTransport) -> usize {
+        let name = pt
+            .operator()
+            .or_else(|| pt.network())
+            .unwrap_or("OpenStreetMap")
+            .to_string();
+        match self.agency_index.get(&name) {
+            Some(i) => *i,
+            None => {
+                self.agencies.push(name.clone());
+                self.agency_index.insert(name, self.agencies.len());
+                self.agencies.len()
+            }
+        }
+    }
+
+    fn write_file(&self, name: &str, content: &str) -> Result<(), WriteError> {
+        let mut out = BufWriter::new(File::create(self.directory.join(name))?);
+        out.write_all(content.as_bytes())?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+impl Writer for GtfsWriter {
+    fn write_public_transport(&mut self, pt: &PublicTransport) -> Result<(), WriteError> {
+        let travel_time = pt.travel_time(&self.speed_profiles);
+        if travel_time.segments.is_empty() {
+            return Ok(());
+        }
+        let agency = self.agency(pt);
+        let field = |value: Option<&str>| csv_field(value.unwrap_or(""));
+        writeln!(
+            self.routes,
+            "{},{},{},{},{},{}",
+            pt.id,
+            agency,
+            field(pt.route_ref()),
+            field(pt.name(&[])),
+            pt.route_type().gtfs_route_type(),
+            pt.colour()
+                .map(|colour| colour[1..].to_uppercase())
+                .unwrap_or_default(),
+        )
+        .unwrap();
+        // the stop distances are along the parts of the geometry, they only match the shape without gaps
+        let single_line = pt.geometry.len() == 1;
+        let shape_id = if pt.geometry.is_empty() {
+            String::new()
+        } else {
+            pt.id.to_string()
+        };
+        writeln!(
+            self.trips,
+            "{},{},{},{},{}",
+            pt.id,
+            SERVICE_ID,
+            pt.id,
+            field(pt.to()),
+            shape_id
+        )
+        .unwrap();
+
+        let stops: HashMap<u64, _> = pt.stops.iter().map(|stop| (stop.id, stop)).collect();
+        let segments = pt.segments();
+        let mut stop_ids = vec![travel_time.segments[0].from];
+        stop_ids.extend(travel_time.segments.iter().map(|segment| segment.to));
+        // distances along the shape never decrease, a stop located behind the previous one gets its distance
+        let mut distances = vec![segments[0].start];
+        for segment in &segments {
+            let previous = distances[distances.len() - 1];
+            distances.push(segment.end.max(previous));
+        }
+        for (i, ((stop_id, (arrival, departure)), distance)) in stop_ids
+            .iter()
+            .zip(travel_time.stop_times())
+            .zip(distances)
+            .enumerate()
+        {
+            if self.stop_ids.insert(*stop_id) {
+                let stop = stops[stop_id];
+                writeln!(
+                    self.stops,
+                    "{},{},{},{}",
+                    stop.id,
+                    csv_field(stop.tags.get("name").map(|v| v.as_str()).unwrap_or("")),
+                    stop.lat,
+                    stop.lon
+                )
+                .unwrap();
+            }
+            let distance = if single_line {
+                format!("{:.1}", distance)
+            } else {
+                String::new()
+            };
+            writeln!(
+                self.stop_times,
+                "{},{},{},{},{},0,{}",
+                pt.id,
+                gtfs_time(arrival),
+                gtfs_time(departure),
+                stop_id,
+                i + 1,
+                distance
+            )
+            .unwrap();
+        }
+
+        let mut sequence = 0;
+        let mut distance = 0_f64;
+        for part in &pt.geometry {
+            for (i, (lon, lat)) in part.iter().enumerate() {
+                if i > 0 {
+                    distance += haversine(part[i - 1], (*lon, *lat));
+                }
+                sequence += 1;
+                let distance = if single_line {
+                    format!("{:.1}", distance)
+                } else {
+                    String::new()
+                };
+                writeln!(
+                    self.shapes,
+                    "{},{},{},{},{}",
+                    pt.id, lat, lon, sequence, distance
+                )
+                .unwrap();
+            }
+        }
+        Ok(())
+    }
+
+    fn write_area(&mut self, _area: &Area) -> Result<(), WriteError> {
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), WriteError> {
+        let mut agency = "agency_id,agency_name,agency_url,agency_timezone\n".to_string();
+        for (i, name) in self.agencies.iter().enumerate() {
+            writeln!(
+                agency,
+                "{},{},https://www.openstreetmap.org,{}",
+                i + 1,
+                csv_field(name),
+                csv_field(&self.timezone)
+            )
+            .unwrap();
+        }
+        let calendar = format!(
+            "service_id,monday,tuesday,wednesday,thursday,friday,saturday,sunday,start_date,end_date\n\
+             {},1,1,1,1,1,1,1,20000101,20991231\n",
+            SERVICE_ID
+        );
+        self.write_file("agency.txt", &agency)?;
+        self.write_file("calendar.txt", &calendar)?;
+        self.write_file("stops.txt", &self.stops)?;
+        self.write_file("routes.txt", &self.routes)?;
+        self.write_file("trips.txt", &self.trips)?;
+        self.write_file("stop_times.txt", &self.stop_times)?;
+        self.write_file("shapes.txt", &self.shapes)?;
+        Ok(())
+    }
+}
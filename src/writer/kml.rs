@@ -0,0 +1,172 @@
+use std::io::Write;
+
+use super::{xml_escape, WriteError, Writer};
+use crate::parser::relation::{Area, LonLat, PublicTransport};
+
+/// line color used when the route has no valid `colour` tag, KML aabbggrr (opaque blue)
+const DEFAULT_COLOR: &str = "ffff0000";
+
+/// converts a "#rrggbb" colour into a KML aabbggrr color
+fn kml_color(colour: &str) -> String {
+    format!("ff{}{}{}", &colour[5..7], &colour[3..5], &colour[1..3])
+}
+
+fn coordinates(line: &[LonLat]) -> String {
+    line.iter()
+        .map(|(lon, lat)| format!("{},{}", lon, lat))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Writes a KML document, each public transport is a folder with its line and its stops,
+/// areas are polygon placemarks
+///
+/// Lines are styled with the color of the `colour` tag, placemarks are written as they arrive
+pub struct KmlWriter<W: Write> {
+    out: W,
+    started: bool,
+}
+
+impl<W: Write> KmlWriter<W> {
+    pub fn new(out: W) -> Self {
+        KmlWriter {
+            out,
+            started: false,
+        }
+    }
+
+    fn start(&mut self) -> Result<(), WriteError> {
+        if !self.started {
+            writeln!(self.out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+            writeln!(self.out, "<kml xmlns=\"http://www.opengis.net/kml/2.2\">")?;
+            writeln!(self.out, "<Document>")?;
+            writeln!(self.out, "  <name>osmptparser</name>")?;
+            self.started = true;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Writer for KmlWriter<W> {
+    fn write_public_transport(&mut self, pt: &PublicTransport) -> Result<(), WriteError> {
+        self.start()?;
+        let name = pt
+            .tags
+            .get("name")
+            .cloned()
+            .unwrap_or_else(|| format!("relation {}", pt.id));
+        let color = pt
+            .colour()
+            .map(|colour| kml_color(&colour))
+            .unwrap_or_else(|| DEFAULT_COLOR.to_string());
+        writeln!(self.out, "  <Folder>")?;
+        writeln!(self.out, "    <name>{}</name>", xml_escape(&name))?;
+        writeln!(self.out, "    <Placemark>")?;
+        writeln!(self.out, "      <name>{}</name>", xml_escape(&name))?;
+        writeln!(
+            self.out,
+            "      <description>https://www.openstreetmap.org/relation/{}</description>",
+            pt.id
+        )?;
+        writeln!(
+            self.out,
+            "      <Style><LineStyle><color>{}</color><width>4</width></LineStyle></Style>",
+            color
+        )?;
+        writeln!(self.out, "      <ExtendedData>")?;
+        let mut keys: Vec<&String> = pt.tags.keys().collect();
+        keys.sort();
+        for key in keys {
+            writeln!(
+                self.out,
+                "        <Data name=\"{}\"><value>{}</value></Data>",
+                xml_escape(key),
+                xml_escape(&pt.tags[key])
+            )?;
+        }
+        writeln!(self.out, "      </ExtendedData>")?;
+        writeln!(self.out, "      <MultiGeometry>")?;
+        for part in pt.geometry.iter().filter(|part| !part.is_empty()) {
+            writeln!(
+                self.out,
+                "        <LineString><coordinates>{}</coordinates></LineString>",
+                coordinates(part)
+            )?;
+        }
+        writeln!(self.out, "      </MultiGeometry>")?;
+        writeln!(self.out, "    </Placemark>")?;
+        for stop in &pt.stops {
+            let stop_name = stop
+                .tags
+                .get("name")
+                .cloned()
+                .unwrap_or_else(|| format!("node {}", stop.id));
+            writeln!(self.out, "    <Placemark>")?;
+            writeln!(self.out, "      <name>{}</name>", xml_escape(&stop_name))?;
+            writeln!(
+                self.out,
+                "      <description>https://www.openstreetmap.org/node/{}</description>",
+                stop.id
+            )?;
+            writeln!(
+                self.out,
+                "      <Style><IconStyle><color>{}</color></IconStyle></Style>",
+                color
+            )?;
+            writeln!(
+                self.out,
+                "      <Point><coordinates>{},{}</coordinates></Point>",
+                stop.lon, stop.lat
+            )?;
+            writeln!(self.out, "    </Placemark>")?;
+        }
+        writeln!(self.out, "  </Folder>")?;
+        Ok(())
+    }
+
+    fn write_area(&mut self, area: &Area) -> Result<(), WriteError> {
+        self.start()?;
+        let osm_type = if area.id_type == 'w' {
+            "way"
+        } else {
+            "relation"
+        };
+        let name = area
+            .tags
+            .get("name")
+            .cloned()
+            .unwrap_or_else(|| format!("{} {}", osm_type, area.id));
+        writeln!(self.out, "  <Placemark>")?;
+        writeln!(self.out, "    <name>{}</name>", xml_escape(&name))?;
+        writeln!(
+            self.out,
+            "    <description>https://www.openstreetmap.org/{}/{}</description>",
+            osm_type, area.id
+        )?;
+        writeln!(self.out, "    <Polygon>")?;
+        for (i, ring) in area.geometry.iter().enumerate() {
+            let boundary = if i == 0 {
+                "outerBoundaryIs"
+            } else {
+                "innerBoundaryIs"
+            };
+            writeln!(
+                self.out,
+                "      <{0}><LinearRing><coordinates>{1}</coordinates></LinearRing></{0}>",
+                boundary,
+                coordinates(ring)
+            )?;
+        }
+        writeln!(self.out, "    </Polygon>")?;
+        writeln!(self.out, "  </Placemark>")?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), WriteError> {
+        self.start()?;
+        writeln!(self.out, "</Document>")?;
+        writeln!(self.out, "</kml>")?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
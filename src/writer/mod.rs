@@ -0,0 +1,240 @@
+pub mod fgb;
+pub mod geojson;
+pub mod gpkg;
+pub mod gpx;
+pub mod gtfs;
+pub mod kml;
+pub mod pgcopy;
+pub mod split;
+mod wkb;
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::parser::relation::{Area, PublicTransport};
+use crate::proj::Crs;
+use crate::timing::SpeedProfiles;
+
+pub use fgb::FgbWriter;
+pub use geojson::GeoJsonWriter;
+pub use gpkg::GpkgWriter;
+pub use gpx::GpxWriter;
+pub use gtfs::GtfsWriter;
+pub use kml::KmlWriter;
+pub use pgcopy::PgCopyWriter;
+pub use split::SplitWriter;
+
+/// Error returned by the writers when the output could not be written
+#[derive(Debug)]
+pub enum WriteError {
+    Io(io::Error),
+    Sqlite(rusqlite::Error),
+    /// the requested combination of options is not valid for the format
+    Invalid(String),
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteError::Io(e) => write!(f, "Error Writing: {}", e),
+            WriteError::Sqlite(e) => write!(f, "Error Writing: {}", e),
+            WriteError::Invalid(e) => write!(f, "Error Writing: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for WriteError {}
+
+impl From<io::Error> for WriteError {
+    fn from(e: io::Error) -> Self {
+        WriteError::Io(e)
+    }
+}
+
+impl From<rusqlite::Error> for WriteError {
+    fn from(e: rusqlite::Error) -> Self {
+        WriteError::Sqlite(e)
+    }
+}
+
+/// Output formats available in the writer layer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// json list with one geojson per public transport / area
+    GeoJson,
+    /// GeoPackage (sqlite) with routes, stops and areas layers
+    Gpkg,
+    /// FlatGeobuf with packed hilbert R-tree index, public transport lines and area polygons
+    Fgb,
+    /// sql script with CREATE TABLE and COPY blocks for PostGIS
+    PgCopy,
+    /// GPX with a track per public transport / area and a waypoint per stop
+    Gpx,
+    /// KML with a folder per public transport, styled with its `colour` tag
+    Kml,
+    /// GTFS feed directory with a trip per public transport and its estimated stop times
+    Gtfs,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "geojson" => Ok(Format::GeoJson),
+            "gpkg" => Ok(Format::Gpkg),
+            "fgb" => Ok(Format::Fgb),
+            "pgcopy" => Ok(Format::PgCopy),
+            "gpx" => Ok(Format::Gpx),
+            "kml" => Ok(Format::Kml),
+            "gtfs" => Ok(Format::Gtfs),
+            _ => Err(format!("unknown format '{}'", s)),
+        }
+    }
+}
+
+impl Format {
+    /// usual file extension of the format
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::GeoJson => "geojson",
+            Format::Gpkg => "gpkg",
+            Format::Fgb => "fgb",
+            Format::PgCopy => "sql",
+            Format::Gpx => "gpx",
+            Format::Kml => "kml",
+            Format::Gtfs => "gtfs",
+        }
+    }
+}
+
+/// Common interface of all the output formats
+/// features are written one by one as they are produced, `finish()` must be called at the end
+pub trait Writer {
+    fn write_public_transport(&mut self, pt: &PublicTransport) -> Result<(), WriteError>;
+    fn write_area(&mut self, area: &Area) -> Result<(), WriteError>;
+    fn finish(&mut self) -> Result<(), WriteError>;
+}
+
+/// Options of the writers, each format uses the ones it supports
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WriterOptions {
+    /// add the route / area metrics to the properties (geojson only)
+    pub metrics: bool,
+    /// the public transports have their administrative areas assigned (geojson only)
+    pub admin_areas: bool,
+    /// reproject the geometries (geojson, gpkg, fgb and pgcopy, gpx and kml are always WGS84)
+    pub crs: Crs,
+    /// speed profiles of the estimated stop times (gtfs only)
+    pub speed_profiles: SpeedProfiles,
+    /// agency timezone, UTC when None (gtfs only)
+    pub timezone: Option<String>,
+    /// add the normalized route information of the public transports as top level properties,
+    /// with the name in the first of these languages that has one (geojson only)
+    pub flat_properties: Option<Vec<String>>,
+}
+
+/// Builds the writer for `format`
+/// `output` is the path of the file to create, when None the output goes to stdout (text formats only)
+pub fn create(format: Format, output: Option<&Path>) -> Result<Box<dyn Writer>, WriteError> {
+    create_with(format, output, WriterOptions::default())
+}
+
+/// Same as create() with `options`
+pub fn create_with(
+    format: Format,
+    output: Option<&Path>,
+    options: WriterOptions,
+) -> Result<Box<dyn Writer>, WriteError> {
+    if options.metrics && format != Format::GeoJson {
+        return Err(WriteError::Invalid(format!(
+            "metrics are not available in {:?} format",
+            format
+        )));
+    }
+    if options.admin_areas && format != Format::GeoJson {
+        return Err(WriteError::Invalid(format!(
+            "admin areas are not available in {:?} format",
+            format
+        )));
+    }
+    if options.crs != Crs::Wgs84
+        && (format == Format::Gpx || format == Format::Kml || format == Format::Gtfs)
+    {
+        return Err(WriteError::Invalid(format!(
+            "{:?} format only supports WGS84 coordinates",
+            format
+        )));
+    }
+    if options.flat_properties.is_some() && format != Format::GeoJson {
+        return Err(WriteError::Invalid(format!(
+            "flat properties are not available in {:?} format",
+            format
+        )));
+    }
+    let crs = options.crs;
+    let flat_properties = options.flat_properties.clone();
+    let geojson = |out: Box<dyn io::Write>| -> Box<dyn Writer> {
+        Box::new(
+            GeoJsonWriter::new(out)
+                .with_metrics(options.metrics)
+                .with_crs(crs)
+                .with_flat_properties(flat_properties),
+        )
+    };
+    match (format, output) {
+        (Format::GeoJson, Some(path)) => Ok(geojson(Box::new(io::BufWriter::new(
+            std::fs::File::create(path)?,
+        )))),
+        (Format::GeoJson, None) => Ok(geojson(Box::new(io::stdout()))),
+        (Format::Gpkg, Some(path)) => Ok(Box::new(GpkgWriter::create_with_crs(path, crs)?)),
+        (Format::PgCopy, Some(path)) => Ok(Box::new(
+            PgCopyWriter::new(io::BufWriter::new(std::fs::File::create(path)?)).with_crs(crs),
+        )),
+        (Format::PgCopy, None) => Ok(Box::new(PgCopyWriter::new(io::stdout()).with_crs(crs))),
+        (Format::Fgb, Some(path)) => Ok(Box::new(FgbWriter::create(path)?.with_crs(crs))),
+        (Format::Gpx, Some(path)) => Ok(Box::new(GpxWriter::new(io::BufWriter::new(
+            std::fs::File::create(path)?,
+        )))),
+        (Format::Gpx, None) => Ok(Box::new(GpxWriter::new(io::stdout()))),
+        (Format::Kml, Some(path)) => Ok(Box::new(KmlWriter::new(io::BufWriter::new(
+            std::fs::File::create(path)?,
+        )))),
+        (Format::Kml, None) => Ok(Box::new(KmlWriter::new(io::stdout()))),
+        (Format::Gtfs, Some(path)) => {
+            let writer = GtfsWriter::create(path)?.with_speed_profiles(options.speed_profiles);
+            Ok(Box::new(match &options.timezone {
+                Some(timezone) => writer.with_timezone(timezone),
+                None => writer,
+            }))
+        }
+        (Format::Gpkg, None) | (Format::Fgb, None) => Err(WriteError::Invalid(format!(
+            "{:?} format needs an output file",
+            format
+        ))),
+        (Format::Gtfs, None) => Err(WriteError::Invalid(
+            "Gtfs format needs an output directory".to_string(),
+        )),
+    }
+}
+
+/// Escapes the xml special characters of a text or attribute value
+pub(crate) fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Quotes a csv field if it contains separators, quotes or line breaks
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::fmt::Write as FmtWrite;
+use std::io::Write;
+
+use super::{wkb, WriteError, Writer};
+use crate::parser::relation::{Area, LonLat, PublicTransport};
+use crate::proj::Crs;
+
+const CREATE_TABLES: &str = "CREATE EXTENSION IF NOT EXISTS postgis;
+CREATE TABLE routes (
+    osm_id bigint PRIMARY KEY,
+    name text,
+    ref text,
+    operator text,
+    network text,
+    tags jsonb,
+    info jsonb,
+    parse_status_code integer,
+    parse_status_detail text,
+    parse_status_issues jsonb,
+    geom geometry(MultiLineString, {srid})
+);
+CREATE TABLE stops (
+    route_id bigint,
+    position integer,
+    distance double precision,
+    distance_to_line double precision,
+    osm_id bigint,
+    name text,
+    ref text,
+    tags jsonb,
+    geom geometry(Point, {srid})
+);
+CREATE TABLE areas (
+    osm_id bigint,
+    osm_type text,
+    name text,
+    tags jsonb,
+    info jsonb,
+    parse_status_code integer,
+    parse_status_detail text,
+    parse_status_issues jsonb,
+    parent_id bigint,
+    geom geometry(Polygon, {srid}),
+    PRIMARY KEY (osm_type, osm_id)
+);
+";
+
+const COPY_ROUTES: &str = "COPY routes (osm_id, name, ref, operator, network, tags, info, parse_status_code, parse_status_detail, parse_status_issues, geom) FROM STDIN;";
+const COPY_STOPS: &str =
+    "COPY stops (route_id, position, distance, distance_to_line, osm_id, name, ref, tags, geom) FROM STDIN;";
+const COPY_AREAS: &str = "COPY areas (osm_id, osm_type, name, tags, info, parse_status_code, parse_status_detail, parse_status_issues, parent_id, geom) FROM STDIN;";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Table {
+    Routes,
+    Stops,
+    Areas,
+}
+
+impl Table {
+    fn copy(self) -> &'static str {
+        match self {
+            Table::Routes => COPY_ROUTES,
+            Table::Stops => COPY_STOPS,
+            Table::Areas => COPY_AREAS,
+        }
+    }
+}
+
+/// Writes a sql script to be piped into `psql`: CREATE TABLE for routes, stops and areas
+/// followed by `COPY ... FROM STDIN` blocks, with EWKB geometries (SRID 4326 unless set with with_crs()) and jsonb tags
+///
+/// Rows are written as they arrive, a new COPY block starts each time the table changes
+pub struct PgCopyWriter<W: Write> {
+    out: W,
+    crs: Crs,
+    started: bool,
+    /// table of the COPY block being written
+    table: Option<Table>,
+}
+
+/// text format COPY value, see https://www.postgresql.org/docs/current/sql-copy.html#id-1.9.3.55.9.2
+fn copy_text(value: Option<&str>) -> String {
+    match value {
+        None => "\\N".to_string(),
+        Some(v) => v
+            .replace('\\', "\\\\")
+            .replace('\t', "\\t")
+            .replace('\n', "\\n")
+            .replace('\r', "\\r"),
+    }
+}
+
+fn copy_json(value: &HashMap<String, String>) -> String {
+    copy_text(Some(&serde_json::to_string(value).unwrap()))
+}
+
+/// hex EWKB, NULL for empty geometries
+fn copy_geometry(geometry: &[Vec<LonLat>], wkb: Vec<u8>, srid: u32) -> String {
+    if geometry.iter().all(|part| part.is_empty()) {
+        return "\\N".to_string();
+    }
+    wkb::with_srid(wkb, srid)
+        .iter()
+        .fold(String::new(), |mut hex, b| {
+            write!(hex, "{:02X}", b).unwrap();
+            hex
+        })
+}
+
+impl<W: Write> PgCopyWriter<W> {
+    pub fn new(out: W) -> Self {
+        PgCopyWriter {
+            out,
+            crs: Crs::Wgs84,
+            started: false,
+            table: None,
+        }
+    }
+
+    /// reprojects the geometries to `crs`, declared as the SRID of the geom columns
+    pub fn with_crs(mut self, crs: Crs) -> Self {
+        self.crs = crs;
+        self
+    }
+
+    /// writes the transaction start and the tables, once
+    fn start(&mut self) -> Result<(), WriteError> {
+        if !self.started {
+            self.started = true;
+            writeln!(self.out, "BEGIN;")?;
+            write!(
+                self.out,
+                "{}",
+                CREATE_TABLES.replace("{srid}", &self.crs.epsg().to_string())
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write_row(&mut self, table: Table, row: &[String]) -> Result<(), WriteError> {
+        self.start()?;
+        if self.table != Some(table) {
+            if self.table.is_some() {
+                writeln!(self.out, "\\.")?;
+            }
+            writeln!(self.out, "{}", table.copy())?;
+            self.table = Some(table);
+        }
+        writeln!(self.out, "{}", row.join("\t"))?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Writer for PgCopyWriter<W> {
+    fn write_public_transport(&mut self, pt: &PublicTransport) -> Result<(), WriteError> {
+        let srid = self.crs.epsg();
+        let geometry = self.crs.project_geometry(&pt.geometry);
+        let row = [
+            pt.id.to_string(),
+            copy_text(pt.tags.get("name").map(String::as_str)),
+            copy_text(pt.tags.get("ref").map(String::as_str)),
+            copy_text(pt.tags.get("operator").map(String::as_str)),
+            copy_text(pt.tags.get("network").map(String::as_str)),
+            copy_json(&pt.tags),
+            copy_json(&pt.info),
+            pt.parse_status.code().to_string(),
+            copy_text(Some(pt.parse_status.detail())),
+            copy_text(Some(&pt.parse_status.issues_json().to_string())),
+            copy_geometry(&geometry, wkb::multilinestring(&geometry), srid),
+        ];
+        self.write_row(Table::Routes, &row)?;
+        let stop_positions = pt.stop_positions();
+        for (position, (stop, stop_position)) in pt.stops.iter().zip(stop_positions).enumerate() {
+            let point = self.crs.project((stop.lon, stop.lat));
+            let row = [
+                pt.id.to_string(),
+                position.to_string(),
+                copy_text(stop_position.map(|p| p.distance.to_string()).as_deref()),
+                copy_text(stop_position.map(|p| p.offset.to_string()).as_deref()),
+                stop.id.to_string(),
+                copy_text(stop.tags.get("name").map(String::as_str)),
+                copy_text(stop.tags.get("ref").map(String::as_str)),
+                copy_json(&stop.tags),
+                copy_geometry(&[vec![point]], wkb::point(point), srid),
+            ];
+            self.write_row(Table::Stops, &row)?;
+        }
+        Ok(())
+    }
+
+    fn write_area(&mut self, area: &Area) -> Result<(), WriteError> {
+        let geometry = self.crs.project_geometry(&area.geometry);
+        let row = [
+            area.id.to_string(),
+            area.id_type.to_string(),
+            copy_text(area.tags.get("name").map(String::as_str)),
+            copy_json(&area.tags),
+            copy_json(&area.info),
+            area.parse_status.code().to_string(),
+            copy_text(Some(area.parse_status.detail())),
+            copy_text(Some(&area.parse_status.issues_json().to_string())),
+            copy_text(area.parent_id.map(|id| id.to_string()).as_deref()),
+            copy_geometry(&geometry, wkb::polygon(&geometry), self.crs.epsg()),
+        ];
+        self.write_row(Table::Areas, &row)
+    }
+
+    fn finish(&mut self) -> Result<(), WriteError> {
+        self.start()?;
+        if self.table.take().is_some() {
+            writeln!(self.out, "\\.")?;
+        }
+        writeln!(self.out, "COMMIT;")?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
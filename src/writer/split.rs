@@ -0,0 +1,66 @@
+use std::path::{Path, PathBuf};
+
+use super::{create_with, Format, WriteError, Writer, WriterOptions};
+use crate::parser::relation::{Area, PublicTransport};
+
+/// Writes each public transport / area into its own file inside a directory
+///
+/// Files are named after the osm id, `<id>.<ext>` for public transports and `<w|r><id>.<ext>` for areas
+pub struct SplitWriter {
+    format: Format,
+    directory: PathBuf,
+    options: WriterOptions,
+}
+
+impl SplitWriter {
+    /// creates `directory` if it does not exist
+    /// only for geojson, gpx and kml, the other formats are databases / feeds meant to hold every feature
+    pub fn create(format: Format, directory: &Path) -> Result<Self, WriteError> {
+        if !matches!(format, Format::GeoJson | Format::Gpx | Format::Kml) {
+            return Err(WriteError::Invalid(format!(
+                "{:?} format can not be split into one file per feature",
+                format
+            )));
+        }
+        std::fs::create_dir_all(directory)?;
+        Ok(SplitWriter {
+            format,
+            directory: directory.to_path_buf(),
+            options: WriterOptions::default(),
+        })
+    }
+
+    /// options of the writer of each file
+    pub fn with_options(mut self, options: WriterOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    fn write_file<F>(&self, name: String, write: F) -> Result<(), WriteError>
+    where
+        F: FnOnce(&mut dyn Writer) -> Result<(), WriteError>,
+    {
+        let path = self
+            .directory
+            .join(format!("{}.{}", name, self.format.extension()));
+        let mut writer = create_with(self.format, Some(&path), self.options.clone())?;
+        write(writer.as_mut())?;
+        writer.finish()
+    }
+}
+
+impl Writer for SplitWriter {
+    fn write_public_transport(&mut self, pt: &PublicTransport) -> Result<(), WriteError> {
+        self.write_file(pt.id.to_string(), |w| w.write_public_transport(pt))
+    }
+
+    fn write_area(&mut self, area: &Area) -> Result<(), WriteError> {
+        self.write_file(format!("{}{}", area.id_type, area.id), |w| {
+            w.write_area(area)
+        })
+    }
+
+    fn finish(&mut self) -> Result<(), WriteError> {
+        Ok(())
+    }
+}
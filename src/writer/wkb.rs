@@ -0,0 +1,71 @@
+use crate::parser::relation::LonLat;
+
+const POINT: u32 = 1;
+const LINESTRING: u32 = 2;
+const POLYGON: u32 = 3;
+const MULTILINESTRING: u32 = 5;
+
+fn write_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_f64(out: &mut Vec<u8>, value: f64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_header(out: &mut Vec<u8>, geometry_type: u32) {
+    // 1 = little endian
+    out.push(1);
+    write_u32(out, geometry_type);
+}
+
+fn write_coords(out: &mut Vec<u8>, coords: &[LonLat]) {
+    write_u32(out, coords.len() as u32);
+    for (lon, lat) in coords {
+        write_f64(out, *lon);
+        write_f64(out, *lat);
+    }
+}
+
+/// little endian WKB point
+pub fn point(p: LonLat) -> Vec<u8> {
+    let mut out = Vec::with_capacity(21);
+    write_header(&mut out, POINT);
+    write_f64(&mut out, p.0);
+    write_f64(&mut out, p.1);
+    out
+}
+
+/// little endian WKB multilinestring, one linestring per element of `lines`
+pub fn multilinestring(lines: &[Vec<LonLat>]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_header(&mut out, MULTILINESTRING);
+    write_u32(&mut out, lines.len() as u32);
+    for line in lines {
+        write_header(&mut out, LINESTRING);
+        write_coords(&mut out, line);
+    }
+    out
+}
+
+/// little endian WKB polygon, one ring per element of `rings`
+pub fn polygon(rings: &[Vec<LonLat>]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_header(&mut out, POLYGON);
+    write_u32(&mut out, rings.len() as u32);
+    for ring in rings {
+        write_coords(&mut out, ring);
+    }
+    out
+}
+
+/// converts a little endian WKB into EWKB adding the `srid`
+pub fn with_srid(wkb: Vec<u8>, srid: u32) -> Vec<u8> {
+    let mut out = Vec::with_capacity(wkb.len() + 4);
+    out.push(wkb[0]);
+    let geometry_type = u32::from_le_bytes([wkb[1], wkb[2], wkb[3], wkb[4]]);
+    write_u32(&mut out, geometry_type | 0x2000_0000);
+    write_u32(&mut out, srid);
+    out.extend_from_slice(&wkb[5..]);
+    out
+}
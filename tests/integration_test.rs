@@ -1,11 +1,34 @@
-use osmptparser::Parser;
+use osmptparser::admin::{AdminAreas, AdminHierarchy, HierarchyIssue};
+use osmptparser::index::SpatialIndex;
+use osmptparser::network::StopNetwork;
+use osmptparser::proj::Crs;
+use osmptparser::route::{normalize_colour, RouteType};
+use osmptparser::schedule::{Day, Schedule, TimeWindow};
+use osmptparser::simplify::{self, Algorithm};
+use osmptparser::stops::StopRegistry;
+use osmptparser::tiles::{TileOptions, TileSet, MAX_ZOOM};
+use osmptparser::timing::SpeedProfiles;
+use osmptparser::validator::{self, Rule};
+use osmptparser::writer::{
+    self, FgbWriter, Format, GpkgWriter, GpxWriter, KmlWriter, PgCopyWriter, SplitWriter, Writer,
+    WriterOptions,
+};
+use osmptparser::{
+    Area, GapFill, Member, MemberType, MissingMembers, Network, Node, ParseIssue, ParseStatus,
+    ParseStatusKind, Parser, PublicTransport, Relation, RoadGraph, StopArea, Way,
+};
+use std::collections::HashMap;
+use std::convert::TryInto;
 
 #[test]
+#[allow(clippy::unnecessary_sort_by, clippy::iter_count)]
 fn get_public_transports() {
     let parser = Parser::new_ptv2("tests/test.pbf", 1);
     let ptsvec = parser.get_public_transports(1500_f64);
     let mut pts = ptsvec.iter().collect::<Vec<_>>();
     pts.sort_by(|a, b| a.id.cmp(&b.id));
+    // both are type=route relations without a route_master tag
+    assert_eq!(pts.len(), 2);
     assert_eq!(pts[0].id, 85965);
     assert_eq!(pts[0].tags["name"], "Trolebus Quitumbe => La Y");
     assert_eq!(pts[0].info["version"], "226");
@@ -17,3 +40,1822 @@ fn get_public_transports() {
     assert_eq!(pts[1].info["timestamp"], "1555013271");
     assert_eq!(pts[1].stops.iter().count(), 1);
 }
+
+#[test]
+fn parse_status_issues() {
+    let parser = Parser::new_ptv2("tests/test.pbf", 1);
+    let mut pts = parser.get_public_transports(1500_f64);
+    pts.sort_by_key(|a| a.id);
+    assert_eq!(pts[0].parse_status.kind, ParseStatusKind::Broken);
+    assert_eq!(pts[0].parse_status.code(), 501);
+    assert_eq!(pts[0].parse_status.issues.len(), 1);
+    match &pts[0].parse_status.issues[0] {
+        ParseIssue::Gap(gap) => {
+            assert_eq!(gap.from_way, 149661020);
+            assert_eq!(gap.to_way, 149568931);
+            assert!(gap.distance > 1500_f64);
+        }
+        issue => panic!("unexpected issue {}", issue),
+    }
+    assert_eq!(pts[1].parse_status.kind, ParseStatusKind::Ok);
+    assert!(pts[1].parse_status.issues.is_empty());
+    assert!(!pts[1].parse_status.is_incomplete());
+    assert!(parser
+        .par_map(&|r| r.missing.is_empty())
+        .into_iter()
+        .all(|complete| complete));
+}
+
+fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn put_bytes(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    put_varint(out, field << 3 | 2);
+    put_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn put_packed<I: IntoIterator<Item = u64>>(out: &mut Vec<u8>, field: u64, values: I) {
+    let mut packed = Vec::new();
+    for value in values {
+        put_varint(&mut packed, value);
+    }
+    put_bytes(out, field, &packed);
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// zigzag encoded deltas, as the sint64 packed ids
+fn sint_deltas(ids: &[u64]) -> Vec<u64> {
+    let mut previous = 0_i64;
+    ids.iter()
+        .map(|id| {
+            let delta = *id as i64 - previous;
+            previous = *id as i64;
+            zigzag(delta)
+        })
+        .collect()
+}
+
+type PbfTags<'a> = Vec<(&'a str, &'a str)>;
+/// (id, type, role) of the members of a relation
+type PbfMembers<'a> = Vec<(u64, MemberType, &'a str)>;
+
+/// index of `s` in the pbf string table, added if it is not there
+fn string_id(strings: &mut Vec<String>, s: &str) -> u64 {
+    match strings.iter().position(|t| t == s) {
+        Some(i) => i as u64,
+        None => {
+            strings.push(s.to_string());
+            strings.len() as u64 - 1
+        }
+    }
+}
+
+fn put_tags(out: &mut Vec<u8>, strings: &mut Vec<String>, tags: &PbfTags) {
+    let ids: Vec<(u64, u64)> = tags
+        .iter()
+        .map(|(k, v)| (string_id(strings, k), string_id(strings, v)))
+        .collect();
+    put_packed(out, 2, ids.iter().map(|(k, _)| *k));
+    put_packed(out, 3, ids.iter().map(|(_, v)| *v));
+}
+
+/// writes a pbf file with uncompressed data blocks of
+/// nodes (id, lon, lat), ways (id, node ids, tags) and relations (id, members, tags)
+fn write_pbf(
+    path: &std::path::Path,
+    nodes: &[(u64, f64, f64)],
+    ways: &[(u64, Vec<u64>, PbfTags)],
+    relations: &[(u64, PbfMembers, PbfTags)],
+) {
+    let mut strings: Vec<String> = vec![String::new()];
+    let mut nodes_group = Vec::new();
+    for (id, lon, lat) in nodes {
+        let mut node = Vec::new();
+        put_varint(&mut node, 1 << 3);
+        put_varint(&mut node, zigzag(*id as i64));
+        put_varint(&mut node, 8 << 3);
+        put_varint(&mut node, zigzag((lat * 1e7).round() as i64));
+        put_varint(&mut node, 9 << 3);
+        put_varint(&mut node, zigzag((lon * 1e7).round() as i64));
+        put_bytes(&mut nodes_group, 1, &node);
+    }
+    let mut ways_group = Vec::new();
+    for (id, refs, tags) in ways {
+        let mut way = Vec::new();
+        put_varint(&mut way, 1 << 3);
+        put_varint(&mut way, *id);
+        put_tags(&mut way, &mut strings, tags);
+        put_packed(&mut way, 8, sint_deltas(refs));
+        put_bytes(&mut ways_group, 3, &way);
+    }
+    let mut relations_group = Vec::new();
+    for (id, members, tags) in relations {
+        let mut relation = Vec::new();
+        put_varint(&mut relation, 1 << 3);
+        put_varint(&mut relation, *id);
+        put_tags(&mut relation, &mut strings, tags);
+        let roles: Vec<u64> = members
+            .iter()
+            .map(|m| string_id(&mut strings, m.2))
+            .collect();
+        put_packed(&mut relation, 8, roles);
+        let ids: Vec<u64> = members.iter().map(|m| m.0).collect();
+        put_packed(&mut relation, 9, sint_deltas(&ids));
+        put_packed(
+            &mut relation,
+            10,
+            members.iter().map(|m| match m.1 {
+                MemberType::Node => 0,
+                MemberType::Way => 1,
+                MemberType::Relation => 2,
+            }),
+        );
+        put_bytes(&mut relations_group, 4, &relation);
+    }
+    let mut stringtable = Vec::new();
+    for s in &strings {
+        put_bytes(&mut stringtable, 1, s.as_bytes());
+    }
+    // a block per primitive group, osm_pbf_iter only reads the first group of a block
+    let mut file = Vec::new();
+    for group in &[nodes_group, ways_group, relations_group] {
+        let mut block = Vec::new();
+        put_bytes(&mut block, 1, &stringtable);
+        put_bytes(&mut block, 2, group);
+        let mut blob = Vec::new();
+        put_bytes(&mut blob, 1, &block);
+        let mut header = Vec::new();
+        put_bytes(&mut header, 1, b"OSMData");
+        put_varint(&mut header, 3 << 3);
+        put_varint(&mut header, blob.len() as u64);
+        file.extend(&(header.len() as u32).to_be_bytes());
+        file.extend(header);
+        file.extend(blob);
+    }
+    std::fs::write(path, file).unwrap();
+}
+
+#[test]
+fn missing_members() {
+    let path = std::env::temp_dir().join("osmptparser_missing_members.pbf");
+    let route = vec![("type", "route"), ("route", "bus"), ("name", "Incomplete")];
+    // way 11, node 3 of way 10, the nodes of way 12 and stop 4 are not in the file
+    write_pbf(
+        &path,
+        &[(1, 0.0, 0.0), (2, 0.001, 0.0), (5, 0.002, 0.0)],
+        &[
+            (10, vec![1, 2, 3], vec![("highway", "primary")]),
+            (12, vec![6, 7], vec![("highway", "primary")]),
+        ],
+        &[(
+            100,
+            vec![
+                (1, MemberType::Node, "stop"),
+                (4, MemberType::Node, "stop"),
+                (10, MemberType::Way, ""),
+                (11, MemberType::Way, ""),
+                (12, MemberType::Way, ""),
+            ],
+            route,
+        )],
+    );
+    let parser = Parser::new_ptv2(path.to_str().unwrap(), 1);
+    let pts = parser.get_public_transports(1500_f64);
+    assert_eq!(pts.len(), 1);
+    assert!(pts[0].parse_status.is_incomplete());
+    assert_eq!(
+        pts[0].parse_status.issues[0],
+        ParseIssue::MissingMembers(MissingMembers {
+            ways: vec![11],
+            nodes: vec![4, 3, 6, 7],
+        })
+    );
+    assert_eq!(pts[0].stops.len(), 1);
+    // the way without nodes is dropped, the one with a node left is kept
+    assert_eq!(pts[0].geometry, vec![vec![(0.0, 0.0), (0.001, 0.0)]]);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn sort_ways() {
+    // 1-2-3-4-5-6 with the ways interleaved and one of them reversed
+    let relation = Relation {
+        id: 1,
+        tags: HashMap::new(),
+        info: HashMap::new(),
+        ways: vec![
+            way(10, &[1, 2], &[]),
+            way(30, &[4, 3], &[]),
+            way(50, &[5, 6], &[]),
+            way(20, &[2, 3], &[]),
+            way(40, &[4, 5], &[]),
+        ],
+        stops: Vec::new(),
+        members: Vec::new(),
+        missing: MissingMembers::default(),
+    };
+    let (geometry, status) = relation.flatten_ways(0_f64, false).unwrap();
+    assert_eq!(status.kind, ParseStatusKind::Sorted);
+    assert_eq!(
+        status.issues,
+        vec![ParseIssue::WaysSorted {
+            way_ids: vec![10, 20, 30, 40, 50]
+        }]
+    );
+    let ids: Vec<u64> = geometry[0].iter().map(|n| n.id).collect();
+    assert_eq!(ids, vec![1, 2, 3, 4, 5, 6]);
+}
+
+#[test]
+fn against_oneway() {
+    // bus route 1-2-3-4, the direction comes from the member order of the ways
+    let against = |ways: Vec<Way>| -> Vec<ParseIssue> {
+        let relation = Relation {
+            id: 1,
+            tags: vec![("route".to_string(), "bus".to_string())]
+                .into_iter()
+                .collect(),
+            info: HashMap::new(),
+            ways,
+            stops: Vec::new(),
+            members: Vec::new(),
+            missing: MissingMembers::default(),
+        };
+        let (_, status) = relation.flatten_route(0_f64, false).unwrap();
+        status
+            .issues
+            .into_iter()
+            .filter(|issue| matches!(issue, ParseIssue::AgainstOneway { .. }))
+            .collect()
+    };
+    assert_eq!(
+        against(vec![
+            way(10, &[1, 2], &[("oneway", "yes")]),
+            way(20, &[2, 3], &[("oneway", "-1")]),
+            way(30, &[4, 3], &[("oneway", "yes")]),
+        ]),
+        vec![
+            ParseIssue::AgainstOneway {
+                way_id: 20,
+                from_node: 2,
+                to_node: 3,
+            },
+            ParseIssue::AgainstOneway {
+                way_id: 30,
+                from_node: 3,
+                to_node: 4,
+            },
+        ]
+    );
+    // oneway=-1 drawn against the route, a roundabout and the bus exceptions
+    assert!(against(vec![
+        way(10, &[1, 2], &[("junction", "roundabout")]),
+        way(20, &[3, 2], &[("oneway", "-1")]),
+        way(30, &[4, 3], &[("oneway", "yes"), ("oneway:bus", "no")]),
+    ])
+    .is_empty());
+    assert_eq!(
+        against(vec![
+            way(10, &[1, 2], &[]),
+            way(20, &[2, 3], &[("oneway", "no"), ("oneway:psv", "-1")]),
+            way(30, &[3, 4], &[]),
+        ]),
+        vec![ParseIssue::AgainstOneway {
+            way_id: 20,
+            from_node: 2,
+            to_node: 3,
+        }]
+    );
+}
+
+#[test]
+fn partial_geometry() {
+    let parser = Parser::new_ptv2("tests/test.pbf", 1);
+    let mut pts = parser.get_public_transports_partial(1500_f64);
+    pts.sort_by_key(|a| a.id);
+    let status = &pts[0].parse_status;
+    assert_eq!(status.kind, ParseStatusKind::Broken);
+    assert_eq!(status.gap_count(), 1);
+    assert_eq!(pts[0].geometry.len(), status.gap_count() + 1);
+    assert!(status.gap_length() > 1500_f64);
+    assert_eq!(status.gap_locations().len(), 1);
+    assert_eq!(pts[1].geometry.len(), 1);
+
+    // positions are nested once in a LineString, twice in a MultiLineString
+    let geometry = |pt: &PublicTransport| -> serde_json::Value {
+        let geojson: serde_json::Value = serde_json::from_str(&pt.to_geojson()).unwrap();
+        geojson["features"][0]["geometry"].clone()
+    };
+    let multi = geometry(&pts[0]);
+    assert_eq!(multi["type"], "MultiLineString");
+    assert_eq!(multi["coordinates"].as_array().unwrap().len(), 2);
+    assert!(multi["coordinates"][0][0][0].is_f64());
+    let single = geometry(&pts[1]);
+    assert_eq!(single["type"], "LineString");
+    assert_eq!(
+        single["coordinates"].as_array().unwrap().len(),
+        pts[1].geometry[0].len()
+    );
+    assert!(single["coordinates"][0][0].is_f64());
+}
+
+#[test]
+fn road_graph_gap_fill() {
+    let graph = RoadGraph::new("tests/test.pbf", 1);
+    let path = graph
+        .shortest_path(344879308, 345729495, Network::Road, 10000_f64)
+        .unwrap();
+    assert_eq!(path.way_ids(), vec![423658293]);
+    assert_eq!(path.edge_way_ids.len(), path.nodes.len() - 1);
+    assert!(path.length > 1100_f64 && path.length < 1200_f64);
+    assert!(graph
+        .shortest_path(344879308, 345729495, Network::Road, 100_f64)
+        .is_none());
+    assert!(graph
+        .shortest_path(344879308, 345729495, Network::Rail, 10000_f64)
+        .is_none());
+
+    // the test file only has the ways of the relations, so there is nothing to fill the gaps with
+    let mut parser = Parser::new_ptv2("tests/test.pbf", 1);
+    parser.set_gap_fill(Some(GapFill::new(graph, 1000_f64)));
+    let mut pts = parser.get_public_transports(1500_f64);
+    pts.sort_by_key(|a| a.id);
+    assert_eq!(pts[0].parse_status.code(), 501);
+    assert!(!pts[0]
+        .parse_status
+        .issues
+        .iter()
+        .any(|i| matches!(i, ParseIssue::GapFilled { .. })));
+}
+
+#[test]
+fn road_graph_fills_gap() {
+    let path = std::env::temp_dir().join("osmptparser_gap_fill.pbf");
+    let fill = |route: &str, road: PbfTags| -> PublicTransport {
+        // way 20 is not a member of the route and joins the gap between ways 10 and 30
+        write_pbf(
+            &path,
+            &[
+                (1, 0.0, 0.0),
+                (2, 0.01, 0.0),
+                (3, 0.02, 0.0),
+                (4, 0.03, 0.0),
+                (5, 0.04, 0.0),
+            ],
+            &[
+                (10, vec![1, 2], vec![("highway", "primary")]),
+                (20, vec![2, 3, 4], road),
+                (30, vec![4, 5], vec![("highway", "primary")]),
+            ],
+            &[(
+                100,
+                vec![(10, MemberType::Way, ""), (30, MemberType::Way, "")],
+                vec![("type", "route"), ("route", route), ("name", "Gap")],
+            )],
+        );
+        let graph = RoadGraph::new(path.to_str().unwrap(), 1);
+        let mut parser = Parser::new_ptv2(path.to_str().unwrap(), 1);
+        parser.set_gap_fill(Some(GapFill::new(graph, 1000_f64)));
+        parser.get_public_transports(1500_f64).remove(0)
+    };
+    let filled = |pt: &PublicTransport| -> Vec<Vec<u64>> {
+        pt.parse_status
+            .issues
+            .iter()
+            .filter_map(|i| match i {
+                ParseIssue::GapFilled { way_ids, .. } => Some(way_ids.clone()),
+                _ => None,
+            })
+            .collect()
+    };
+
+    let pt = fill("bus", vec![("highway", "secondary")]);
+    assert_eq!(filled(&pt), vec![vec![20]]);
+    assert_eq!(pt.geometry.len(), 1);
+    assert_eq!(pt.geometry[0].len(), 5);
+
+    // the bus can not travel way 20 against its oneway direction
+    let pt = fill("bus", vec![("highway", "secondary"), ("oneway", "-1")]);
+    assert!(filled(&pt).is_empty());
+    assert_eq!(pt.parse_status.code(), 501);
+
+    let bus_lane = vec![
+        ("highway", "secondary"),
+        ("oneway", "-1"),
+        ("oneway:bus", "no"),
+    ];
+    let pt = fill("bus", bus_lane.clone());
+    assert_eq!(filled(&pt), vec![vec![20]]);
+
+    // oneway:bus does not apply to the other route types
+    let pt = fill("trolleybus", bus_lane);
+    assert!(filled(&pt).is_empty());
+    let pt = fill(
+        "trolleybus",
+        vec![
+            ("highway", "secondary"),
+            ("oneway", "-1"),
+            ("oneway:trolleybus", "no"),
+        ],
+    );
+    assert_eq!(filled(&pt), vec![vec![20]]);
+}
+
+#[test]
+fn validate() {
+    let pts = public_transports(1500_f64);
+    let rules = |i: usize| -> Vec<Rule> {
+        validator::validate(&pts[i])
+            .findings
+            .iter()
+            .map(|f| f.rule)
+            .collect()
+    };
+    let rules_85965 = rules(0);
+    assert!(rules_85965.contains(&Rule::PtVersion));
+    assert!(rules_85965.contains(&Rule::MemberOrder));
+    assert!(rules_85965.contains(&Rule::Gap));
+    assert_eq!(rules(1), vec![Rule::PtVersion, Rule::NameFromTo]);
+
+    // stop positions are checked by node id, not by the coordinates of the (maybe simplified) line
+    let mut simplified = pts[1].clone();
+    let line = &simplified.geometry[0];
+    simplified.geometry = vec![vec![line[0], line[line.len() - 1]]];
+    let findings = validator::validate(&simplified).findings;
+    assert!(findings.iter().all(|f| f.rule != Rule::StopNotOnRoute));
+    let mut moved = pts[1].clone();
+    let stop_id = moved.stops[0].id;
+    moved.stops[0].id = 1;
+    for member in moved.members.iter_mut().filter(|m| m.id == stop_id) {
+        member.id = 1;
+    }
+    let findings = validator::validate(&moved).findings;
+    let not_on_route: Vec<u64> = findings
+        .iter()
+        .filter(|f| f.rule == Rule::StopNotOnRoute)
+        .map(|f| f.osm_id)
+        .collect();
+    assert_eq!(not_on_route, vec![1]);
+}
+
+#[test]
+fn schedule() {
+    let tags: HashMap<String, String> = vec![
+        ("interval", "00:15"),
+        (
+            "interval:conditional",
+            "00:05 @ (Mo-Fr 06:00-09:00, 16:00-19:00); 30 @ (Su 22:00-01:00)",
+        ),
+        ("duration", "01:10"),
+        ("opening_hours", "Mo-Sa 05:00-23:00; Su 07:00-01:00; PH off"),
+    ]
+    .into_iter()
+    .map(|(k, v)| (k.to_string(), v.to_string()))
+    .collect();
+    let (schedule, issues) = Schedule::from_tags(&tags);
+    assert!(issues.is_empty());
+    assert_eq!(schedule.interval, Some(15_f64));
+    assert_eq!(schedule.duration, Some(70_f64));
+    assert_eq!(schedule.headways.len(), 3);
+    assert_eq!(schedule.headway_at(Day::We, 7 * 60), Some(5_f64));
+    assert_eq!(schedule.headway_at(Day::We, 12 * 60), Some(15_f64));
+    assert_eq!(schedule.headway_at(Day::Sa, 7 * 60), Some(15_f64));
+    assert_eq!(schedule.headway_at(Day::Sa, 4 * 60), None);
+    assert_eq!(schedule.headway_at(Day::Su, 24 * 60 + 30), Some(30_f64));
+    assert_eq!(schedule.headway_at(Day::Mo, 30), None);
+    assert_eq!(schedule.headway_at(Day::Ph, 12 * 60), None);
+    let frequencies = schedule.frequencies(Day::Mo);
+    assert_eq!(frequencies.len(), 2);
+    assert_eq!(
+        frequencies[1].0,
+        TimeWindow {
+            start: 960,
+            end: 1140
+        }
+    );
+    assert_eq!(
+        schedule.frequencies(Day::Sa),
+        vec![(
+            TimeWindow {
+                start: 300,
+                end: 1380
+            },
+            15_f64
+        )]
+    );
+    assert_eq!(schedule.to_json()["service"][1]["windows"][0][1], "25:00");
+
+    let tags: HashMap<String, String> =
+        vec![("interval", "often"), ("opening_hours", "sunrise-sunset")]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+    let (schedule, issues) = Schedule::from_tags(&tags);
+    assert!(schedule.is_empty());
+    assert_eq!(issues.len(), 2);
+    assert!(matches!(&issues[0], ParseIssue::InvalidSchedule { key, .. } if key == "interval"));
+
+    // 99999999 hours do not fit in u32 minutes
+    let tags: HashMap<String, String> = vec![("duration", "99999999:00")]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    let (overflow, overflow_issues) = Schedule::from_tags(&tags);
+    assert_eq!(overflow.duration, None);
+    assert!(
+        matches!(&overflow_issues[..], [ParseIssue::InvalidSchedule { key, .. }] if key == "duration")
+    );
+
+    let mut pts = public_transports(1500_f64);
+    assert!(pts.iter().all(|pt| pt.schedule.is_empty()));
+    let mut pt = pts.remove(1);
+    pt.parse_status.issues.extend(issues);
+    let findings = validator::validate(&pt).findings;
+    assert_eq!(
+        findings
+            .iter()
+            .filter(|f| f.rule == Rule::InvalidSchedule)
+            .count(),
+        2
+    );
+}
+
+#[test]
+fn route_info() {
+    assert_eq!(normalize_colour("#8FD952"), Some("#8fd952".to_string()));
+    assert_eq!(normalize_colour("#f00"), Some("#ff0000".to_string()));
+    assert_eq!(normalize_colour("Dark Green"), Some("#006400".to_string()));
+    assert_eq!(normalize_colour("8fd952"), Some("#8fd952".to_string()));
+    assert_eq!(normalize_colour("rainbow"), None);
+
+    let mut pts = public_transports(1500_f64);
+    let pt = &mut pts[0];
+    assert_eq!(pt.route_ref(), Some("T1"));
+    assert_eq!(pt.route_type(), RouteType::Trolleybus);
+    assert_eq!(pt.route_type().gtfs_route_type(), 11);
+    assert_eq!(pt.colour(), Some("#8fd952".to_string()));
+    assert_eq!(pt.network(), Some("Metrobus-Q"));
+    assert_eq!(pt.from(), Some("Terminal Quitumbe"));
+    assert_eq!(pt.to(), Some("Terminal La Y"));
+    pt.tags.insert(
+        "name:en".to_string(),
+        "Trolleybus Quitumbe => La Y".to_string(),
+    );
+    assert_eq!(pt.name(&["es"]), Some("Trolebus Quitumbe => La Y"));
+    assert_eq!(pt.name(&["fr", "en"]), Some("Trolleybus Quitumbe => La Y"));
+    pt.tags.insert("colour".to_string(), "blue".to_string());
+    pt.tags
+        .insert("route".to_string(), "hovercraft".to_string());
+    let properties = pt.flat_properties(&["en"]);
+    assert_eq!(properties["colour"], "#0000ff");
+    assert_eq!(properties["route_type"], "other");
+    assert_eq!(properties["name"], "Trolleybus Quitumbe => La Y");
+    assert!(properties["ref"] == "T1" && properties["operator"].is_string());
+
+    let options = WriterOptions {
+        flat_properties: Some(vec!["en".to_string()]),
+        ..WriterOptions::default()
+    };
+    assert!(writer::create_with(Format::Kml, None, options).is_err());
+}
+
+#[test]
+fn stop_positions() {
+    let pts = public_transports(15000_f64);
+    let positions = pts[0].stop_positions();
+    assert_eq!(positions.len(), pts[0].stops.len());
+    assert!(positions.iter().all(|p| p.is_some()));
+    let first = positions[0].unwrap();
+    assert_eq!(first.offset, 0_f64);
+    assert!(first.distance > 10500_f64 && first.distance < 11000_f64);
+    let last = positions[positions.len() - 1].unwrap();
+    assert!(last.distance > first.distance);
+    assert!(pts[0].parse_status.issues.contains(&ParseIssue::Reversed));
+    // stop 345190302 is 2m from the start of the line, but in order 6m from it between its neighbours
+    assert!(positions
+        .windows(2)
+        .all(|pair| pair[0].unwrap().distance < pair[1].unwrap().distance));
+    let stop = pts[0]
+        .stops
+        .iter()
+        .position(|stop| stop.id == 345190302)
+        .unwrap();
+    assert!(positions[stop].unwrap().distance > 21000_f64);
+    let order_errors = |pt: &PublicTransport| -> Vec<u64> {
+        validator::validate(pt)
+            .findings
+            .iter()
+            .filter(|f| f.rule == Rule::StopOrder)
+            .map(|f| f.osm_id)
+            .collect()
+    };
+    assert!(order_errors(&pts[0]).is_empty());
+
+    // circular route starting and ending at the same stop
+    let mut circular = stop_route(
+        1,
+        &pts[1],
+        vec![
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.005),
+            node(3, 0.005, 0.01),
+            node(4, 0.0, 0.005),
+            node(1, 0.0, 0.0),
+        ],
+    );
+    circular.geometry = vec![vec![
+        (0.0, 0.0),
+        (0.01, 0.0),
+        (0.01, 0.01),
+        (0.0, 0.01),
+        (0.0, 0.0),
+    ]];
+    let positions: Vec<f64> = circular
+        .stop_positions()
+        .iter()
+        .map(|p| p.unwrap().distance)
+        .collect();
+    assert_eq!(positions[0], 0_f64);
+    assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+    // the last stop is at the end of the loop, not at its start
+    assert!(positions[4] > 4400_f64);
+    assert!(order_errors(&circular).is_empty());
+    circular.stops.swap(1, 2);
+    circular.members.swap(1, 2);
+    assert_eq!(order_errors(&circular), vec![3]);
+}
+
+#[test]
+fn simplify() {
+    let pts = public_transports(15000_f64);
+    let points = |pt: &osmptparser::PublicTransport| -> usize {
+        pt.geometry.iter().map(|part| part.len()).sum()
+    };
+    let snapped: Vec<bool> = pts[0]
+        .stop_positions()
+        .iter()
+        .map(|p| p.is_some_and(|p| p.offset == 0_f64))
+        .collect();
+    for algorithm in [Algorithm::DouglasPeucker, Algorithm::Visvalingam] {
+        let mut pt = pts[0].clone();
+        pt.simplify(algorithm, 10_f64);
+        assert!(points(&pt) < points(&pts[0]));
+        for (position, was_snapped) in pt.stop_positions().iter().zip(&snapped) {
+            if *was_snapped {
+                assert_eq!(position.unwrap().offset, 0_f64);
+            }
+        }
+    }
+
+    let parser = Parser::new("tests/test.pbf", 1, "type".to_string());
+    for mut area in parser.get_areas(15000_f64) {
+        area.simplify(Algorithm::DouglasPeucker, 50_f64);
+        for ring in &area.geometry {
+            assert!(ring.len() >= 4);
+            assert_eq!(ring.first(), ring.last());
+        }
+    }
+
+    let mut area = square_with_hole();
+    area.simplify(Algorithm::DouglasPeucker, 50_f64);
+    assert_eq!(area.geometry.len(), 2);
+    assert_eq!(area.geometry[0].len(), 5);
+    for ring in &area.geometry {
+        assert_eq!(ring.first(), ring.last());
+    }
+
+    // removing the 33m spike of the outer ring would cut through the hole, the tolerance is halved
+    let spike = (0.005, 0.0103);
+    let outer = vec![
+        (0.0, 0.0),
+        (0.01, 0.0),
+        (0.01, 0.01),
+        (0.006, 0.01),
+        spike,
+        (0.004, 0.01),
+        (0.0, 0.01),
+        (0.0, 0.0),
+    ];
+    let hole = vec![
+        (0.0049, 0.0099),
+        (0.0051, 0.0099),
+        (0.0051, 0.01015),
+        (0.0049, 0.01015),
+        (0.0049, 0.0099),
+    ];
+    let simplified =
+        simplify::simplify_polygon(&[outer.clone(), hole], Algorithm::DouglasPeucker, 40_f64);
+    assert!(simplified[0].contains(&spike));
+    let simplified = simplify::simplify_polygon(&[outer], Algorithm::DouglasPeucker, 40_f64);
+    assert!(!simplified[0].contains(&spike));
+}
+
+/// 0.01° square at the equator with a 0.002° square hole, a point every 0.001° on the outer ring
+fn square_with_hole() -> Area {
+    let mut outer: Vec<(f64, f64)> = Vec::new();
+    for (x0, y0, dx, dy) in [
+        (0_f64, 0_f64, 1_f64, 0_f64),
+        (10_f64, 0_f64, 0_f64, 1_f64),
+        (10_f64, 10_f64, -1_f64, 0_f64),
+        (0_f64, 10_f64, 0_f64, -1_f64),
+    ] {
+        for i in 0..10 {
+            let i = i as f64;
+            outer.push(((x0 + i * dx) / 1000_f64, (y0 + i * dy) / 1000_f64));
+        }
+    }
+    outer.push(outer[0]);
+    let hole = vec![
+        (0.001, 0.001),
+        (0.001, 0.003),
+        (0.003, 0.003),
+        (0.003, 0.001),
+        (0.001, 0.001),
+    ];
+    Area {
+        id: 1,
+        id_type: 'w',
+        tags: HashMap::new(),
+        info: HashMap::new(),
+        geometry: vec![outer, hole],
+        parse_status: ParseStatus::new(ParseStatusKind::Ok, Vec::new()),
+        parent_id: None,
+    }
+}
+
+#[test]
+fn metrics() {
+    let pts = public_transports(15000_f64);
+    let metrics = pts[0].metrics();
+    assert!(metrics.length > 37000_f64 && metrics.length < 39000_f64);
+    assert_eq!(metrics.stop_count, pts[0].stops.len());
+    let (min, avg, max) = (
+        metrics.stop_spacing_min.unwrap(),
+        metrics.stop_spacing_avg.unwrap(),
+        metrics.stop_spacing_max.unwrap(),
+    );
+    assert!(min <= avg && avg <= max);
+    assert!(metrics.sinuosity.unwrap() >= 1_f64);
+    let (minx, miny, maxx, maxy) = metrics.bbox.unwrap();
+    assert!(minx < maxx && miny < maxy);
+    assert!(pts[0]
+        .to_geojson_with_metrics()
+        .contains("\"stop_spacing_avg\""));
+
+    let metrics = square_with_hole().metrics();
+    // 1112 m side square minus a 222.4 m side hole
+    assert!((metrics.area - 1_186_976_f64).abs() < 1_000_f64);
+    assert!((metrics.perimeter - 5_337.4).abs() < 1_f64);
+    let (cx, cy) = metrics.centroid.unwrap();
+    assert!(cx > 0.005 && cy > 0.005);
+    let (px, py) = metrics.pole_of_inaccessibility.unwrap();
+    assert!(px > 0.004 && px < 0.008 && py > 0.004 && py < 0.008);
+
+    // two outer rings, the hole of the first one before it
+    let mut area = square_with_hole();
+    let outer = area.geometry.remove(0);
+    let island = vec![
+        (0.02, 0.0),
+        (0.03, 0.0),
+        (0.03, 0.005),
+        (0.02, 0.005),
+        (0.02, 0.0),
+    ];
+    area.geometry.insert(0, island);
+    area.geometry.push(outer);
+    let metrics = area.metrics();
+    // plus a 1112 m x 556 m rectangle
+    assert!((metrics.area - 1_805_193_f64).abs() < 1_000_f64);
+    let (cx, cy) = metrics.centroid.unwrap();
+    assert!(cx > 0.01 && cx < 0.02 && cy > 0.004 && cy < 0.005);
+    let (px, py) = metrics.pole_of_inaccessibility.unwrap();
+    assert!(px > 0.004 && px < 0.008 && py > 0.004 && py < 0.008);
+}
+
+#[test]
+fn spatial_index() {
+    let pts = public_transports(15000_f64);
+    let stop = pts[0].stops[0].clone();
+    let offset = pts[0].stop_positions()[0].unwrap().offset;
+    let index = SpatialIndex::new(pts.clone(), vec![square_with_hole()]);
+
+    let near = index.routes.within((stop.lon, stop.lat), 300_f64);
+    assert_eq!(near[0].0.id, pts[0].id);
+    assert!((near[0].1 - offset).abs() < 1e-6);
+    let nearest = index.stops.nearest((stop.lon, stop.lat), 3);
+    assert_eq!(nearest.len(), 3);
+    assert_eq!(nearest[0].0.id, stop.id);
+    assert_eq!(nearest[0].1, 0_f64);
+    assert!(nearest[1].1 <= nearest[2].1);
+    let mut brute: Vec<f64> = index
+        .stops
+        .items()
+        .iter()
+        .map(|s| osmptparser::index::Spatial::distance(s, (stop.lon, stop.lat)))
+        .collect();
+    brute.sort_by(|a, b| a.total_cmp(b));
+    assert_eq!(nearest[2].1, brute[2]);
+    assert_eq!(
+        index.routes.in_bbox((-180.0, -90.0, 180.0, 90.0)).len(),
+        pts.iter().filter(|pt| !pt.geometry.is_empty()).count()
+    );
+
+    assert_eq!(index.areas.containing((0.005, 0.005)).len(), 1);
+    assert!(index.areas.containing((0.002, 0.002)).is_empty());
+    assert!(index.areas.containing((0.02, 0.005)).is_empty());
+    assert!(index.areas.nearest((0.011, 0.005), 1)[0].1 < 112_f64);
+}
+
+#[test]
+fn stop_registry() {
+    let pts = public_transports(1500_f64);
+    // same stops served by another route
+    let mut other = pts[0].clone();
+    other.id = 1;
+    other.tags.insert("ref".to_string(), "X1".to_string());
+    let (a, b) = (pts[0].stops[0].id, pts[0].stops[1].id);
+    let member = |id: u64, role: &str| Member {
+        id,
+        member_type: MemberType::Node,
+        role: role.to_string(),
+    };
+    let stop_area = StopArea {
+        id: 10,
+        tags: vec![("name".to_string(), "Station".to_string())]
+            .into_iter()
+            .collect(),
+        members: vec![member(a, "stop"), member(b, "platform")],
+    };
+
+    let mut registry = StopRegistry::new(vec![stop_area]);
+    for pt in pts.iter().chain(std::iter::once(&other)) {
+        registry.add_public_transport(pt);
+    }
+    let total: usize = pts.iter().map(|pt| pt.stops.len()).sum();
+    assert_eq!(registry.len(), total);
+    assert_eq!(registry.routes_serving(a), &[1, pts[0].id]);
+    assert!(registry.routes_serving(0).is_empty());
+    assert_eq!(registry.stop_area_of(b).unwrap().id, 10);
+    assert_eq!(registry.stop_area_stops(10).len(), 2);
+    assert_eq!(registry.stop_area_routes(10), vec![1, pts[0].id]);
+
+    let geojson = registry.to_geojson();
+    let feature = geojson["features"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|f| f["properties"]["id"] == a)
+        .unwrap();
+    let mut refs = vec!["X1".to_string()];
+    refs.extend(pts[0].tags.get("ref").cloned());
+    refs.sort();
+    assert_eq!(feature["properties"]["route_refs"], serde_json::json!(refs));
+    assert_eq!(feature["properties"]["stop_area"]["name"], "Station");
+}
+
+#[test]
+fn segments() {
+    let pts = public_transports(15000_f64);
+    let pt = &pts[0];
+    let sequence = pt.stop_sequence();
+    let segments = pt.segments();
+    assert_eq!(segments.len(), sequence.len() - 1);
+    assert_eq!(segments[0].from, sequence[0].id);
+    assert_eq!(segments[0].to, sequence[1].id);
+    for pair in segments.windows(2) {
+        assert_eq!(pair[0].end, pair[1].start);
+        assert_eq!(pair[0].geometry.last(), pair[1].geometry.first());
+    }
+    for segment in &segments {
+        assert!(segment.geometry.len() >= 2);
+        assert!((segment.length - (segment.end - segment.start).abs()).abs() < 1e-9);
+    }
+    let total: f64 = segments.iter().map(|s| s.length).sum();
+    let last = &segments[segments.len() - 1];
+    assert!((total - (last.end - segments[0].start)).abs() < 1e-6);
+    assert!(total <= pt.metrics().length);
+
+    let geojson = pt.segments_geojson();
+    assert_eq!(
+        geojson["features"].as_array().unwrap().len(),
+        segments.len()
+    );
+    assert_eq!(
+        geojson["features"][0]["properties"]["from_name"],
+        "Terminal Terrestre Quitumbe"
+    );
+
+    let no_geometry = stop_route(1, pt, sequence.iter().map(|s| (*s).clone()).collect());
+    let segments = no_geometry.segments();
+    assert_eq!(segments.len(), sequence.len() - 1);
+    assert!(segments
+        .iter()
+        .all(|s| s.geometry.is_empty() && s.length == 0_f64));
+    let geojson = no_geometry.segments_geojson();
+    assert!(geojson["features"][0]["geometry"].is_null());
+
+    // out and back route, visiting stops 2 and 1 again on the way back
+    let mut out_and_back = stop_route(
+        2,
+        pt,
+        vec![
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.0),
+            node(3, 0.02, 0.0),
+            node(2, 0.01, 0.0),
+            node(1, 0.0, 0.0),
+        ],
+    );
+    out_and_back.geometry = vec![vec![
+        (0.0, 0.0),
+        (0.01, 0.0),
+        (0.02, 0.0),
+        (0.01, 0.0),
+        (0.0, 0.0),
+    ]];
+    let segments = out_and_back.segments();
+    let stops: Vec<(u64, u64)> = segments.iter().map(|s| (s.from, s.to)).collect();
+    assert_eq!(stops, vec![(1, 2), (2, 3), (3, 2), (2, 1)]);
+    // 0.01° at the equator
+    for (i, segment) in segments.iter().enumerate() {
+        assert!((segment.start - i as f64 * 1111.95).abs() < 1_f64);
+        assert!((segment.length - 1111.95).abs() < 1_f64);
+    }
+    assert_eq!(segments[2].geometry, vec![(0.02, 0.0), (0.01, 0.0)]);
+    assert_eq!(segments[3].geometry, vec![(0.01, 0.0), (0.0, 0.0)]);
+
+    // two stops at the same point of the line
+    let mut same_point = stop_route(
+        3,
+        pt,
+        vec![
+            node(1, 0.0, 0.0),
+            node(2, 0.01, 0.0),
+            node(5, 0.01, 0.0),
+            node(3, 0.02, 0.0),
+        ],
+    );
+    same_point.geometry = vec![vec![(0.0, 0.0), (0.01, 0.0), (0.02, 0.0)]];
+    assert_eq!(same_point.segments()[1].length, 0_f64);
+    let geojson = same_point.segments_geojson();
+    let features = geojson["features"].as_array().unwrap();
+    assert_eq!(features.len(), 3);
+    assert!(features[1]["geometry"].is_null());
+    assert_eq!(features[1]["properties"]["from"], 2);
+    assert_eq!(features[2]["geometry"]["type"], "LineString");
+}
+
+#[test]
+fn travel_time() {
+    let profiles = SpeedProfiles::default();
+    let bus = profiles.profile(Some(&"bus".to_string()));
+    let tags = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    };
+    assert_eq!(bus.speed(Some(&tags(&[("maxspeed", "50")]))), 35_f64);
+    assert!((bus.speed(Some(&tags(&[("maxspeed", "30 mph")]))) - 33.796).abs() < 1e-3);
+    assert_eq!(
+        bus.speed(Some(&tags(&[
+            ("maxspeed", "EC:urban"),
+            ("highway", "primary")
+        ]))),
+        35_f64
+    );
+    assert_eq!(bus.speed(Some(&tags(&[("maxspeed", "200")]))), 80_f64);
+    assert_eq!(bus.speed(None), bus.default_speed);
+
+    let custom = SpeedProfiles::from_json(&serde_json::json!({
+        "bus": {"dwell_time": 45, "highway": {"primary": 50}},
+        "ferry": {"default_speed": 15},
+    }))
+    .unwrap();
+    let bus = custom.profile(Some(&"bus".to_string()));
+    assert_eq!(bus.dwell_time, 45_f64);
+    assert_eq!(bus.speed(Some(&tags(&[("highway", "primary")]))), 50_f64);
+    assert_eq!(bus.speed(Some(&tags(&[("highway", "secondary")]))), 30_f64);
+    assert_eq!(
+        custom.profile(Some(&"ferry".to_string())).default_speed,
+        15_f64
+    );
+    assert!(SpeedProfiles::from_json(&serde_json::json!({"bus": {"speed": 1}})).is_err());
+    // a speed or factor of 0 would make the running times infinite or 0, dwell_time can be 0
+    assert_eq!(
+        SpeedProfiles::from_json(&serde_json::json!({"bus": {"maxspeed_factor": 0}})),
+        Err("bus: maxspeed_factor must be a positive number, found 0".to_string())
+    );
+    assert!(
+        SpeedProfiles::from_json(&serde_json::json!({"bus": {"highway": {"primary": 0}}})).is_err()
+    );
+    assert!(SpeedProfiles::from_json(&serde_json::json!({"bus": {"dwell_time": 0}})).is_ok());
+
+    let pts = public_transports(15000_f64);
+    let pt = &pts[0];
+    assert_eq!(pt.segment_ways.len(), pt.geometry.len());
+    let time = pt.travel_time(&profiles);
+    assert_eq!(time.segments.len(), pt.segments().len());
+    assert!(time.segments.iter().all(|s| s.running_time > 0_f64));
+    let expected = time.running_time + time.dwell_time * (time.segments.len() - 1) as f64;
+    assert!((time.total_time - expected).abs() < 1e-6);
+    let stop_times = time.stop_times();
+    assert_eq!(stop_times.len(), time.segments.len() + 1);
+    assert!((stop_times.last().unwrap().1 - time.total_time).abs() < 1e-6);
+
+    let path = std::env::temp_dir().join("osmptparser_test_gtfs");
+    let _ = std::fs::remove_dir_all(&path);
+    let mut writer = writer::create(Format::Gtfs, Some(&path)).unwrap();
+    for pt in &pts {
+        writer.write_public_transport(pt).unwrap();
+    }
+    writer.finish().unwrap();
+    let lines = |name: &str| {
+        std::fs::read_to_string(path.join(name))
+            .unwrap()
+            .lines()
+            .count()
+    };
+    // B6 has a single stop, only T1 has a trip
+    assert_eq!(lines("trips.txt"), 2);
+    assert_eq!(lines("stop_times.txt"), stop_times.len() + 1);
+    assert_eq!(lines("agency.txt"), 2);
+    assert_eq!(lines("calendar.txt"), 2);
+    assert!(lines("shapes.txt") > 2);
+    assert!(writer::create(Format::Gtfs, None).is_err());
+    std::fs::remove_dir_all(&path).unwrap();
+
+    // stop 2 is listed after stop 3 but it is before it on the line
+    let mut backwards = stop_route(
+        1,
+        pt,
+        vec![node(1, 0.0, 0.0), node(3, 0.02, 0.0), node(2, 0.01, 0.0)],
+    );
+    backwards.geometry = vec![vec![(0.0, 0.0), (0.01, 0.0), (0.02, 0.0)]];
+    backwards.segment_ways = vec![vec![None; 3]];
+    let mut writer = writer::create(Format::Gtfs, Some(&path)).unwrap();
+    writer.write_public_transport(&backwards).unwrap();
+    writer.finish().unwrap();
+    let distances: Vec<f64> = std::fs::read_to_string(path.join("stop_times.txt"))
+        .unwrap()
+        .lines()
+        .skip(1)
+        .map(|line| line.rsplit(',').next().unwrap().parse().unwrap())
+        .collect();
+    assert_eq!(distances.len(), 3);
+    assert!(distances.windows(2).all(|pair| pair[0] <= pair[1]));
+    assert!((distances[2] - 2223.9).abs() < 0.1);
+    std::fs::remove_dir_all(&path).unwrap();
+}
+
+/// ptv2 routes of tests/test.pbf merged with `gap` meters of tolerance, sorted by id
+fn public_transports(gap: f64) -> Vec<PublicTransport> {
+    let parser = Parser::new_ptv2("tests/test.pbf", 1);
+    let mut pts = parser.get_public_transports(gap);
+    pts.sort_by_key(|a| a.id);
+    pts
+}
+
+/// node `id` without tags
+fn node(id: u64, lon: f64, lat: f64) -> Node {
+    Node {
+        id,
+        tags: HashMap::new(),
+        lon,
+        lat,
+    }
+}
+
+/// way `id` along the equator, node n is at n / 1000 degrees of longitude
+fn way(id: u64, nodes: &[u64], tags: &[(&str, &str)]) -> Way {
+    Way {
+        id,
+        tags: tags
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+        info: HashMap::new(),
+        nodes: nodes
+            .iter()
+            .map(|n| node(*n, *n as f64 * 0.001, 0_f64))
+            .collect(),
+    }
+}
+
+/// route `id` without geometry stopping at `stops`
+fn stop_route(id: u64, template: &PublicTransport, stops: Vec<Node>) -> PublicTransport {
+    let mut pt = template.clone();
+    pt.id = id;
+    pt.tags.insert("ref".to_string(), format!("R{}", id));
+    pt.geometry = Vec::new();
+    pt.members = stops
+        .iter()
+        .map(|stop| Member {
+            id: stop.id,
+            member_type: MemberType::Node,
+            role: "stop".to_string(),
+        })
+        .collect();
+    pt.stops = stops;
+    pt
+}
+
+#[test]
+fn stop_network() {
+    let pts = public_transports(15000_f64);
+    let sequence: Vec<Node> = pts[0].stop_sequence().into_iter().cloned().collect();
+    let stop_area = StopArea {
+        id: 10,
+        tags: HashMap::new(),
+        members: vec![
+            Member {
+                id: sequence[5].id,
+                member_type: MemberType::Node,
+                role: "stop".to_string(),
+            },
+            Member {
+                id: 100,
+                member_type: MemberType::Node,
+                role: "stop".to_string(),
+            },
+        ],
+    };
+    let mut network = StopNetwork::new(vec![stop_area]);
+    for pt in &pts {
+        network.add_public_transport(pt);
+    }
+    network.add_public_transport(&stop_route(
+        1,
+        &pts[0],
+        vec![sequence[2].clone(), node(99, 0.0, 0.0)],
+    ));
+    network.add_public_transport(&stop_route(
+        2,
+        &pts[0],
+        vec![node(100, 0.0, 0.0), node(101, 0.0, 0.0)],
+    ));
+
+    let edges = network.edges_from(sequence[0].id);
+    assert_eq!(edges.len(), 1);
+    assert_eq!(edges[0].to, sequence[1].id);
+    assert!(edges[0].length > 0_f64);
+    assert!(edges[0].geometry.len() >= 2);
+
+    let journey = network
+        .shortest_hops(sequence[0].id, sequence[3].id)
+        .unwrap();
+    assert_eq!(journey.edges.len(), 3);
+    assert_eq!(journey.transfers, 0);
+    let journey = network.fewest_transfers(sequence[0].id, 99).unwrap();
+    assert_eq!(journey.edges.len(), 3);
+    assert_eq!(journey.transfers, 1);
+    assert_eq!(journey.edges[2].route_ref, Some("R1".to_string()));
+    // walking from sequence[5] to node 100 in the same stop area
+    let journey = network.fewest_transfers(sequence[0].id, 101).unwrap();
+    assert_eq!(journey.edges.len(), 6);
+    assert_eq!(journey.transfers, 1);
+    assert!(network.shortest_hops(101, sequence[0].id).is_none());
+
+    let json = network.to_json();
+    assert_eq!(
+        json["adjacency"][sequence[0].id.to_string()][0]["to"],
+        sequence[1].id
+    );
+    let mut graphml = Vec::new();
+    network.write_graphml(&mut graphml).unwrap();
+    let graphml = String::from_utf8(graphml).unwrap();
+    assert_eq!(graphml.matches("<edge ").count(), network.edges().len());
+    assert!(graphml.contains("<data key=\"stop_area\">10</data>"));
+}
+
+fn admin_area(id: u64, name: &str, admin_level: &str, bbox: (f64, f64, f64, f64)) -> Area {
+    let (minx, miny, maxx, maxy) = bbox;
+    let mut tags = HashMap::new();
+    tags.insert("name".to_string(), name.to_string());
+    if !admin_level.is_empty() {
+        tags.insert("admin_level".to_string(), admin_level.to_string());
+    }
+    Area {
+        id,
+        id_type: 'r',
+        tags,
+        info: HashMap::new(),
+        geometry: vec![vec![
+            (minx, miny),
+            (maxx, miny),
+            (maxx, maxy),
+            (minx, maxy),
+            (minx, miny),
+        ]],
+        parse_status: ParseStatus::new(ParseStatusKind::Ok, Vec::new()),
+        parent_id: None,
+    }
+}
+
+#[test]
+fn admin_areas() {
+    let pts = public_transports(15000_f64);
+    let admin_areas = AdminAreas::new(vec![
+        admin_area(1, "Ecuador", "2", (-82.0, -5.0, -75.0, 2.0)),
+        admin_area(2, "West", "4", (-79.0, -1.0, -78.52, 1.0)),
+        admin_area(3, "East", "4", (-78.52, -1.0, -78.0, 1.0)),
+        admin_area(4, "No level", "", (-79.0, -1.0, -78.0, 1.0)),
+    ]);
+    assert_eq!(admin_areas.areas().len(), 3);
+    let names: Vec<String> = admin_areas
+        .at((-78.6, 0.0))
+        .into_iter()
+        .map(|a| a.name)
+        .collect();
+    assert_eq!(names, vec!["Ecuador", "West"]);
+
+    let mut pt = pts[0].clone();
+    admin_areas.assign(&mut pt);
+    let admin = pt.admin.as_ref().unwrap();
+    let names: Vec<&str> = admin.route.iter().map(|a| a.name.as_str()).collect();
+    assert_eq!(names, vec!["Ecuador", "East", "West"]);
+    assert_eq!(admin.stops.len(), pt.stops.len());
+    for (stop, areas) in pt.stops.iter().zip(&admin.stops) {
+        assert_eq!(areas.len(), 2);
+        assert_eq!(areas[0].admin_level, 2);
+        let expected = if stop.lon < -78.52 { "West" } else { "East" };
+        assert_eq!(areas[1].name, expected);
+    }
+    assert!(pt.to_geojson().contains("\"admin_areas\":{\"2\":[{"));
+    assert!(!pts[0].to_geojson().contains("admin_areas"));
+
+    let options = WriterOptions {
+        admin_areas: true,
+        ..WriterOptions::default()
+    };
+    assert!(writer::create_with(Format::GeoJson, None, options.clone()).is_ok());
+    assert!(writer::create_with(Format::Kml, None, options).is_err());
+}
+
+#[test]
+fn admin_hierarchy() {
+    let hierarchy = AdminHierarchy::new(vec![
+        admin_area(1, "Ecuador", "2", (-82.0, -5.0, -75.0, 2.0)),
+        admin_area(2, "West", "4", (-82.0, -5.0, -78.5, 2.0)),
+        admin_area(3, "East", "4", (-78.5, -5.0, -76.0, 2.0)),
+        admin_area(4, "Inside East", "4", (-77.0, -5.0, -76.5, 2.0)),
+        admin_area(5, "City", "8", (-80.0, -1.0, -79.0, 0.0)),
+        // inside East and Inside East, the smallest one is the parent
+        admin_area(6, "Town", "8", (-76.9, -1.0, -76.6, 0.0)),
+        admin_area(7, "Line", "8", (-79.5, -1.0, -79.5, 0.0)),
+    ]);
+    let ids = |indexes: &[usize]| -> Vec<u64> {
+        indexes.iter().map(|i| hierarchy.areas()[*i].id).collect()
+    };
+    assert_eq!(ids(&hierarchy.roots()), vec![1, 7]);
+    assert_eq!(ids(hierarchy.children(0)), vec![2, 3, 4]);
+    assert_eq!(ids(hierarchy.children(1)), vec![5]);
+    assert_eq!(hierarchy.parent(4), Some(1));
+    assert_eq!(hierarchy.parent(5), Some(3));
+    assert_eq!(hierarchy.parent(6), None);
+
+    let issues = hierarchy.issues();
+    assert_eq!(issues.len(), 5);
+    match &issues[0] {
+        HierarchyIssue::Gap {
+            area_id,
+            uncovered,
+            location,
+        } => {
+            assert_eq!(*area_id, 1);
+            assert!(*uncovered > 0.1 && *uncovered < 0.2);
+            assert!(location.0 > -76.0);
+        }
+        issue => panic!("unexpected {:?}", issue),
+    }
+    assert!(issues.iter().any(|issue| match issue {
+        HierarchyIssue::Overlap { area_ids, location } => {
+            *area_ids == (3, 4) && location.0 > -77.0 && location.0 < -76.5
+        }
+        _ => false,
+    }));
+    assert!(issues
+        .iter()
+        .any(|issue| matches!(issue, HierarchyIssue::Gap { area_id: 2, .. })));
+    assert_eq!(issues[4], HierarchyIssue::ZeroArea { area_id: 7 });
+
+    let tree = hierarchy.to_json();
+    assert_eq!(tree[0]["name"], "Ecuador");
+    assert_eq!(tree[0]["children"][0]["children"][0]["name"], "City");
+    let areas = hierarchy.into_areas();
+    assert_eq!(areas[4].parent_id, Some(2));
+    assert!(areas[4].to_geojson().contains("\"parent_id\":2"));
+    assert_eq!(areas[0].parent_id, None);
+}
+
+#[test]
+fn write_gpkg() {
+    let path = std::env::temp_dir().join("osmptparser_write_gpkg.gpkg");
+    let parser = Parser::new_ptv2("tests/test.pbf", 1);
+    let mut writer = GpkgWriter::create(&path).unwrap();
+    for pt in parser.get_public_transports(1500_f64) {
+        writer.write_public_transport(&pt).unwrap();
+    }
+    writer.finish().unwrap();
+
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    let count = |table: &str| -> i64 {
+        conn.query_row(&format!("SELECT count(*) FROM {}", table), [], |r| r.get(0))
+            .unwrap()
+    };
+    assert_eq!(count("routes"), 2);
+    assert_eq!(count("stops"), 32);
+    assert_eq!(count("areas"), 0);
+    let name: String = conn
+        .query_row("SELECT name FROM routes WHERE osm_id = 2030162", [], |r| {
+            r.get(0)
+        })
+        .unwrap();
+    assert_eq!(name, "B6 Mapasingue Oeste Ida");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn reproject() {
+    assert_eq!(
+        "utm:17s".parse::<Crs>(),
+        Ok(Crs::Utm {
+            zone: 17,
+            north: false
+        })
+    );
+    assert_eq!("EPSG:32717".parse::<Crs>().unwrap().epsg(), 32717);
+    assert!("utm:61n".parse::<Crs>().is_err());
+    assert_eq!(Crs::utm_for((-78.5, -0.2)).epsg(), 32717);
+
+    // reference values to the cm, web mercator by its closed formula and utm by the krüger series of order 6
+    let near = |(x, y): (f64, f64), expected: (f64, f64)| {
+        assert!((x - expected.0).abs() < 0.01, "{} != {}", x, expected.0);
+        assert!((y - expected.1).abs() < 0.01, "{} != {}", y, expected.1);
+    };
+    near(
+        Crs::WebMercator.project((-78.5, -0.2)),
+        (-8_738_580.027, -22_263.943),
+    );
+    let utm_31n = "32631".parse::<Crs>().unwrap();
+    near(utm_31n.project((0_f64, 0_f64)), (166_021.443, 0_f64));
+    near(utm_31n.project((3_f64, 0_f64)), (500_000_f64, 0_f64));
+    near(
+        utm_31n.project((2.2945, 48.8582)),
+        (448_251.795, 5_411_932.678),
+    );
+    near(
+        Crs::utm_for((-78.5, -0.2)).project((-78.5, -0.2)),
+        (778_274.631, 9_977_872.788),
+    );
+
+    let path = std::env::temp_dir().join("osmptparser_reproject.geojson");
+    let options = WriterOptions {
+        crs: Crs::WebMercator,
+        ..WriterOptions::default()
+    };
+    let mut writer = writer::create_with(Format::GeoJson, Some(&path), options).unwrap();
+    writer.write_area(&square_with_hole()).unwrap();
+    writer.finish().unwrap();
+    let geojson: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        geojson[0]["crs"],
+        serde_json::json!({"type": "name", "properties": {"name": "urn:ogc:def:crs:EPSG::3857"}})
+    );
+    let coordinates = &geojson[0]["geometry"]["coordinates"][0];
+    let point = |i: usize| {
+        (
+            coordinates[i][0].as_f64().unwrap(),
+            coordinates[i][1].as_f64().unwrap(),
+        )
+    };
+    near(point(0), (0_f64, 0_f64));
+    near(point(1), (111.319, 0_f64));
+    near(point(10), (1_113.195, 0_f64));
+    near(point(11), (1_113.195, 111.319));
+
+    let path = std::env::temp_dir().join("osmptparser_reproject.gpkg");
+    let options = WriterOptions {
+        crs: Crs::WebMercator,
+        ..WriterOptions::default()
+    };
+    let mut writer = writer::create_with(Format::Gpkg, Some(&path), options.clone()).unwrap();
+    writer.write_area(&square_with_hole()).unwrap();
+    writer.finish().unwrap();
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    let srs_id: i64 = conn
+        .query_row(
+            "SELECT srs_id FROM gpkg_geometry_columns WHERE table_name = 'areas'",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert_eq!(srs_id, 3857);
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(writer::create_with(Format::Gpx, None, options).is_err());
+}
+
+#[test]
+fn write_fgb() {
+    let path = std::env::temp_dir().join("osmptparser_write_fgb.fgb");
+    let parser = Parser::new_ptv2("tests/test.pbf", 1);
+    let mut writer = FgbWriter::create(&path).unwrap();
+    let mut first_points = Vec::new();
+    parser.for_each_public_transport(15000_f64, |pt| {
+        first_points.extend(pt.geometry.first().map(|part| part[0]));
+        writer.write_public_transport(&pt).unwrap();
+    });
+    writer.finish().unwrap();
+
+    let bytes = std::fs::read(&path).unwrap();
+    assert_eq!(&bytes[..8], b"fgb\x03fgb\x00");
+    assert!(!path.with_extension("fgb.tmp").exists());
+    let header_size = u32_at(&bytes, 8) as usize;
+    let header = table(&bytes[12..12 + header_size], 0);
+    let features_count = header
+        .field(20)
+        .map(|pos| u64::from_le_bytes(header.buf[pos..pos + 8].try_into().unwrap()))
+        .unwrap();
+    assert_eq!(features_count, first_points.len() as u64);
+    let node_size = header
+        .field(22)
+        .map(|pos| u16::from_le_bytes(header.buf[pos..pos + 2].try_into().unwrap()))
+        .unwrap() as u64;
+    assert_eq!(node_size, 16);
+    // packed R-tree: every level of nodes of 40 bytes up to the root
+    let (mut nodes, mut level) = (0, features_count);
+    loop {
+        nodes += level;
+        if level == 1 {
+            break;
+        }
+        level = level.div_ceil(node_size);
+    }
+    let features_start = 12 + header_size + nodes as usize * 40;
+    let feature_size = u32_at(&bytes, features_start) as usize;
+    let feature_buf = &bytes[features_start + 4..features_start + 4 + feature_size];
+    let feature = table(feature_buf, 0);
+    let geometry = table(feature_buf, feature.offset_field(4).unwrap());
+    assert_eq!(geometry.field(16).map(|pos| feature_buf[pos]), Some(5));
+    let xy = geometry.offset_field(6).unwrap();
+    assert!(u32_at(feature_buf, xy) >= 4);
+    let point = (
+        f64::from_le_bytes(feature_buf[xy + 4..xy + 12].try_into().unwrap()),
+        f64::from_le_bytes(feature_buf[xy + 12..xy + 20].try_into().unwrap()),
+    );
+    assert!(first_points.contains(&point));
+    std::fs::remove_file(&path).unwrap();
+
+    // tag keys named like the fixed columns get their own columns
+    let mut pts = parser.get_public_transports(1500_f64);
+    pts.sort_by_key(|a| a.id);
+    let mut pt = pts.remove(1);
+    pt.tags
+        .insert("osm_id".to_string(), "not a number".to_string());
+    pt.tags
+        .insert("tag:osm_id".to_string(), "prefixed".to_string());
+    let mut writer = FgbWriter::create(&path).unwrap();
+    writer.write_public_transport(&pt).unwrap();
+    writer.finish().unwrap();
+    let bytes = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let header_size = u32_at(&bytes, 8) as usize;
+    let header_buf = &bytes[12..12 + header_size];
+    let header = table(header_buf, 0);
+    let columns_pos = header.offset_field(18).unwrap();
+    let string_at = |buf: &[u8], pos: usize| -> String {
+        let len = u32_at(buf, pos) as usize;
+        String::from_utf8(buf[pos + 4..pos + 4 + len].to_vec()).unwrap()
+    };
+    let columns: Vec<String> = (0..u32_at(header_buf, columns_pos) as usize)
+        .map(|i| {
+            let pos = columns_pos + 4 + i * 4;
+            let column = table(header_buf, pos + u32_at(header_buf, pos) as usize);
+            string_at(header_buf, column.offset_field(4).unwrap())
+        })
+        .collect();
+    let position = |name: &str| columns.iter().position(|c| c == name).unwrap();
+    assert_eq!(position("osm_id"), 0);
+    assert_eq!(columns.iter().filter(|c| *c == "osm_id").count(), 1);
+    // a single feature, the index is the leaf and the root
+    let features_start = 12 + header_size + 2 * 40;
+    let feature_buf = &bytes[features_start + 4..];
+    let properties_pos = table(feature_buf, 0).offset_field(6).unwrap();
+    let properties = &feature_buf
+        [properties_pos + 4..properties_pos + 4 + u32_at(feature_buf, properties_pos) as usize];
+    let mut values: HashMap<usize, String> = HashMap::new();
+    let mut pos = 0;
+    while pos < properties.len() {
+        let column = u16::from_le_bytes(properties[pos..pos + 2].try_into().unwrap()) as usize;
+        pos += 2;
+        let value = if column == 0 || column == 2 {
+            pos += 8;
+            u64::from_le_bytes(properties[pos - 8..pos].try_into().unwrap()).to_string()
+        } else {
+            let value = string_at(properties, pos);
+            pos += 4 + value.len();
+            value
+        };
+        assert!(values.insert(column, value).is_none());
+    }
+    assert_eq!(values[&0], pt.id.to_string());
+    assert_eq!(values[&position("tag:osm_id")], "not a number");
+    assert_eq!(values[&position("tag:tag:osm_id")], "prefixed");
+}
+
+fn u32_at(buf: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(buf[pos..pos + 4].try_into().unwrap())
+}
+
+/// flatbuffers table of `buf` (without size prefix), `pos` 0 for the root table
+struct Table<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+fn table(buf: &[u8], pos: usize) -> Table<'_> {
+    let pos = if pos == 0 {
+        u32_at(buf, 0) as usize
+    } else {
+        pos
+    };
+    Table { buf, pos }
+}
+
+impl Table<'_> {
+    /// position of the field at vtable `slot`, None if it is not set
+    fn field(&self, slot: usize) -> Option<usize> {
+        let vtable = (self.pos as i64
+            - i32::from_le_bytes(self.buf[self.pos..self.pos + 4].try_into().unwrap()) as i64)
+            as usize;
+        let vtable_size =
+            u16::from_le_bytes(self.buf[vtable..vtable + 2].try_into().unwrap()) as usize;
+        if slot >= vtable_size {
+            return None;
+        }
+        match u16::from_le_bytes(
+            self.buf[vtable + slot..vtable + slot + 2]
+                .try_into()
+                .unwrap(),
+        ) {
+            0 => None,
+            offset => Some(self.pos + offset as usize),
+        }
+    }
+
+    /// position of the table / vector referenced by the field at `slot`
+    fn offset_field(&self, slot: usize) -> Option<usize> {
+        self.field(slot)
+            .map(|pos| pos + u32_at(self.buf, pos) as usize)
+    }
+}
+
+#[test]
+fn write_gpx() {
+    let parser = Parser::new_ptv2("tests/test.pbf", 1);
+    let mut out = Vec::new();
+    let mut writer = GpxWriter::new(&mut out);
+    parser.for_each_public_transport(1500_f64, |pt| {
+        writer.write_public_transport(&pt).unwrap();
+    });
+    writer.finish().unwrap();
+
+    let gpx = String::from_utf8(out).unwrap();
+    assert_eq!(gpx.matches("<trk>").count(), 2);
+    assert_eq!(gpx.matches("<wpt ").count(), 32);
+    assert!(gpx.contains("<name>B6 Mapasingue Oeste Ida</name>"));
+    assert!(gpx.find("<wpt ").unwrap() < gpx.find("<trk>").unwrap());
+}
+
+#[test]
+fn write_kml() {
+    let pts = public_transports(15000_f64);
+    let mut out = Vec::new();
+    let mut writer = KmlWriter::new(&mut out);
+    for pt in &pts {
+        writer.write_public_transport(pt).unwrap();
+    }
+    writer.write_area(&square_with_hole()).unwrap();
+    writer.finish().unwrap();
+
+    let kml = String::from_utf8(out).unwrap();
+    assert!(kml.ends_with("</Document>\n</kml>\n"));
+    assert_eq!(kml.matches("<Folder>").count(), 2);
+    // a line placemark per route, a point placemark per stop and the area
+    assert_eq!(kml.matches("<Placemark>").count(), 2 + 32 + 1);
+    assert_eq!(kml.matches("<Point>").count(), 32);
+    let parts: usize = pts
+        .iter()
+        .map(|pt| pt.geometry.iter().filter(|part| !part.is_empty()).count())
+        .sum();
+    assert_eq!(kml.matches("<LineString>").count(), parts);
+    let (lon, lat) = pts[0].geometry[0][0];
+    assert!(kml.contains(&format!("<LineString><coordinates>{},{} ", lon, lat)));
+    // T1 colour #8fd952 as aabbggrr
+    assert!(kml.contains("<LineStyle><color>ff52d98f</color><width>4</width></LineStyle>"));
+    assert!(kml.contains("<description>https://www.openstreetmap.org/relation/85965</description>"));
+    assert!(kml.contains("<Data name=\"ref\"><value>T1</value></Data>"));
+    assert_eq!(kml.matches("<outerBoundaryIs>").count(), 1);
+    assert_eq!(kml.matches("<innerBoundaryIs>").count(), 1);
+}
+
+#[test]
+fn split() {
+    let path = std::env::temp_dir().join("osmptparser_split");
+    let _ = std::fs::remove_dir_all(&path);
+    let mut writer = SplitWriter::create(Format::Kml, &path).unwrap();
+    writer.write_area(&square_with_hole()).unwrap();
+    writer.finish().unwrap();
+    assert!(path.join("w1.kml").exists());
+    std::fs::remove_dir_all(&path).unwrap();
+
+    // one sql with CREATE TABLE / one gtfs feed per route can not be loaded together
+    for format in [Format::PgCopy, Format::Gtfs, Format::Gpkg, Format::Fgb] {
+        assert!(SplitWriter::create(format, &path).is_err());
+    }
+    assert!(!path.exists());
+}
+
+#[test]
+fn write_pgcopy() {
+    let pts = public_transports(15000_f64);
+    let mut out = Vec::new();
+    let mut writer = PgCopyWriter::new(&mut out);
+    for pt in &pts {
+        writer.write_public_transport(pt).unwrap();
+    }
+    writer.finish().unwrap();
+
+    let sql = String::from_utf8(out).unwrap();
+    assert!(sql.starts_with("BEGIN;\nCREATE EXTENSION IF NOT EXISTS postgis;\n"));
+    assert!(sql.ends_with("\\.\nCOMMIT;\n"));
+    assert!(sql.contains("geom geometry(MultiLineString, 4326)"));
+    // a routes block and a stops block per route, as the rows arrive
+    assert_eq!(sql.matches("COPY routes (").count(), 2);
+    assert_eq!(sql.matches("COPY stops (").count(), 2);
+    assert_eq!(sql.matches("\n\\.\n").count(), 4);
+    let lines: Vec<&str> = sql.lines().collect();
+    let copy_routes = lines
+        .iter()
+        .position(|line| line.starts_with("COPY routes ("))
+        .unwrap();
+    let route: Vec<&str> = lines[copy_routes + 1].split('\t').collect();
+    assert_eq!(route.len(), 11);
+    assert_eq!(route[0], "85965");
+    assert_eq!(route[2], "T1");
+    // hex EWKB, little endian multilinestring with SRID
+    assert!(route[10].starts_with("0105000020E6100000"));
+    assert_eq!(lines[copy_routes + 2], "\\.");
+    assert!(lines[copy_routes + 3].starts_with("COPY stops ("));
+    let stops = lines
+        .iter()
+        .filter(|line| line.split('\t').count() == 9)
+        .count();
+    assert_eq!(stops, 32);
+    assert_eq!(sql.matches("COPY areas (").count(), 0);
+}
+
+fn varint(buf: &[u8], pos: &mut usize) -> u64 {
+    let mut value = 0_u64;
+    let mut shift = 0;
+    loop {
+        let byte = buf[*pos];
+        *pos += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        shift += 7;
+        if byte < 0x80 {
+            return value;
+        }
+    }
+}
+
+/// (field number, varint value or length, bytes of length delimited fields) of a protobuf message
+fn protobuf_fields(buf: &[u8]) -> Vec<(u64, u64, &[u8])> {
+    let mut fields = Vec::new();
+    let mut pos = 0;
+    while pos < buf.len() {
+        let key = varint(buf, &mut pos);
+        let value = varint(buf, &mut pos);
+        if key & 0x7 == 2 {
+            fields.push((key >> 3, value, &buf[pos..pos + value as usize]));
+            pos += value as usize;
+        } else {
+            fields.push((key >> 3, value, &buf[pos..pos]));
+        }
+    }
+    fields
+}
+
+/// lines of the features of the `routes` layer of an mvt tile, in tile coordinates
+fn tile_route_lines(tile: &[u8]) -> Vec<Vec<(i64, i64)>> {
+    let mut lines: Vec<Vec<(i64, i64)>> = Vec::new();
+    for (_, _, layer) in protobuf_fields(tile).into_iter().filter(|f| f.0 == 3) {
+        let fields = protobuf_fields(layer);
+        if !fields.iter().any(|f| f.0 == 1 && f.2 == b"routes") {
+            continue;
+        }
+        for (_, _, feature) in fields.into_iter().filter(|f| f.0 == 2) {
+            let geometry = protobuf_fields(feature)
+                .into_iter()
+                .find(|f| f.0 == 4)
+                .unwrap()
+                .2;
+            let mut commands = Vec::new();
+            let mut pos = 0;
+            while pos < geometry.len() {
+                commands.push(varint(geometry, &mut pos));
+            }
+            let unzigzag = |v: u64| (v >> 1) as i64 ^ -((v & 1) as i64);
+            let mut cursor = (0_i64, 0_i64);
+            let mut i = 0;
+            while i < commands.len() {
+                let (command, count) = (commands[i] & 0x7, (commands[i] >> 3) as usize);
+                if command == 1 {
+                    lines.push(Vec::new());
+                }
+                for j in 0..count {
+                    cursor.0 += unzigzag(commands[i + 1 + j * 2]);
+                    cursor.1 += unzigzag(commands[i + 2 + j * 2]);
+                    lines.last_mut().unwrap().push(cursor);
+                }
+                i += 1 + count * 2;
+            }
+        }
+    }
+    lines
+}
+
+#[test]
+fn tiles() {
+    let options = |min_zoom, max_zoom| TileOptions {
+        min_zoom,
+        max_zoom,
+        ..TileOptions::default()
+    };
+    assert!(TileSet::new(options(0, MAX_ZOOM)).is_ok());
+    assert!(TileSet::new(options(0, 32)).is_err());
+    assert!(TileSet::new(options(5, 4)).is_err());
+
+    let parser = Parser::new_ptv2("tests/test.pbf", 1);
+    let mut pt = parser.get_public_transports(1500_f64).remove(0);
+    pt.stops = Vec::new();
+    // at lat 10 all the line is in the tile row above the equator
+    pt.geometry = vec![vec![(-10.0, 10.0), (10.0, 10.0)]];
+    let mut tileset = TileSet::new(options(0, 2)).unwrap();
+    tileset.add_public_transport(&pt);
+    let path = std::env::temp_dir().join("osmptparser_test_tiles");
+    if path.exists() {
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+    tileset.write_directory(&path).unwrap();
+
+    let mut tiles: Vec<String> = Vec::new();
+    for z in 0..=2 {
+        for x in std::fs::read_dir(path.join(z.to_string())).unwrap() {
+            let x = x.unwrap();
+            for y in std::fs::read_dir(x.path()).unwrap() {
+                tiles.push(format!(
+                    "{}/{}/{}",
+                    z,
+                    x.file_name().to_str().unwrap(),
+                    y.unwrap().file_name().to_str().unwrap()
+                ));
+            }
+        }
+    }
+    tiles.sort();
+    assert_eq!(
+        tiles,
+        vec![
+            "0/0/0.pbf",
+            "1/0/0.pbf",
+            "1/1/0.pbf",
+            "2/1/1.pbf",
+            "2/2/1.pbf"
+        ]
+    );
+    assert!(path.join("metadata.json").exists());
+
+    // the line crosses the x = 2 tile border at z2, each side is clipped to the tile plus the 64 buffer
+    let left = tile_route_lines(&std::fs::read(path.join("2/1/1.pbf")).unwrap());
+    let right = tile_route_lines(&std::fs::read(path.join("2/2/1.pbf")).unwrap());
+    assert_eq!(left.len(), 1);
+    assert_eq!(right.len(), 1);
+    assert_eq!(left[0].len(), 2);
+    assert_eq!(left[0][1].0, 4096 + 64);
+    assert_eq!(right[0][0].0, -64);
+    assert_eq!(left[0][0].1, right[0][0].1);
+    std::fs::remove_dir_all(&path).unwrap();
+}